@@ -0,0 +1,203 @@
+//! Aggregations over items, see [Aggregate].
+
+use crate::error::DatabaseError;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// Aggregations over the values a key extractor returns for each item, which
+/// can be used on anything iterating over references to items. This includes
+/// [crate::Database::items], the results of queries such as
+/// [crate::Database::query] and a [crate::query::Query].
+///
+/// # Examples
+///
+/// ```rust
+/// use serde::{Serialize, Deserialize};
+/// use tinydb::{aggregate::Aggregate, Database};
+///
+/// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+/// struct ExampleStruct {
+///     team: String,
+///     score: i32,
+/// }
+///
+/// fn main() {
+///     let mut my_db = Database::new("aggregate_test", None, false);
+///
+///     my_db.add_item(ExampleStruct { team: "red".into(), score: 10 });
+///     my_db.add_item(ExampleStruct { team: "red".into(), score: 30 });
+///     my_db.add_item(ExampleStruct { team: "blue".into(), score: 5 });
+///
+///     assert_eq!(my_db.items.sum_of(|s| s.score), 45);
+///     assert_eq!(my_db.items.max_of(|s| s.score).unwrap(), 30);
+///     assert_eq!(my_db.items.count_of(|s| s.score >= 10), 2);
+///
+///     let averages = my_db
+///         .items
+///         .group_by(|s| s.team.clone(), |team| team.avg_of(|s| s.score).unwrap());
+///     assert_eq!(averages["red"], 20.0);
+///
+///     let reds = my_db.query(|s| &s.team, String::from("red")).unwrap();
+///     assert_eq!(reds.min_of(|s| s.score).unwrap(), 10);
+/// }
+/// ```
+pub trait Aggregate<'a, T: 'a>: IntoIterator<Item = &'a T> + Sized {
+    /// Counts the items `predicate` returns `true` for, e.g. `|_| true` to
+    /// count every item.
+    fn count_of<P: Fn(&T) -> bool>(self, predicate: P) -> usize {
+        self.into_iter().filter(|item| predicate(item)).count()
+    }
+
+    /// Adds up the values `f` returns for every item.
+    fn sum_of<S: std::iter::Sum<S>, F: Fn(&T) -> S>(self, f: F) -> S {
+        self.into_iter().map(f).sum()
+    }
+
+    /// Finds the smallest value `f` returns for any item.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::ItemNotFound] if there are no items.
+    fn min_of<K: Ord, F: Fn(&T) -> K>(self, f: F) -> Result<K, DatabaseError> {
+        self.into_iter()
+            .map(f)
+            .min()
+            .ok_or(DatabaseError::ItemNotFound)
+    }
+
+    /// Finds the largest value `f` returns for any item. Errors in the same
+    /// way as [Aggregate::min_of].
+    fn max_of<K: Ord, F: Fn(&T) -> K>(self, f: F) -> Result<K, DatabaseError> {
+        self.into_iter()
+            .map(f)
+            .max()
+            .ok_or(DatabaseError::ItemNotFound)
+    }
+
+    /// Averages the values `f` returns for every item, which can be any
+    /// primitive number. Errors in the same way as [Aggregate::min_of].
+    fn avg_of<N: ToF64, F: Fn(&T) -> N>(self, f: F) -> Result<f64, DatabaseError> {
+        let (count, total) = self
+            .into_iter()
+            .fold((0usize, 0.0), |(count, total), item| {
+                (count + 1, total + f(item).to_f64())
+            });
+
+        if count == 0 {
+            return Err(DatabaseError::ItemNotFound);
+        }
+
+        Ok(total / count as f64)
+    }
+
+    /// Collects every distinct value `f` returns.
+    fn distinct_of<K: Hash + Eq, F: Fn(&T) -> K>(self, f: F) -> HashSet<K> {
+        self.into_iter().map(f).collect()
+    }
+
+    /// Counts how many items share each value `f` returns.
+    fn count_by<K: Hash + Eq, F: Fn(&T) -> K>(self, f: F) -> HashMap<K, usize> {
+        let mut counts = HashMap::new();
+
+        for item in self {
+            *counts.entry(f(item)).or_insert(0) += 1;
+        }
+
+        counts
+    }
+
+    /// Groups items by the key `f` returns, then aggregates each group with
+    /// `aggregate` to give a map of keys to aggregated values. Groups are
+    /// given as a [Vec] so they can be aggregated further.
+    fn group_by<K, V, F, A>(self, f: F, mut aggregate: A) -> HashMap<K, V>
+    where
+        K: Hash + Eq,
+        F: Fn(&T) -> K,
+        A: FnMut(Vec<&'a T>) -> V,
+    {
+        let mut groups: HashMap<K, Vec<&'a T>> = HashMap::new();
+
+        for item in self {
+            groups.entry(f(item)).or_default().push(item);
+        }
+
+        groups
+            .into_iter()
+            .map(|(key, group)| (key, aggregate(group)))
+            .collect()
+    }
+}
+
+impl<'a, T: 'a, I: IntoIterator<Item = &'a T>> Aggregate<'a, T> for I {}
+
+/// Numbers which can be averaged with [Aggregate::avg_of].
+///
+/// This is implemented for every primitive number, including those which
+/// don't implement `Into<f64>` as they can't all be represented exactly.
+/// Integers larger than 2^53 are rounded to the nearest [f64].
+pub trait ToF64 {
+    /// Converts the number to the nearest [f64].
+    fn to_f64(self) -> f64;
+}
+
+/// Implements [ToF64] for primitive numbers with an `as` cast.
+macro_rules! impl_to_f64 {
+    ($($number:ty),*) => {
+        $(
+            impl ToF64 for $number {
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+            }
+        )*
+    };
+}
+
+impl_to_f64!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests every aggregation over some items and an empty collection.
+    #[test]
+    fn aggregations() -> Result<(), DatabaseError> {
+        let crew = [("Lister", 25), ("Rimmer", 30), ("Kryten", 30), ("Cat", 25)];
+
+        assert_eq!(crew.iter().sum_of(|(_, age)| *age), 110);
+        assert_eq!(crew.iter().min_of(|(name, _)| name.len())?, 3);
+        assert_eq!(crew.iter().max_of(|(_, age)| *age)?, 30);
+        assert_eq!(crew.iter().avg_of(|(_, age)| *age)?, 27.5);
+        assert_eq!(crew.iter().avg_of(|(name, _)| name.len())?, 5.25);
+        assert_eq!(crew.iter().avg_of(|(_, age)| *age as u64 * 2)?, 55.0);
+        assert_eq!(crew.iter().avg_of(|_| i128::MAX)?, i128::MAX as f64);
+        assert_eq!(crew.iter().count_of(|(_, age)| *age > 25), 2);
+        assert_eq!(crew.iter().count_of(|_| true), 4);
+        assert_eq!(
+            crew.iter().distinct_of(|(_, age)| *age),
+            [25, 30].iter().copied().collect()
+        );
+        assert_eq!(crew.iter().count_by(|(_, age)| *age)[&30], 2);
+
+        let oldest = crew.iter().group_by(
+            |(name, _)| name.len() > 3,
+            |group| group.max_of(|(_, age)| *age),
+        );
+        assert_eq!(oldest[&true].as_ref().ok(), Some(&30));
+        assert_eq!(oldest[&false].as_ref().ok(), Some(&25));
+
+        let empty: Vec<&(&str, i32)> = vec![];
+        assert_eq!(empty.clone().sum_of(|(_, age)| *age), 0);
+        assert_eq!(empty.clone().count_of(|_| true), 0);
+        assert!(empty
+            .clone()
+            .group_by(|(_, age)| *age, |g| g.len())
+            .is_empty());
+        assert!(matches!(
+            empty.avg_of(|(_, age)| *age),
+            Err(DatabaseError::ItemNotFound)
+        ));
+
+        Ok(())
+    }
+}
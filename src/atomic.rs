@@ -0,0 +1,218 @@
+//! Crash-safe replacement of files on disk, used whenever a database is dumped.
+//!
+//! Rather than truncating the destination and writing into it, data is written
+//! to a sibling temporary file which is then flushed, synced and atomically
+//! renamed over the destination. A reader will therefore only ever see the old
+//! or the new complete file, even if the process dies half-way through a dump.
+
+use crate::error::DatabaseError;
+use std::fs::{self, File, OpenOptions};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Number of temporary paths handed out by this process so far, used to make
+/// each of them unique.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Atomically replaces the file at `path` with whatever `write` outputs.
+///
+/// If `write` returns an error or panics, the temporary file is removed and the
+/// file previously at `path` (if any) is left untouched.
+pub(crate) fn write_atomic<F>(path: &Path, write: F) -> Result<(), DatabaseError>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<(), DatabaseError>,
+{
+    let guard = TempGuard {
+        path: temp_path(path),
+        committed: false,
+    };
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&guard.path)?;
+    let mut writer = BufWriter::new(file);
+
+    write(&mut writer)?;
+
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    drop(file);
+
+    guard.commit(path)
+}
+
+/// Generates a sibling temporary path to write to before replacing `path`,
+/// which is hidden and tagged with the current process id and a counter so that
+/// concurrent writes to the same path, from this process or another, never
+/// share a temporary file.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let count = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    path.with_file_name(format!(".{}.{}.{}.tmp", name, std::process::id(), count))
+}
+
+/// Syncs the directory containing `path` so that a rename inside of it is
+/// durable. This is a no-op on platforms which can't open directories.
+fn sync_parent(path: &Path) -> Result<(), DatabaseError> {
+    if cfg!(unix) {
+        let parent = match path.parent() {
+            Some(x) if !x.as_os_str().is_empty() => x,
+            _ => Path::new("."),
+        };
+
+        File::open(parent)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Removes the temporary file when dropped unless it has been committed, which
+/// also covers the case of the writing closure panicking.
+struct TempGuard {
+    path: PathBuf,
+    committed: bool,
+}
+
+impl TempGuard {
+    /// Renames the temporary file over `dest` and syncs its directory.
+    fn commit(mut self, dest: &Path) -> Result<(), DatabaseError> {
+        fs::rename(&self.path, dest)?;
+        self.committed = true;
+
+        sync_parent(dest)
+    }
+}
+
+impl Drop for TempGuard {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, Write};
+    use std::panic;
+    use std::thread;
+
+    /// Creates a fresh path inside of the system's temp directory.
+    fn test_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("tinydb-atomic-{}.tinydb", name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    /// Returns `true` if any temporary file for `path` was left behind.
+    fn has_temp_files(path: &Path) -> io::Result<bool> {
+        let prefix = format!(".{}.", path.file_name().unwrap().to_string_lossy());
+
+        for entry in fs::read_dir(path.parent().unwrap())? {
+            if entry?.file_name().to_string_lossy().starts_with(&prefix) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Tests that a successful write replaces the file and leaves no temp file.
+    #[test]
+    fn replaces_file() -> Result<(), DatabaseError> {
+        let path = test_path("replace");
+
+        write_atomic(&path, |w| Ok(w.write_all(b"old")?))?;
+        write_atomic(&path, |w| Ok(w.write_all(b"new")?))?;
+
+        assert_eq!(fs::read(&path)?, b"new");
+        assert!(!has_temp_files(&path)?);
+
+        Ok(())
+    }
+
+    /// Simulates a writer failing half-way through and checks the previous
+    /// file survives intact.
+    #[test]
+    fn failing_writer_keeps_old() -> Result<(), DatabaseError> {
+        let path = test_path("failing");
+        write_atomic(&path, |w| Ok(w.write_all(b"intact")?))?;
+
+        let result = write_atomic(&path, |w| {
+            w.write_all(b"partial")?;
+            Err(io::Error::other("disk on fire").into())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path)?, b"intact");
+        assert!(!has_temp_files(&path)?);
+
+        Ok(())
+    }
+
+    /// Simulates a panic whilst writing and checks the previous file survives
+    /// intact.
+    #[test]
+    fn panicking_writer_keeps_old() -> Result<(), DatabaseError> {
+        let path = test_path("panicking");
+        write_atomic(&path, |w| Ok(w.write_all(b"intact")?))?;
+
+        let result = panic::catch_unwind(|| {
+            write_atomic(&path, |w| {
+                w.write_all(b"partial").unwrap();
+                panic!("crashed whilst dumping");
+            })
+        });
+
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path)?, b"intact");
+        assert!(!has_temp_files(&path)?);
+
+        Ok(())
+    }
+
+    /// Tests that threads writing to the same path at once each use their own
+    /// temporary file, so the result is always one of their complete writes.
+    #[test]
+    fn concurrent_writers() -> Result<(), DatabaseError> {
+        let path = test_path("concurrent");
+        let contents: Vec<Vec<u8>> = (0..8).map(|i| vec![i; 64 * 1024]).collect();
+
+        assert_ne!(temp_path(&path), temp_path(&path));
+
+        thread::scope(|scope| {
+            let writers: Vec<_> = contents
+                .iter()
+                .map(|data| {
+                    let path = &path;
+                    scope.spawn(move || {
+                        write_atomic(path, |w| {
+                            for chunk in data.chunks(1024) {
+                                w.write_all(chunk)?;
+                                w.flush()?;
+                            }
+                            Ok(())
+                        })
+                    })
+                })
+                .collect();
+
+            writers
+                .into_iter()
+                .try_for_each(|writer| writer.join().unwrap())
+        })?;
+
+        assert!(contents.contains(&fs::read(&path)?));
+        assert!(!has_temp_files(&path)?);
+
+        Ok(())
+    }
+}
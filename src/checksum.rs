@@ -0,0 +1,228 @@
+//! CRC32 checksums appended to the end of dumps to detect silent corruption.
+//!
+//! When [crate::header::FLAG_CHECKSUM] is set, the last 4 bytes of a dump are
+//! the little-endian CRC32 of every byte before them, header included.
+
+use crate::error::DatabaseError;
+use crate::header::HEADER_LEN;
+use crc32fast::Hasher;
+use std::io::{self, Read, Write};
+
+/// Length of the checksum trailer in bytes.
+pub(crate) const CHECKSUM_LEN: usize = 4;
+
+/// Wraps a writer, hashing everything written through it.
+pub(crate) struct ChecksumWriter<W: Write> {
+    inner: W,
+    hasher: Hasher,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    /// Creates a new writer with an empty checksum.
+    pub(crate) fn new(inner: W) -> Self {
+        ChecksumWriter {
+            inner,
+            hasher: Hasher::new(),
+        }
+    }
+
+    /// Appends the checksum of everything written so far as a trailer.
+    pub(crate) fn finish(mut self) -> Result<(), DatabaseError> {
+        let checksum = self.hasher.finalize();
+        self.inner.write_all(&checksum.to_le_bytes())?;
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Wraps a reader over a dump, hashing everything read through it whilst
+/// holding back the last [CHECKSUM_LEN] bytes so the trailer is never passed
+/// on, meaning dumps can be checked without reading them into memory.
+pub(crate) struct ChecksumReader<R: Read> {
+    inner: R,
+    hasher: Hasher,
+    held: [u8; CHECKSUM_LEN],
+    held_len: usize,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    /// Creates a new reader, with `prefix` already read from the dump (e.g.
+    /// its header) included in the checksum.
+    pub(crate) fn new(inner: R, prefix: &[u8]) -> Self {
+        let mut hasher = Hasher::new();
+        hasher.update(prefix);
+
+        ChecksumReader {
+            inner,
+            hasher,
+            held: [0; CHECKSUM_LEN],
+            held_len: 0,
+        }
+    }
+
+    /// Reads whatever is left of the dump and checks its trailer, returning
+    /// the checksum if it matches.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::ChecksumMismatch] if the trailer is missing
+    /// or doesn't match the rest of the dump.
+    pub(crate) fn finish(mut self) -> Result<u32, DatabaseError> {
+        io::copy(&mut self, &mut io::sink())?;
+        let checksum = self.hasher.finalize();
+
+        if self.held_len == CHECKSUM_LEN && self.held == checksum.to_le_bytes() {
+            Ok(checksum)
+        } else {
+            Err(DatabaseError::ChecksumMismatch)
+        }
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        while self.held_len < CHECKSUM_LEN {
+            match self.inner.read(&mut self.held[self.held_len..])? {
+                0 => return Ok(0),
+                read => self.held_len += read,
+            }
+        }
+
+        let read = self.inner.read(buf)?;
+
+        // pass on the oldest `read` bytes of the held bytes followed by what
+        // was just read, holding back the newest
+        if read >= CHECKSUM_LEN {
+            let mut newest = [0; CHECKSUM_LEN];
+            newest.copy_from_slice(&buf[read - CHECKSUM_LEN..read]);
+
+            buf.copy_within(..read - CHECKSUM_LEN, CHECKSUM_LEN);
+            buf[..CHECKSUM_LEN].copy_from_slice(&self.held);
+            self.held = newest;
+        } else {
+            let mut joined = [0; CHECKSUM_LEN * 2];
+            joined[..CHECKSUM_LEN].copy_from_slice(&self.held);
+            joined[CHECKSUM_LEN..CHECKSUM_LEN + read].copy_from_slice(&buf[..read]);
+
+            buf[..read].copy_from_slice(&joined[..read]);
+            self.held
+                .copy_from_slice(&joined[read..read + CHECKSUM_LEN]);
+        }
+
+        self.hasher.update(&buf[..read]);
+
+        Ok(read)
+    }
+}
+
+/// Checks the trailer of a whole dump held in `stream`, returning the stream
+/// without its trailer and the checksum if it matches. The stream must be long
+/// enough to hold a header and a trailer.
+///
+/// # Errors
+///
+/// Will return [DatabaseError::ChecksumMismatch] if the trailer is missing or
+/// doesn't match the rest of the stream.
+#[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+pub(crate) fn split_checked(stream: &[u8]) -> Result<(&[u8], u32), DatabaseError> {
+    if stream.len() < HEADER_LEN + CHECKSUM_LEN {
+        return Err(DatabaseError::ChecksumMismatch);
+    }
+
+    let (body, trailer) = stream.split_at(stream.len() - CHECKSUM_LEN);
+    let checksum = crc32fast::hash(body);
+
+    if trailer == checksum.to_le_bytes() {
+        Ok((body, checksum))
+    } else {
+        Err(DatabaseError::ChecksumMismatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that a written trailer is accepted and any flipped bit rejected.
+    #[test]
+    fn detects_bit_rot() -> Result<(), DatabaseError> {
+        let mut stream = vec![];
+        let mut writer = ChecksumWriter::new(&mut stream);
+
+        writer.write_all(b"some important data")?;
+        writer.finish()?;
+
+        assert_eq!(split_checked(&stream)?.0, b"some important data");
+
+        stream[3] ^= 0b0000_0100;
+
+        assert!(matches!(
+            split_checked(&stream),
+            Err(DatabaseError::ChecksumMismatch)
+        ));
+        assert!(matches!(
+            split_checked(&[0, 1]),
+            Err(DatabaseError::ChecksumMismatch)
+        ));
+
+        Ok(())
+    }
+
+    /// Tests that reading through a [ChecksumReader] in small and large
+    /// chunks passes on everything but the trailer, which is then checked.
+    #[test]
+    fn reader_holds_back_trailer() -> Result<(), DatabaseError> {
+        let mut stream = vec![];
+        let mut writer = ChecksumWriter::new(&mut stream);
+
+        writer.write_all(b"header and a payload")?;
+        writer.finish()?;
+
+        for chunk in [1, 3, 4, 7, 64] {
+            let mut reader = ChecksumReader::new(&stream[6..], b"header");
+            let mut body = vec![];
+            let mut buf = vec![0; chunk];
+
+            loop {
+                match reader.read(&mut buf)? {
+                    0 => break,
+                    read => body.extend_from_slice(&buf[..read]),
+                }
+            }
+
+            assert_eq!(body, b" and a payload");
+            assert_eq!(reader.finish()?, split_checked(&stream)?.1);
+        }
+
+        let mut corrupted = stream.clone();
+        corrupted[8] ^= 0b0001_0000;
+
+        assert!(matches!(
+            ChecksumReader::new(&corrupted[6..], b"header").finish(),
+            Err(DatabaseError::ChecksumMismatch)
+        ));
+        assert!(matches!(
+            ChecksumReader::new(&[0, 1][..], b"").finish(),
+            Err(DatabaseError::ChecksumMismatch)
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,561 @@
+//! Serialization formats which databases can be dumped with.
+//!
+//! Bincode is always available and used by default, whilst other formats are
+//! enabled with cargo features:
+//!
+//! | Format      | Feature   | Extension           |
+//! |-------------|-----------|---------------------|
+//! | Bincode     | (always)  | `.tinydb` or `.db`  |
+//! | JSON        | `json`    | `.json`             |
+//! | CBOR        | `cbor`    | `.cbor`             |
+//! | MessagePack | `msgpack` | `.msgpack` or `.mp` |
+//! | RON         | `ron`     | `.ron`              |
+//!
+//! The format used by a dump is recorded in its [crate::header::Header] so
+//! [crate::Database::from] detects it automatically, and
+//! [crate::Database::auto_from] picks the format of new databases from their
+//! extension.
+//!
+//! Payloads are decoded as they are read, except for RON which can only be
+//! parsed from memory so is read whole first. When decoding an item at a time
+//! with [crate::Database::for_each_item], CBOR items are all decoded before
+//! any are passed on as ciborium can't decode into a seed.
+//!
+//! Other formats can be added by implementing [Codec] with an identifier in
+//! [USER_IDS], then dumping and loading with
+//! [crate::Database::dump_with_codec] and [crate::Database::from_with_codec].
+
+use crate::error::DatabaseError;
+use crate::items::ItemsSeed;
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::{Read, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// Format identifiers reserved for codecs implemented outside of TinyDB, which
+/// built-in formats will never use.
+pub const USER_IDS: RangeInclusive<u8> = 8..=15;
+
+/// A serialization format which encodes and decodes the payload of a dump.
+///
+/// # Examples
+///
+/// Implementing a codec which stores bincode backwards:
+///
+/// ```rust
+/// use serde::{de::DeserializeOwned, Serialize};
+/// use std::io::{Read, Write};
+/// use tinydb::{codec::Codec, error::DatabaseError, Database};
+///
+/// struct Backwards;
+///
+/// impl Codec for Backwards {
+///     const ID: u8 = 8;
+///
+///     fn encode<W: Write, V: Serialize>(mut writer: W, value: &V) -> Result<(), DatabaseError> {
+///         let mut encoded = bincode::serialize(value).unwrap();
+///         encoded.reverse();
+///
+///         Ok(writer.write_all(&encoded)?)
+///     }
+///
+///     fn decode<R: Read, V: DeserializeOwned>(reader: R, limit: u64) -> Result<V, DatabaseError> {
+///         let mut encoded = vec![];
+///         reader.take(limit).read_to_end(&mut encoded)?;
+///         encoded.reverse();
+///
+///         Ok(bincode::deserialize(&encoded).unwrap())
+///     }
+/// }
+///
+/// fn main() {
+///     let mut db = Database::new("codec_example", None, false);
+///     db.add_item(String::from("Camille")).unwrap();
+///     db.dump_with_codec::<Backwards>().unwrap();
+///
+///     let loaded: Database<String> = Database::from_with_codec::<Backwards>("codec_example.tinydb").unwrap();
+///     assert_eq!(loaded.items, db.items);
+///     # std::fs::remove_file("codec_example.tinydb").unwrap();
+/// }
+/// ```
+pub trait Codec {
+    /// Identifier of the format, stored inside of the header of each dump.
+    /// Codecs implemented outside of TinyDB must use one of [USER_IDS].
+    const ID: u8;
+
+    /// Encodes `value` into `writer`.
+    fn encode<W: Write, V: Serialize>(writer: W, value: &V) -> Result<(), DatabaseError>;
+
+    /// Decodes a value from `reader`, which is never longer than `limit` bytes.
+    fn decode<R: Read, V: DeserializeOwned>(reader: R, limit: u64) -> Result<V, DatabaseError>;
+}
+
+/// The compact binary [bincode](https://docs.rs/bincode) format.
+pub struct Bincode;
+
+impl Codec for Bincode {
+    const ID: u8 = 0;
+
+    fn encode<W: Write, V: Serialize>(writer: W, value: &V) -> Result<(), DatabaseError> {
+        bincode::serialize_into(writer, value).map_err(encode_failed)
+    }
+
+    fn decode<R: Read, V: DeserializeOwned>(reader: R, limit: u64) -> Result<V, DatabaseError> {
+        bincode_options(limit)
+            .deserialize_from(reader)
+            .map_err(decode_failed)
+    }
+}
+
+/// Same config as `bincode::deserialize` but limited to `limit` bytes so that
+/// corrupted lengths can't cause huge allocations.
+fn bincode_options(limit: u64) -> impl bincode::Options {
+    bincode::options()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit)
+}
+
+/// The [JSON](https://docs.rs/serde_json) format.
+#[cfg(feature = "json")]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl Codec for Json {
+    const ID: u8 = 1;
+
+    fn encode<W: Write, V: Serialize>(writer: W, value: &V) -> Result<(), DatabaseError> {
+        serde_json::to_writer(writer, value).map_err(encode_failed)
+    }
+
+    fn decode<R: Read, V: DeserializeOwned>(reader: R, _limit: u64) -> Result<V, DatabaseError> {
+        serde_json::from_reader(reader).map_err(decode_failed)
+    }
+}
+
+/// The [CBOR](https://docs.rs/ciborium) format.
+#[cfg(feature = "cbor")]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Codec for Cbor {
+    const ID: u8 = 2;
+
+    fn encode<W: Write, V: Serialize>(writer: W, value: &V) -> Result<(), DatabaseError> {
+        ciborium::ser::into_writer(value, writer).map_err(encode_failed)
+    }
+
+    fn decode<R: Read, V: DeserializeOwned>(reader: R, _limit: u64) -> Result<V, DatabaseError> {
+        ciborium::de::from_reader(reader).map_err(decode_failed)
+    }
+}
+
+/// The [MessagePack](https://docs.rs/rmp-serde) format, encoding structures
+/// as maps so that they can be read without knowing their field order.
+#[cfg(feature = "msgpack")]
+pub struct MessagePack;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MessagePack {
+    const ID: u8 = 3;
+
+    fn encode<W: Write, V: Serialize>(mut writer: W, value: &V) -> Result<(), DatabaseError> {
+        rmp_serde::encode::write_named(&mut writer, value).map_err(encode_failed)
+    }
+
+    fn decode<R: Read, V: DeserializeOwned>(reader: R, _limit: u64) -> Result<V, DatabaseError> {
+        rmp_serde::decode::from_read(reader).map_err(decode_failed)
+    }
+}
+
+/// The [RON](https://docs.rs/ron) format. RON can only be parsed from memory,
+/// so payloads are read whole before being decoded.
+#[cfg(feature = "ron")]
+pub struct Ron;
+
+#[cfg(feature = "ron")]
+impl Codec for Ron {
+    const ID: u8 = 4;
+
+    fn encode<W: Write, V: Serialize>(writer: W, value: &V) -> Result<(), DatabaseError> {
+        ron::ser::to_writer(writer, value).map_err(encode_failed)
+    }
+
+    fn decode<R: Read, V: DeserializeOwned>(reader: R, _limit: u64) -> Result<V, DatabaseError> {
+        ron::de::from_reader(reader).map_err(decode_failed)
+    }
+}
+
+/// Selects which [Codec] a [crate::Database] is dumped with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Format {
+    /// See [Bincode].
+    #[default]
+    Bincode,
+
+    /// See [Json].
+    #[cfg(feature = "json")]
+    Json,
+
+    /// See [Cbor].
+    #[cfg(feature = "cbor")]
+    Cbor,
+
+    /// See [MessagePack].
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+
+    /// See [Ron].
+    #[cfg(feature = "ron")]
+    Ron,
+
+    /// A [Codec] implemented outside of TinyDB, containing its identifier from
+    /// [USER_IDS]. Databases can only be dumped and loaded in this format with
+    /// [crate::Database::dump_with_codec] and [crate::Database::from_with_codec].
+    ///
+    /// Use [Format::custom] to make sure the identifier is in [USER_IDS].
+    /// Dumping with any other identifier fails before anything is written.
+    Custom(u8),
+}
+
+impl Format {
+    /// Returns the identifier stored inside of the header for this format.
+    pub fn id(self) -> u8 {
+        match self {
+            Format::Bincode => Bincode::ID,
+            #[cfg(feature = "json")]
+            Format::Json => Json::ID,
+            #[cfg(feature = "cbor")]
+            Format::Cbor => Cbor::ID,
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => MessagePack::ID,
+            #[cfg(feature = "ron")]
+            Format::Ron => Ron::ID,
+            Format::Custom(id) => id,
+        }
+    }
+
+    /// Creates a [Format::Custom] for the codec with identifier `id`.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::UnsupportedFormat] if `id` isn't one of
+    /// [USER_IDS].
+    pub fn custom(id: u8) -> Result<Self, DatabaseError> {
+        if USER_IDS.contains(&id) {
+            Ok(Format::Custom(id))
+        } else {
+            Err(DatabaseError::UnsupportedFormat(id))
+        }
+    }
+
+    /// Finds the format with the given identifier.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::UnsupportedFormat] if the identifier is
+    /// unknown or its feature isn't enabled. Identifiers in [USER_IDS] are
+    /// always [Format::Custom].
+    pub fn from_id(id: u8) -> Result<Self, DatabaseError> {
+        match id {
+            Bincode::ID => Ok(Format::Bincode),
+            #[cfg(feature = "json")]
+            Json::ID => Ok(Format::Json),
+            #[cfg(feature = "cbor")]
+            Cbor::ID => Ok(Format::Cbor),
+            #[cfg(feature = "msgpack")]
+            MessagePack::ID => Ok(Format::MessagePack),
+            #[cfg(feature = "ron")]
+            Ron::ID => Ok(Format::Ron),
+            id => Format::custom(id),
+        }
+    }
+
+    /// Guesses the format from the extension of `path`, returning [None] if
+    /// it isn't recognised or its feature isn't enabled.
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "tinydb" | "db" => Some(Format::Bincode),
+            #[cfg(feature = "json")]
+            "json" => Some(Format::Json),
+            #[cfg(feature = "cbor")]
+            "cbor" => Some(Format::Cbor),
+            #[cfg(feature = "msgpack")]
+            "msgpack" | "mp" => Some(Format::MessagePack),
+            #[cfg(feature = "ron")]
+            "ron" => Some(Format::Ron),
+            _ => None,
+        }
+    }
+
+    /// Checks that this format can be encoded with the built-in codecs or `C`,
+    /// so that nothing is written for a format which can't be.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::UnsupportedFormat] if this is a
+    /// [Format::Custom] with an identifier outside of [USER_IDS] or which isn't
+    /// the one of `C`.
+    pub(crate) fn check_encodable<C: Codec>(self) -> Result<(), DatabaseError> {
+        match Format::from_id(self.id())? {
+            Format::Custom(id) if id != C::ID => Err(DatabaseError::UnsupportedFormat(id)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Encodes `value` into `writer` with this format, or with `C` if this is
+    /// its format.
+    pub(crate) fn encode<C: Codec, W: Write, V: Serialize>(
+        self,
+        writer: W,
+        value: &V,
+    ) -> Result<(), DatabaseError> {
+        if self.id() == C::ID {
+            return C::encode(writer, value);
+        }
+
+        match self {
+            Format::Bincode => Bincode::encode(writer, value),
+            #[cfg(feature = "json")]
+            Format::Json => Json::encode(writer, value),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => Cbor::encode(writer, value),
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => MessagePack::encode(writer, value),
+            #[cfg(feature = "ron")]
+            Format::Ron => Ron::encode(writer, value),
+            Format::Custom(id) => Err(DatabaseError::UnsupportedFormat(id)),
+        }
+    }
+
+    /// Decodes a value with this format, or with `C` if this is its format,
+    /// from `reader`, which is never longer than `limit` bytes, reporting the
+    /// offset into the file where decoding failed if it can't be decoded given
+    /// that `reader` starts at `offset`.
+    pub(crate) fn decode<C: Codec, R: Read, V: DeserializeOwned>(
+        self,
+        reader: R,
+        offset: u64,
+        limit: u64,
+    ) -> Result<V, DatabaseError> {
+        let mut counter = CountingReader {
+            inner: reader,
+            count: offset,
+        };
+
+        let decoded = match self {
+            _ if self.id() == C::ID => C::decode(&mut counter, limit),
+            Format::Bincode => Bincode::decode(&mut counter, limit),
+            #[cfg(feature = "json")]
+            Format::Json => Json::decode(&mut counter, limit),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => Cbor::decode(&mut counter, limit),
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => MessagePack::decode(&mut counter, limit),
+            #[cfg(feature = "ron")]
+            Format::Ron => Ron::decode(&mut counter, limit),
+            Format::Custom(id) => Err(DatabaseError::UnsupportedFormat(id)),
+        };
+
+        decoded.map_err(|e| at_offset(e, counter.count))
+    }
+
+    /// Decodes an encoded [crate::Database] with this format from `reader` in
+    /// the same way as [Format::decode], but passes each of its items to `f`
+    /// as soon as it is decoded rather than collecting them.
+    ///
+    /// CBOR has no way of decoding this way, so its items are collected
+    /// before being passed on, and RON payloads are read into memory whole
+    /// first. [Format::Custom] can't be decoded this way at all.
+    pub(crate) fn decode_items<R: Read, T: DeserializeOwned, F: FnMut(T)>(
+        self,
+        reader: R,
+        offset: u64,
+        limit: u64,
+        f: F,
+    ) -> Result<(), DatabaseError> {
+        let mut counter = CountingReader {
+            inner: reader,
+            count: offset,
+        };
+        let seed = ItemsSeed::new(f);
+
+        let decoded = match self {
+            Format::Bincode => bincode_options(limit)
+                .deserialize_from_seed(seed, &mut counter)
+                .map_err(decode_failed),
+            #[cfg(feature = "json")]
+            Format::Json => {
+                use serde::de::DeserializeSeed;
+
+                let mut deserializer = serde_json::Deserializer::from_reader(&mut counter);
+
+                seed.deserialize(&mut deserializer)
+                    .and_then(|()| deserializer.end())
+                    .map_err(decode_failed)
+            }
+            #[cfg(feature = "cbor")]
+            Format::Cbor => {
+                /// Only the items of an encoded [crate::Database].
+                #[derive(serde::Deserialize)]
+                struct Items<T> {
+                    items: Vec<T>,
+                }
+
+                Cbor::decode::<_, Items<T>>(&mut counter, limit)
+                    .map(|decoded| decoded.items.into_iter().for_each(seed.into_inner()))
+            }
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => {
+                use serde::de::DeserializeSeed;
+
+                seed.deserialize(&mut rmp_serde::Deserializer::new(&mut counter))
+                    .map_err(decode_failed)
+            }
+            #[cfg(feature = "ron")]
+            Format::Ron => {
+                use serde::de::DeserializeSeed;
+
+                let mut encoded = vec![];
+                counter.read_to_end(&mut encoded)?;
+
+                ron::Deserializer::from_bytes(&encoded)
+                    .map_err(decode_failed)
+                    .and_then(|mut deserializer| {
+                        seed.deserialize(&mut deserializer)
+                            .and_then(|()| deserializer.end())
+                            .map_err(decode_failed)
+                    })
+            }
+            Format::Custom(id) => Err(DatabaseError::UnsupportedFormat(id)),
+        };
+
+        decoded.map_err(|e| at_offset(e, counter.count))
+    }
+}
+
+/// Sets the offset at which decoding failed on a [DatabaseError::DecodeFailed].
+fn at_offset(error: DatabaseError, offset: u64) -> DatabaseError {
+    match error {
+        DatabaseError::DecodeFailed { path, source, .. } => DatabaseError::DecodeFailed {
+            path,
+            offset: Some(offset),
+            source,
+        },
+        other => other,
+    }
+}
+
+/// Wraps an encoding error from a [Codec].
+fn encode_failed(source: impl std::error::Error + Send + Sync + 'static) -> DatabaseError {
+    DatabaseError::EncodeFailed {
+        path: None,
+        source: Box::new(source),
+    }
+}
+
+/// Wraps a decoding error from a [Codec].
+fn decode_failed(source: impl std::error::Error + Send + Sync + 'static) -> DatabaseError {
+    DatabaseError::DecodeFailed {
+        path: None,
+        offset: None,
+        source: Box::new(source),
+    }
+}
+
+/// Wraps a reader and counts the bytes read through it, used to report the
+/// offset at which decoding failed.
+struct CountingReader<R: Read> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Every format enabled for this build.
+    fn formats() -> Vec<Format> {
+        vec![
+            Format::Bincode,
+            #[cfg(feature = "json")]
+            Format::Json,
+            #[cfg(feature = "cbor")]
+            Format::Cbor,
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack,
+            #[cfg(feature = "ron")]
+            Format::Ron,
+        ]
+    }
+
+    /// Tests that a value survives encoding and decoding in every format.
+    #[test]
+    fn roundtrip() -> Result<(), DatabaseError> {
+        let value: HashSet<(String, i32)> = vec![(String::from("Talkie Toaster"), 3)]
+            .into_iter()
+            .collect();
+
+        for format in formats() {
+            let mut encoded = vec![];
+            format.encode::<Bincode, _, _>(&mut encoded, &value)?;
+
+            assert_eq!(
+                format.decode::<Bincode, _, HashSet<(String, i32)>>(
+                    &encoded[..],
+                    0,
+                    encoded.len() as u64
+                )?,
+                value
+            );
+            assert_eq!(Format::from_id(format.id())?, format);
+        }
+
+        Ok(())
+    }
+
+    /// Tests that unknown identifiers and extensions aren't accepted, and that
+    /// user identifiers are custom formats.
+    #[test]
+    fn unknown() {
+        assert!(matches!(
+            Format::from_id(7),
+            Err(DatabaseError::UnsupportedFormat(7))
+        ));
+        assert!(matches!(
+            Format::from_id(16),
+            Err(DatabaseError::UnsupportedFormat(16))
+        ));
+        assert!(matches!(Format::from_id(15), Ok(Format::Custom(15))));
+        assert!(matches!(Format::custom(8), Ok(Format::Custom(8))));
+        assert!(matches!(
+            Format::custom(Bincode::ID),
+            Err(DatabaseError::UnsupportedFormat(0))
+        ));
+        assert!(matches!(
+            Format::Custom(200).check_encodable::<Bincode>(),
+            Err(DatabaseError::UnsupportedFormat(200))
+        ));
+        assert!(matches!(
+            Format::Custom(9).check_encodable::<Bincode>(),
+            Err(DatabaseError::UnsupportedFormat(9))
+        ));
+        assert_eq!(
+            Format::from_extension(Path::new("x.db")),
+            Some(Format::Bincode)
+        );
+        assert_eq!(Format::from_extension(Path::new("x.xml")), None);
+        assert_eq!(Format::from_extension(Path::new("x")), None);
+    }
+}
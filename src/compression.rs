@@ -0,0 +1,161 @@
+//! Optional compression of dumped databases.
+//!
+//! Compression algorithms are enabled with cargo features:
+//!
+//! | Algorithm | Feature |
+//! |-----------|---------|
+//! | Zstandard | `zstd`  |
+//! | LZ4       | `lz4`   |
+//!
+//! The algorithm used by a dump is recorded in its [crate::header::Header] so
+//! [crate::Database::from] detects and decompresses it automatically.
+
+use crate::error::DatabaseError;
+use std::io::{Read, Write};
+
+/// Selects how the payload of a [crate::Database] is compressed when dumped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Compression {
+    /// Leave the payload uncompressed.
+    #[default]
+    None,
+
+    /// Compress with [Zstandard](https://docs.rs/zstd) at the given level,
+    /// from `1` (fastest) to `22` (smallest), or `0` for its default of `3`.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+
+    /// Compress with the [LZ4](https://docs.rs/lz4_flex) frame format.
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+
+impl Compression {
+    /// Returns the identifier stored inside of the header for this algorithm.
+    pub fn id(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(_) => 1,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => 2,
+        }
+    }
+
+    /// Finds the algorithm with the given identifier. As the level used isn't
+    /// needed to decompress, Zstandard is returned with its default level.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::UnsupportedCompression] if the identifier
+    /// is unknown or its feature isn't enabled.
+    pub fn from_id(id: u8) -> Result<Self, DatabaseError> {
+        match id {
+            0 => Ok(Compression::None),
+            #[cfg(feature = "zstd")]
+            1 => Ok(Compression::Zstd(0)),
+            #[cfg(feature = "lz4")]
+            2 => Ok(Compression::Lz4),
+            _ => Err(DatabaseError::UnsupportedCompression(id)),
+        }
+    }
+
+    /// Compresses everything `write` outputs into `writer`.
+    pub(crate) fn compress<W, F>(self, writer: W, write: F) -> Result<(), DatabaseError>
+    where
+        W: Write,
+        F: FnOnce(&mut dyn Write) -> Result<(), DatabaseError>,
+    {
+        match self {
+            Compression::None => {
+                let mut writer = writer;
+                write(&mut writer)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => {
+                let mut encoder = zstd::Encoder::new(writer, level)?;
+                write(&mut encoder)?;
+                encoder.finish()?;
+
+                Ok(())
+            }
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(writer);
+                write(&mut encoder)?;
+                encoder.finish().map_err(std::io::Error::from)?;
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Wraps `reader` so that everything read through it is decompressed,
+    /// which avoids holding the whole decompressed payload in memory.
+    pub(crate) fn decompress<'a, R: Read + 'a>(
+        self,
+        reader: R,
+    ) -> Result<Box<dyn Read + 'a>, DatabaseError> {
+        match self {
+            Compression::None => Ok(Box::new(reader)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(_) => Ok(Box::new(zstd::Decoder::new(reader)?)),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Ok(Box::new(lz4_flex::frame::FrameDecoder::new(reader))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every algorithm enabled for this build.
+    fn algorithms() -> Vec<Compression> {
+        vec![
+            Compression::None,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(19),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4,
+        ]
+    }
+
+    /// Tests that data survives compression with every algorithm and that
+    /// repetitive data actually shrinks.
+    #[test]
+    fn roundtrip() -> Result<(), DatabaseError> {
+        let data = b"Smoke me a kipper, I'll be back for breakfast. ".repeat(100);
+
+        for compression in algorithms() {
+            let mut compressed = vec![];
+            compression.compress(&mut compressed, |w| Ok(w.write_all(&data)?))?;
+
+            if compression != Compression::None {
+                assert!(compressed.len() < data.len());
+            }
+
+            let mut decompressed = vec![];
+            compression
+                .decompress(&compressed[..])?
+                .read_to_end(&mut decompressed)?;
+
+            assert_eq!(decompressed, data);
+            assert_eq!(
+                Compression::from_id(compression.id())?.id(),
+                compression.id()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Tests that unknown identifiers aren't accepted.
+    #[test]
+    fn unknown() {
+        assert!(matches!(
+            Compression::from_id(15),
+            Err(DatabaseError::UnsupportedCompression(15))
+        ));
+    }
+}
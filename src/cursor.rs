@@ -0,0 +1,162 @@
+//! Cursor-based pagination over ordered indexes, see
+//! [crate::Database::query_page].
+
+use crate::error::DatabaseError;
+use crate::query;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Position just after the last item of a [Page], used to fetch the page
+/// following it with [crate::Database::query_page].
+///
+/// Cursors record where in an ordered index the page ended rather than how
+/// many items came before it, so fetching the next page after items have been
+/// added or removed won't skip or repeat any which were there all along.
+///
+/// Cursors can be converted to and from an opaque string token with
+/// [fmt::Display] and [FromStr] to be handed to clients, or serialized with
+/// serde. Tokens aren't encrypted, so the key and encoding of the last item
+/// can be recovered from them, and they grow with the size of that item.
+///
+/// Items sharing a key are ordered by a hash of their bincode encoding and
+/// then by the encoding itself, which doesn't depend on the Rust release or
+/// platform. Tokens stay valid across restarts and upgrades as long as the
+/// encoding of the keys and items does, which isn't the case for those holding
+/// a [std::collections::HashMap] or [std::collections::HashSet] as they are
+/// encoded in an order which changes between runs. Use ordered collections
+/// such as [std::collections::BTreeMap] in them instead, or only hand out
+/// tokens which are used before the database is next loaded.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Cursor {
+    /// Name of the ordered index the cursor belongs to
+    index: String,
+
+    /// Encoded key of the last item of the page
+    key: Vec<u8>,
+
+    /// Encoded last item of the page, ordering it amongst the items sharing
+    /// its key along with its hash
+    item: Vec<u8>,
+}
+
+impl Cursor {
+    /// Creates a cursor positioned after the item with `key` and the `tie`
+    /// made by [query::fixed_tie] in the ordered index called `index`.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::EncodeFailed] if `key` can't be encoded.
+    pub(crate) fn new<K: Serialize>(
+        index: &str,
+        key: &K,
+        tie: (u64, Vec<u8>),
+    ) -> Result<Self, DatabaseError> {
+        Ok(Cursor {
+            index: index.into(),
+            key: bincode::serialize(key)
+                .map_err(|source| DatabaseError::EncodeFailed { path: None, source })?,
+            item: tie.1,
+        })
+    }
+
+    /// Returns the key and tie of the item the cursor is positioned after.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::InvalidCursor] if the cursor doesn't belong
+    /// to the index called `index` or its key isn't a `K`.
+    pub(crate) fn position<K: DeserializeOwned>(
+        &self,
+        index: &str,
+    ) -> Result<(K, (u64, Vec<u8>)), DatabaseError> {
+        if self.index != index {
+            return Err(DatabaseError::InvalidCursor);
+        }
+
+        let tie = (query::fnv1a(&self.item), self.item.clone());
+
+        bincode::deserialize(&self.key)
+            .map(|key| (key, tie))
+            .map_err(|_| DatabaseError::InvalidCursor)
+    }
+}
+
+impl fmt::Display for Cursor {
+    /// Writes the cursor as a hexadecimal token.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in bincode::serialize(self).map_err(|_| fmt::Error)? {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = DatabaseError;
+
+    /// Reads a cursor from a token written with [fmt::Display].
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::InvalidCursor] if the token is malformed.
+    fn from_str(token: &str) -> Result<Self, Self::Err> {
+        let bytes = token
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| match std::str::from_utf8(pair) {
+                Ok(pair) if pair.len() == 2 => u8::from_str_radix(pair, 16).ok(),
+                _ => None,
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(DatabaseError::InvalidCursor)?;
+
+        bincode::deserialize(&bytes).map_err(|_| DatabaseError::InvalidCursor)
+    }
+}
+
+/// A page of items returned by [crate::Database::query_page].
+#[derive(Debug)]
+pub struct Page<'a, T> {
+    /// Items on this page, in key order
+    pub items: Vec<&'a T>,
+
+    /// Cursor to fetch the next page with, or [None] if there were no more
+    /// items when this page was fetched
+    pub next: Option<Cursor>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that cursors survive being written as tokens and read back, and
+    /// that malformed or mismatched cursors are rejected.
+    #[test]
+    fn cursor_tokens() -> Result<(), DatabaseError> {
+        let tie = query::fixed_tie(&"Lister")?;
+        let cursor = Cursor::new("age", &30i32, tie.clone())?;
+        let token = cursor.to_string();
+
+        assert_eq!(token.parse::<Cursor>()?, cursor);
+        assert_eq!(cursor.position::<i32>("age")?, (30, tie));
+
+        for bad in ["", "abc", "zz", "ff", "Kryten"] {
+            assert!(matches!(
+                bad.parse::<Cursor>(),
+                Err(DatabaseError::InvalidCursor)
+            ));
+        }
+        assert!(matches!(
+            cursor.position::<i32>("name"),
+            Err(DatabaseError::InvalidCursor)
+        ));
+        assert!(matches!(
+            cursor.position::<u64>("age"),
+            Err(DatabaseError::InvalidCursor)
+        ));
+
+        Ok(())
+    }
+}
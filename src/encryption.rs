@@ -0,0 +1,285 @@
+//! At-rest encryption of dumped databases, enabled with the `encryption`
+//! feature.
+//!
+//! Encrypted dumps are sealed with
+//! [ChaCha20-Poly1305](https://docs.rs/chacha20poly1305) under a 256-bit
+//! [Key], using a fresh random nonce every time they are written and the
+//! [crate::header::Header] as associated data so it can't be tampered with.
+//! The checksum trailer still covers the encrypted file, meaning
+//! [crate::Database::verify] can detect corruption without the key.
+//!
+//! Records in the write-ahead log of an encrypted database are sealed with the
+//! same key.
+
+use crate::error::DatabaseError;
+use crate::header::{Header, HEADER_LEN};
+use crate::store::Store;
+use crate::{atomic, checksum, codec, open_path, wal, Database};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt;
+use std::hash::Hash;
+use std::io::{BufReader, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Length of a [Key] in bytes.
+pub const KEY_LEN: usize = 32;
+
+/// Length of the nonce stored before each sealed payload in bytes.
+const NONCE_LEN: usize = 12;
+
+/// A 256-bit key used to encrypt and decrypt databases.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Key([u8; KEY_LEN]);
+
+impl Key {
+    /// Creates a key from raw bytes, which should come from a secure source
+    /// such as a key management service.
+    pub fn from_bytes(bytes: [u8; KEY_LEN]) -> Self {
+        Key(bytes)
+    }
+
+    /// Generates a new random key using the operating system's secure random
+    /// number generator.
+    pub fn generate() -> Self {
+        Key(ChaCha20Poly1305::generate_key(&mut OsRng).into())
+    }
+
+    /// Returns the raw bytes of the key, e.g. to store it elsewhere.
+    pub fn as_bytes(&self) -> &[u8; KEY_LEN] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Key(..)")
+    }
+}
+
+/// Provides the [Key] used to encrypt or decrypt a database, allowing keys to
+/// be fetched from elsewhere (e.g. a key management service) when needed
+/// rather than kept around.
+pub trait KeyProvider: Send + Sync {
+    /// Returns the key to use.
+    fn key(&self) -> Result<Key, DatabaseError>;
+}
+
+impl KeyProvider for Key {
+    fn key(&self) -> Result<Key, DatabaseError> {
+        Ok(self.clone())
+    }
+}
+
+impl fmt::Debug for dyn KeyProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "KeyProvider(..)")
+    }
+}
+
+/// Encrypts `plaintext` with `key` and authenticates it alongside `aad`,
+/// returning the random nonce used followed by the ciphertext.
+pub(crate) fn seal(key: &Key, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, DatabaseError> {
+    let cipher = ChaCha20Poly1305::new(&key.0.into());
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| DatabaseError::EncodeFailed {
+            path: None,
+            source: Box::new(std::io::Error::other("could not encrypt")),
+        })?;
+
+    let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+
+    Ok(sealed)
+}
+
+/// Decrypts something sealed with [seal] using the same `key` and `aad`.
+///
+/// # Errors
+///
+/// Will return [DatabaseError::DecryptionFailed] if the key is wrong or the
+/// sealed data or `aad` has been tampered with.
+pub(crate) fn open(key: &Key, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, DatabaseError> {
+    if sealed.len() < NONCE_LEN {
+        return Err(DatabaseError::DecryptionFailed);
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+
+    ChaCha20Poly1305::new(&key.0.into())
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| DatabaseError::DecryptionFailed)
+}
+
+/// Re-encrypts the encrypted dump at `path`, a [Database] storing `T`, from
+/// the `old` key to the `new` key. Keys of keyed databases are rotated with
+/// [crate::keyed::KeyedDatabase::rotate_key] instead.
+///
+/// Any write-ahead log next to the dump is first folded into it under the
+/// `old` key and removed, so that there is only ever one file to re-encrypt.
+/// Each step replaces files atomically in the same way as
+/// [crate::Database::dump_db], so a crash or error part-way through leaves
+/// everything readable with one of the keys. The `new` key is only fetched
+/// once the log has been folded away. The database shouldn't be open
+/// elsewhere whilst rotating its key.
+///
+/// # Errors
+///
+/// Will return [DatabaseError::NotEncrypted] if the dump isn't encrypted,
+/// [DatabaseError::ItemTypeMismatch] if it is a keyed database or
+/// [DatabaseError::DecryptionFailed] if it or its log can't be decrypted with
+/// `old`.
+///
+/// # Examples
+///
+/// ```rust
+/// use tinydb::encryption::{self, Key};
+/// use tinydb::Database;
+///
+/// fn main() {
+///     let (old, new) = (Key::generate(), Key::generate());
+///
+///     let mut db = Database::new("rotate_example", None, false);
+///     db.set_key(old.clone()).unwrap();
+///     db.add_item(String::from("Camille")).unwrap();
+///     db.dump_db().unwrap();
+///
+///     encryption::rotate_key::<String>("rotate_example.tinydb", &old, &new).unwrap();
+///
+///     assert!(Database::<String>::from_with_key("rotate_example.tinydb", old).is_err());
+///     assert!(Database::<String>::from_with_key("rotate_example.tinydb", new).is_ok());
+///     # std::fs::remove_file("rotate_example.tinydb").unwrap();
+/// }
+/// ```
+pub fn rotate_key<T>(
+    path: impl AsRef<Path>,
+    old: &dyn KeyProvider,
+    new: &dyn KeyProvider,
+) -> Result<(), DatabaseError>
+where
+    T: Hash + Eq + Serialize + DeserializeOwned,
+{
+    rotate::<Database<T>>(path.as_ref(), old, new)
+}
+
+/// Re-encrypts the encrypted dump at `path` of the store `S` from the `old`
+/// key to the `new` key, see [rotate_key].
+pub(crate) fn rotate<S: Store>(
+    path: &Path,
+    old: &dyn KeyProvider,
+    new: &dyn KeyProvider,
+) -> Result<(), DatabaseError> {
+    let old = old.key()?;
+
+    let header = Header::read(&mut open_path(path)?.0)?;
+    if !header.is_encrypted() {
+        return Err(DatabaseError::NotEncrypted);
+    }
+    header.check_kind(S::KIND)?;
+
+    fold_wal::<S>(path, &old)?;
+    let new = new.key()?;
+
+    let stream = std::fs::read(path)?;
+
+    let body = if header.has_checksum() {
+        checksum::split_checked(&stream)?.0
+    } else {
+        &stream[..]
+    };
+
+    let aad = header.to_bytes();
+    let plaintext = open(&old, &body[HEADER_LEN..], &aad)?;
+    let sealed = seal(&new, &plaintext, &aad)?;
+
+    atomic::write_atomic(path, |file| {
+        let mut writer = checksum::ChecksumWriter::new(file);
+
+        header.write(&mut writer)?;
+        writer.write_all(&sealed)?;
+
+        writer.finish()
+    })
+}
+
+/// Folds the write-ahead log next to the encrypted dump at `path` into it,
+/// then removes the log. Replaying records is idempotent, so a crash before
+/// the log is removed is harmless.
+fn fold_wal<S: Store>(path: &Path, key: &Key) -> Result<(), DatabaseError> {
+    let wal_path = wal::wal_path(path);
+    let records = wal::read_records::<S::Record>(&wal_path, &Some(key.clone()))?;
+
+    if !records.is_empty() {
+        let (file, len) = open_path(path)?;
+        let mut db =
+            S::read_from::<codec::Bincode, _>(BufReader::new(file), len, &Some(key.clone()), false)
+                .map_err(|e| e.with_path(path))?;
+
+        db.apply_records(records);
+        db.storage_mut().set_key(Arc::new(key.clone()));
+
+        atomic::write_atomic(path, |file| db.dump_to(file))?;
+    }
+
+    match std::fs::remove_file(&wal_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that sealed data can only be opened with the same key and
+    /// associated data.
+    #[test]
+    fn seal_and_open() -> Result<(), DatabaseError> {
+        let key = Key::generate();
+        let sealed = seal(&key, b"Arnold Judas Rimmer", b"header")?;
+
+        assert_eq!(open(&key, &sealed, b"header")?, b"Arnold Judas Rimmer");
+        assert!(matches!(
+            open(&Key::generate(), &sealed, b"header"),
+            Err(DatabaseError::DecryptionFailed)
+        ));
+        assert!(matches!(
+            open(&key, &sealed, b"tampered"),
+            Err(DatabaseError::DecryptionFailed)
+        ));
+        assert!(matches!(
+            open(&key, &sealed[..4], b"header"),
+            Err(DatabaseError::DecryptionFailed)
+        ));
+
+        Ok(())
+    }
+
+    /// Tests that sealing the same data twice uses a different nonce.
+    #[test]
+    fn fresh_nonces() -> Result<(), DatabaseError> {
+        let key = Key::from_bytes([7; KEY_LEN]);
+
+        assert_ne!(seal(&key, b"same", b"")?, seal(&key, b"same", b"")?);
+
+        Ok(())
+    }
+}
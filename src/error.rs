@@ -1,5 +1,8 @@
 //! Contains various items related to errors inside of TinyDB.
 
+use std::fmt;
+use std::path::PathBuf;
+
 /// An error enum for the possible faliure states of the [crate::Database] structure.
 #[derive(Debug)]
 pub enum DatabaseError {
@@ -25,6 +28,195 @@ pub enum DatabaseError {
     /// [crate::Database::auto_from] does not have a valid file stem or could not
     /// convert from an [std::ffi::OsString] to a [String].
     BadDbName,
+
+    /// When a file being loaded doesn't start with the [crate::header::MAGIC]
+    /// bytes and is therefore not a TinyDB dump.
+    NotTinyDb,
+
+    /// When a file being loaded was written with a format version this version
+    /// of TinyDB doesn't support, containing the version found.
+    UnsupportedVersion(u16),
+
+    /// When a database being loaded was dumped by a different
+    /// [crate::header::Kind] of database, such as a
+    /// [crate::keyed::KeyedDatabase] loaded as a [crate::Database], or with a
+    /// different schema to the one expected, see
+    /// [crate::Database::check_schema]. Either way it stores a different item
+    /// type or layout.
+    ItemTypeMismatch,
+
+    /// When the checksum stored at the end of a file is missing or doesn't
+    /// match its contents, meaning it has been truncated or corrupted.
+    ChecksumMismatch,
+
+    /// When a file being loaded was encoded with a [crate::codec::Format] which
+    /// is unknown or whose feature isn't enabled, containing its identifier.
+    UnsupportedFormat(u8),
+
+    /// When a file being loaded was compressed with a
+    /// [crate::compression::Compression] which is unknown or whose feature
+    /// isn't enabled, containing its identifier.
+    UnsupportedCompression(u8),
+
+    /// When a file being loaded is encrypted but no key was given to decrypt
+    /// it, or the `encryption` feature isn't enabled.
+    KeyRequired,
+
+    /// When an encrypted file could not be decrypted, either because the key
+    /// is wrong or because it has been tampered with.
+    DecryptionFailed,
+
+    /// When trying to change the key of a file which isn't encrypted.
+    NotEncrypted,
+
+    /// When an index used by a query doesn't exist or doesn't support the
+    /// query, such as a range query on an unordered index. Contains the name
+    /// of the index.
+    IndexNotFound(String),
+
+    /// When an item being added or updated has the same key as a different
+    /// existing item under a unique constraint, see
+    /// [crate::Database::create_unique_constraint]. Contains the name of the
+    /// constraint and a clone of the existing item, which can be retrieved
+    /// with [DatabaseError::existing_item].
+    UniqueViolation {
+        constraint: String,
+        existing: Box<dyn std::any::Any + Send + Sync>,
+    },
+
+    /// When a pagination [crate::cursor::Cursor] is malformed or was made for a
+    /// different index or key type to the one it is used with.
+    InvalidCursor,
+
+    /// When a query written in the [crate::lang] query language could not be
+    /// parsed. Contains the byte offset into the query where the problem was
+    /// found and a description of it.
+    QueryParse { position: usize, message: String },
+
+    /// When a database could not be decoded, typically due to a truncated or
+    /// corrupted file. Contains the path being loaded and the byte offset into
+    /// the file where decoding failed, if known.
+    DecodeFailed {
+        path: Option<PathBuf>,
+        offset: Option<u64>,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// When a database could not be encoded whilst dumping it to the given
+    /// path, if known.
+    EncodeFailed {
+        path: Option<PathBuf>,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+impl DatabaseError {
+    /// Returns the existing item an item conflicted with if this is a
+    /// [DatabaseError::UniqueViolation] and the item is of type `T`.
+    pub fn existing_item<T: 'static>(&self) -> Option<&T> {
+        match self {
+            DatabaseError::UniqueViolation { existing, .. } => existing.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// Attaches `path` to encoding and decoding errors which don't have one.
+    pub(crate) fn with_path(self, path: &std::path::Path) -> Self {
+        match self {
+            DatabaseError::DecodeFailed {
+                path: None,
+                offset,
+                source,
+            } => DatabaseError::DecodeFailed {
+                path: Some(path.to_path_buf()),
+                offset,
+                source,
+            },
+            DatabaseError::EncodeFailed { path: None, source } => DatabaseError::EncodeFailed {
+                path: Some(path.to_path_buf()),
+                source,
+            },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseError::ItemNotFound => write!(f, "item not found"),
+            DatabaseError::DupeFound => write!(f, "duplicate item found"),
+            DatabaseError::SavePathRequired => write!(f, "a save path is required"),
+            DatabaseError::IOError(e) => write!(f, "i/o error: {}", e),
+            DatabaseError::DatabaseNotFound => write!(f, "database not found"),
+            DatabaseError::BadDbName => write!(f, "could not infer database name from path"),
+            DatabaseError::NotTinyDb => write!(f, "file is not a tinydb database"),
+            DatabaseError::UnsupportedVersion(v) => {
+                write!(f, "unsupported database format version {}", v)
+            }
+            DatabaseError::ItemTypeMismatch => {
+                write!(f, "database was dumped with a different kind or schema")
+            }
+            DatabaseError::ChecksumMismatch => write!(f, "database checksum mismatch"),
+            DatabaseError::UnsupportedFormat(id) => {
+                write!(f, "unsupported database serialization format {}", id)
+            }
+            DatabaseError::UnsupportedCompression(id) => {
+                write!(f, "unsupported database compression {}", id)
+            }
+            DatabaseError::KeyRequired => write!(f, "database is encrypted and requires a key"),
+            DatabaseError::DecryptionFailed => {
+                write!(f, "could not decrypt database, the key may be wrong")
+            }
+            DatabaseError::NotEncrypted => write!(f, "database is not encrypted"),
+            DatabaseError::IndexNotFound(name) => {
+                write!(f, "no index called {:?} supports this query", name)
+            }
+            DatabaseError::UniqueViolation { constraint, .. } => {
+                write!(f, "item violates unique constraint {:?}", constraint)
+            }
+            DatabaseError::InvalidCursor => write!(f, "invalid pagination cursor"),
+            DatabaseError::QueryParse { position, message } => {
+                write!(f, "could not parse query at byte {}: {}", position, message)
+            }
+            DatabaseError::DecodeFailed {
+                path,
+                offset,
+                source,
+            } => {
+                write!(f, "could not decode database")?;
+
+                if let Some(path) = path {
+                    write!(f, " from {}", path.display())?;
+                }
+                if let Some(offset) = offset {
+                    write!(f, " at byte {}", offset)?;
+                }
+
+                write!(f, ": {}", source)
+            }
+            DatabaseError::EncodeFailed { path, source } => {
+                write!(f, "could not encode database")?;
+
+                if let Some(path) = path {
+                    write!(f, " to {}", path.display())?;
+                }
+
+                write!(f, ": {}", source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DatabaseError::IOError(e) => Some(e),
+            DatabaseError::DecodeFailed { source, .. } => Some(source.as_ref()),
+            DatabaseError::EncodeFailed { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
 }
 
 impl From<std::io::Error> for DatabaseError {
@@ -0,0 +1,361 @@
+//! Geospatial queries over items with latitude/longitude coordinates, see
+//! [crate::Database::create_geo_index].
+//!
+//! Points are kept in an R-tree as positions on a unit sphere rather than as
+//! raw coordinates, so that straight-line distances between them order items
+//! the same way as distances over the surface of the Earth do. This keeps
+//! queries correct near the poles and across the antimeridian.
+
+use crate::index::{AnyIndex, Extractor};
+use rstar::primitives::GeomWithData;
+use rstar::{RTree, AABB};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Mean radius of the Earth in metres, used for all distances.
+pub const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// A position on the Earth, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    /// Latitude from -90 (south) to 90 (north)
+    pub lat: f64,
+
+    /// Longitude from -180 (west) to 180 (east)
+    pub lon: f64,
+}
+
+impl Point {
+    /// Creates a point from its latitude and longitude in degrees.
+    pub fn new(lat: f64, lon: f64) -> Self {
+        Point { lat, lon }
+    }
+
+    /// Returns the great-circle distance to `other` in metres.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tinydb::geo::Point;
+    ///
+    /// let london = Point::new(51.5074, -0.1278);
+    /// let paris = Point::new(48.8566, 2.3522);
+    ///
+    /// assert_eq!((london.distance(&paris) / 1000.0).round(), 344.0);
+    /// ```
+    pub fn distance(&self, other: &Point) -> f64 {
+        let chord = distance_2(self.to_unit(), other.to_unit()).sqrt();
+        2.0 * (chord / 2.0).min(1.0).asin() * EARTH_RADIUS
+    }
+
+    /// Returns the position of the point on a sphere with a radius of one.
+    fn to_unit(self) -> [f64; 3] {
+        let (lat, lon) = (self.lat.to_radians(), self.lon.to_radians());
+        [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+    }
+
+    /// Returns `true` if both coordinates are finite numbers.
+    fn is_finite(&self) -> bool {
+        self.lat.is_finite() && self.lon.is_finite()
+    }
+}
+
+/// Squared straight-line distance between two positions on the unit sphere.
+fn distance_2(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a.iter().zip(&b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// Smallest and largest of `values`.
+fn span(values: impl IntoIterator<Item = f64>) -> (f64, f64) {
+    values
+        .into_iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+            (min.min(value), max.max(value))
+        })
+}
+
+/// Entry of a [GeoIndex], positioned on the unit sphere, holding on to the
+/// item shared between every index.
+type Entry<T> = GeomWithData<[f64; 3], (Point, Arc<T>)>;
+
+/// Index keeping the point extracted from each item in an R-tree.
+pub(crate) struct GeoIndex<T> {
+    extractor: Extractor<T, Point>,
+    tree: RTree<Entry<T>>,
+}
+
+impl<T: PartialEq> GeoIndex<T> {
+    /// Creates an index over the shared `items` using `extractor` to find
+    /// their points.
+    pub(crate) fn new(
+        extractor: Extractor<T, Point>,
+        items: impl IntoIterator<Item = Arc<T>>,
+    ) -> Self {
+        let entries = items
+            .into_iter()
+            .filter_map(|item| entry(extractor(&item), item))
+            .collect();
+
+        GeoIndex {
+            extractor,
+            tree: RTree::bulk_load(entries),
+        }
+    }
+}
+
+/// Creates the entry for `item` at `point`, unless the point isn't finite.
+fn entry<T>(point: Point, item: Arc<T>) -> Option<Entry<T>> {
+    if !point.is_finite() {
+        return None;
+    }
+
+    Some(GeomWithData::new(point.to_unit(), (point, item)))
+}
+
+impl<T: PartialEq> GeoIndex<T> {
+    /// Returns every item within `radius` metres of `center`, closest first.
+    pub(crate) fn within_radius(&self, center: Point, radius: f64) -> Vec<&T> {
+        if !center.is_finite() || radius.is_nan() || radius < 0.0 {
+            return vec![];
+        }
+
+        let unit = center.to_unit();
+        let chord = 2.0 * ((radius / EARTH_RADIUS).min(PI) / 2.0).sin();
+
+        let mut found: Vec<(f64, &T)> = self
+            .tree
+            .locate_within_distance(unit, chord * chord)
+            .map(|entry| (distance_2(unit, *entry.geom()), &*entry.data.1))
+            .collect();
+        found.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        found.into_iter().map(|(_, item)| item).collect()
+    }
+
+    /// Returns every item inside the box with the corners `south_west` and
+    /// `north_east`, which crosses the antimeridian if its western edge is
+    /// further east than its eastern one.
+    pub(crate) fn within_box(&self, south_west: Point, north_east: Point) -> Vec<&T> {
+        if !south_west.is_finite() || !north_east.is_finite() || south_west.lat > north_east.lat {
+            return vec![];
+        }
+
+        let (west, mut east) = (south_west.lon, north_east.lon);
+        if west > east {
+            east += 360.0;
+        }
+
+        // the sides of the box are furthest out either at its corners or
+        // where it crosses the equator or a multiple of 90 degrees longitude
+        let mut lats = vec![south_west.lat, north_east.lat];
+        if south_west.lat < 0.0 && north_east.lat > 0.0 {
+            lats.push(0.0);
+        }
+        let mut lons = vec![west, east];
+        let mut quarter = (west / 90.0).ceil() * 90.0;
+        while quarter < east {
+            lons.push(quarter);
+            quarter += 90.0;
+        }
+
+        let (cos_lat_min, cos_lat_max) = span(lats.iter().map(|lat| lat.to_radians().cos()));
+        let (cos_lon_min, cos_lon_max) = span(lons.iter().map(|lon| lon.to_radians().cos()));
+        let (sin_lon_min, sin_lon_max) = span(lons.iter().map(|lon| lon.to_radians().sin()));
+        let (z_min, z_max) = span(lats.iter().map(|lat| lat.to_radians().sin()));
+
+        let (x_min, x_max) = span(
+            [cos_lat_min, cos_lat_max]
+                .iter()
+                .flat_map(|lat| [lat * cos_lon_min, lat * cos_lon_max]),
+        );
+        let (y_min, y_max) = span(
+            [cos_lat_min, cos_lat_max]
+                .iter()
+                .flat_map(|lat| [lat * sin_lon_min, lat * sin_lon_max]),
+        );
+
+        // padded so that points on the edges aren't lost to rounding, as
+        // they are checked exactly below
+        let pad = 1e-9;
+        let envelope = AABB::from_corners(
+            [x_min - pad, y_min - pad, z_min - pad],
+            [x_max + pad, y_max + pad, z_max + pad],
+        );
+
+        let inside_lon = |lon: f64| {
+            if south_west.lon <= north_east.lon {
+                lon >= south_west.lon && lon <= north_east.lon
+            } else {
+                lon >= south_west.lon || lon <= north_east.lon
+            }
+        };
+
+        self.tree
+            .locate_in_envelope(&envelope)
+            .filter(|entry| {
+                let (point, _) = &entry.data;
+                point.lat >= south_west.lat && point.lat <= north_east.lat && inside_lon(point.lon)
+            })
+            .map(|entry| &*entry.data.1)
+            .collect()
+    }
+
+    /// Returns the `k` items closest to `point`, closest first.
+    pub(crate) fn nearest(&self, point: Point, k: usize) -> Vec<&T> {
+        if !point.is_finite() {
+            return vec![];
+        }
+
+        self.tree
+            .nearest_neighbor_iter(&point.to_unit())
+            .take(k)
+            .map(|entry| &*entry.data.1)
+            .collect()
+    }
+}
+
+impl<T> AnyIndex<T> for GeoIndex<T>
+where
+    T: PartialEq + Send + Sync + 'static,
+{
+    fn insert(&mut self, item: &Arc<T>) {
+        if let Some(entry) = entry((self.extractor)(item), item.clone()) {
+            self.tree.insert(entry);
+        }
+    }
+
+    fn remove(&mut self, item: &T) {
+        let unit = (self.extractor)(item).to_unit();
+        let found = self
+            .tree
+            .locate_all_at_point(&unit)
+            .find(|entry| *entry.data.1 == *item)
+            .cloned();
+
+        if let Some(entry) = found {
+            self.tree.remove(&entry);
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn AnyIndex<T>> {
+        Box::new(GeoIndex {
+            extractor: self.extractor.clone(),
+            tree: self.tree.clone(),
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Places as (name, latitude, longitude).
+    const PLACES: [(&str, f64, f64); 8] = [
+        ("London", 51.5074, -0.1278),
+        ("Liverpool", 53.4084, -2.9916),
+        ("Paris", 48.8566, 2.3522),
+        ("Reykjavik", 64.1466, -21.9426),
+        ("Fiji", -17.7134, 178.065),
+        ("Samoa", -13.759, -172.1046),
+        ("Svalbard", 78.2232, 15.6267),
+        ("Null Island", 0.0, 0.0),
+    ];
+
+    /// Creates an index over [PLACES], by name.
+    fn index() -> GeoIndex<&'static str> {
+        let names: Vec<&str> = PLACES.iter().map(|(name, _, _)| *name).collect();
+
+        GeoIndex::new(
+            Arc::new(|name: &&str| {
+                let (_, lat, lon) = PLACES.iter().find(|(n, _, _)| n == name).unwrap();
+                Point::new(*lat, *lon)
+            }),
+            names.into_iter().map(Arc::new),
+        )
+    }
+
+    /// Distance in kilometres between two of [PLACES].
+    fn km(from: &str, to: &str) -> f64 {
+        let point = |name| {
+            let (_, lat, lon) = PLACES.iter().find(|(n, _, _)| *n == name).unwrap();
+            Point::new(*lat, *lon)
+        };
+
+        point(from).distance(&point(to)) / 1000.0
+    }
+
+    /// Tests great-circle distances against known values.
+    #[test]
+    fn distances() {
+        assert_eq!(km("London", "London"), 0.0);
+        assert_eq!(km("London", "Liverpool").round(), 287.0);
+        assert_eq!(km("Fiji", "Samoa").round(), 1140.0);
+        assert!(
+            (Point::new(90.0, 0.0).distance(&Point::new(-90.0, 45.0)) - PI * EARTH_RADIUS).abs()
+                < 1e-6
+        );
+    }
+
+    /// Tests radius, box and nearest queries, including across the
+    /// antimeridian, as items change.
+    #[test]
+    fn geo_index() {
+        let mut index = index();
+
+        assert_eq!(
+            index.within_radius(Point::new(51.5, 0.0), 400_000.0),
+            [&"London", &"Liverpool", &"Paris"]
+        );
+        assert_eq!(
+            index.within_radius(Point::new(-15.0, 179.9), 1_000_000.0),
+            [&"Fiji", &"Samoa"]
+        );
+        assert!(index.within_radius(Point::new(0.0, 0.0), -1.0).is_empty());
+        assert_eq!(
+            index
+                .within_radius(Point::new(0.0, 0.0), f64::INFINITY)
+                .len(),
+            PLACES.len()
+        );
+
+        let mut found = index.within_box(Point::new(-30.0, 170.0), Point::new(0.0, -170.0));
+        found.sort();
+        assert_eq!(found, [&"Fiji", &"Samoa"]);
+
+        let mut found = index.within_box(Point::new(48.8566, -3.0), Point::new(70.0, 2.3522));
+        found.sort();
+        assert_eq!(found, [&"Liverpool", &"London", &"Paris"]);
+        assert_eq!(
+            index
+                .within_box(Point::new(-90.0, -180.0), Point::new(90.0, 180.0))
+                .len(),
+            PLACES.len()
+        );
+        assert!(index
+            .within_box(Point::new(10.0, 0.0), Point::new(-10.0, 1.0))
+            .is_empty());
+
+        assert_eq!(
+            index.nearest(Point::new(90.0, 0.0), 2),
+            [&"Svalbard", &"Reykjavik"]
+        );
+        assert_eq!(index.nearest(Point::new(0.0, 0.0), 0), Vec::<&&str>::new());
+
+        index.remove(&"Svalbard");
+        assert_eq!(index.nearest(Point::new(90.0, 0.0), 1), [&"Reykjavik"]);
+        assert_eq!(index.tree.size(), PLACES.len() - 1);
+
+        index.insert(&Arc::new("Svalbard"));
+        assert_eq!(index.nearest(Point::new(90.0, 0.0), 1), [&"Svalbard"]);
+        assert_eq!(
+            index.within_radius(Point::new(0.0, 0.0), 1.0),
+            [&"Null Island"]
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! The fixed header written to the start of every `.tinydb` file.
+//!
+//! This allows [crate::Database::from] to tell a TinyDB dump apart from any
+//! other file and to reject dumps written in a layout it doesn't understand or
+//! by a different [Kind] of database, rather than blindly attempting to decode
+//! them. Files without a header, which were written before headers were
+//! added, can still be read as [LEGACY_VERSION] dumps with
+//! [crate::Database::from_legacy].
+//!
+//! # Layout
+//!
+//! All integers are little-endian:
+//!
+//! | Offset | Size | Field                         |
+//! |--------|------|-------------------------------|
+//! | 0      | 6    | [MAGIC] bytes                 |
+//! | 6      | 2    | Format version                |
+//! | 8      | 2    | Flags                         |
+//! | 10     | 8    | Schema identifier             |
+//!
+//! The flags are made up of:
+//!
+//! | Bits | Field                                             |
+//! |------|---------------------------------------------------|
+//! | 0    | [FLAG_CHECKSUM]                                   |
+//! | 4-7  | [Format] identifier, see [FLAGS_FORMAT]           |
+//! | 8-11 | [Compression] identifier, see [FLAGS_COMPRESSION] |
+//! | 12   | [FLAG_ENCRYPTED]                                  |
+//! | 13   | [FLAG_KEYED]                                      |
+//! | 14   | [FLAG_TEXT_INDEXES]                               |
+//!
+//! The encoded database follows the header, and if [FLAG_CHECKSUM] is set, a
+//! 4 byte CRC32 trailer of everything before it ends the file. If
+//! [FLAG_TEXT_INDEXES] is set, the encoded database is preceded by the length
+//! of the encoded text indexes as 8 little-endian bytes and then the indexes
+//! themselves, all of which is compressed and encrypted together.
+//!
+//! The schema identifier is chosen by the user, see
+//! [crate::Database::schema], and is `0` if none was.
+
+use crate::codec::Format;
+use crate::compression::Compression;
+use crate::error::DatabaseError;
+use std::io::{Read, Write};
+
+/// Magic bytes which every `.tinydb` file starts with.
+pub const MAGIC: [u8; 6] = *b"TINYDB";
+
+/// Current format version written by [crate::Database::dump_db], bumped
+/// whenever the layout of a dump changes.
+pub const FORMAT_VERSION: u16 = 2;
+
+/// Version given to files without a header, which are a bincode encoded
+/// database with nothing before or after it.
+pub const LEGACY_VERSION: u16 = 0;
+
+/// Total length of an encoded [Header] in bytes.
+pub const HEADER_LEN: usize = 18;
+
+/// Flag set when the file ends with a CRC32 checksum trailer.
+pub const FLAG_CHECKSUM: u16 = 1;
+
+/// Mask of the flags holding the identifier of the [Format] used to encode
+/// the database.
+pub const FLAGS_FORMAT: u16 = 0x00f0;
+
+/// Mask of the flags holding the identifier of the [Compression] applied to
+/// the encoded database.
+pub const FLAGS_COMPRESSION: u16 = 0x0f00;
+
+/// Flag set when the (possibly compressed) encoded database is encrypted, in
+/// which case it is prefixed with the nonce used.
+pub const FLAG_ENCRYPTED: u16 = 0x1000;
+
+/// Flag set when the file was dumped by a [Kind::Keyed] database.
+pub const FLAG_KEYED: u16 = 0x2000;
+
+/// Flag set when the encoded database is preceded by the text indexes
+/// persisted with it, see [crate::Database::persist_text_index].
+pub const FLAG_TEXT_INDEXES: u16 = 0x4000;
+
+/// Kind of database a file was dumped by, which it can only be loaded as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// A [crate::Database] of bare items
+    Plain,
+
+    /// A [crate::keyed::KeyedDatabase] of items stored under keys
+    Keyed,
+}
+
+/// Decoded header of a `.tinydb` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    /// Format version the file was written with, see [FORMAT_VERSION].
+    pub version: u16,
+
+    /// Feature flags for the rest of the file, such as [FLAG_CHECKSUM].
+    pub flags: u16,
+
+    /// Schema identifier of the items stored, see [crate::Database::schema].
+    pub schema: u64,
+}
+
+impl Header {
+    /// Creates a header at the current [FORMAT_VERSION] for a `kind` of
+    /// database with the given `schema`, encoded with `format` and then
+    /// compressed with `compression`.
+    pub(crate) fn new(kind: Kind, format: Format, compression: Compression, schema: u64) -> Self {
+        let mut flags =
+            FLAG_CHECKSUM | ((format.id() as u16) << 4) | ((compression.id() as u16) << 8);
+        if kind == Kind::Keyed {
+            flags |= FLAG_KEYED;
+        }
+
+        Header {
+            version: FORMAT_VERSION,
+            flags,
+            schema,
+        }
+    }
+
+    /// Creates the header describing a file without one, see
+    /// [LEGACY_VERSION].
+    pub(crate) fn legacy() -> Self {
+        Header {
+            version: LEGACY_VERSION,
+            flags: 0,
+            schema: 0,
+        }
+    }
+
+    /// Encodes the header into its on-disk layout.
+    pub(crate) fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+
+        bytes[..6].copy_from_slice(&MAGIC);
+        bytes[6..8].copy_from_slice(&self.version.to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.flags.to_le_bytes());
+        bytes[10..].copy_from_slice(&self.schema.to_le_bytes());
+
+        bytes
+    }
+
+    /// Writes the encoded header to `writer`.
+    pub(crate) fn write<W: Write>(self, writer: &mut W) -> Result<(), DatabaseError> {
+        writer.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Reads a header from `reader`, checking the magic bytes and version.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::NotTinyDb] if the magic bytes are missing
+    /// or [DatabaseError::UnsupportedVersion] if the version is unknown.
+    pub(crate) fn read<R: Read>(reader: &mut R) -> Result<Self, DatabaseError> {
+        let mut bytes = [0; HEADER_LEN];
+
+        if reader.read_exact(&mut bytes).is_err() || bytes[..6] != MAGIC {
+            return Err(DatabaseError::NotTinyDb);
+        }
+
+        let header = Header {
+            version: u16::from_le_bytes([bytes[6], bytes[7]]),
+            flags: u16::from_le_bytes([bytes[8], bytes[9]]),
+            schema: u64::from_le_bytes([
+                bytes[10], bytes[11], bytes[12], bytes[13], bytes[14], bytes[15], bytes[16],
+                bytes[17],
+            ]),
+        };
+
+        if header.version != FORMAT_VERSION {
+            return Err(DatabaseError::UnsupportedVersion(header.version));
+        }
+
+        Ok(header)
+    }
+
+    /// Returns `true` if the file has a checksum trailer.
+    pub fn has_checksum(&self) -> bool {
+        self.flags & FLAG_CHECKSUM != 0
+    }
+
+    /// Returns `true` if the encoded database is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & FLAG_ENCRYPTED != 0
+    }
+
+    /// Returns `true` if text indexes are persisted alongside the database.
+    pub fn has_text_indexes(&self) -> bool {
+        self.flags & FLAG_TEXT_INDEXES != 0
+    }
+
+    /// Returns the kind of database the file was dumped by.
+    pub fn kind(&self) -> Kind {
+        if self.flags & FLAG_KEYED != 0 {
+            Kind::Keyed
+        } else {
+            Kind::Plain
+        }
+    }
+
+    /// Checks that the file was dumped by the `expected` kind of database.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::ItemTypeMismatch] if it was dumped by
+    /// another kind.
+    pub(crate) fn check_kind(&self, expected: Kind) -> Result<(), DatabaseError> {
+        if self.kind() == expected {
+            Ok(())
+        } else {
+            Err(DatabaseError::ItemTypeMismatch)
+        }
+    }
+
+    /// Returns the format the database was encoded with.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::UnsupportedFormat] if the format is unknown
+    /// or its feature isn't enabled.
+    pub fn format(&self) -> Result<Format, DatabaseError> {
+        Format::from_id(((self.flags & FLAGS_FORMAT) >> 4) as u8)
+    }
+
+    /// Returns the compression applied to the encoded database.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::UnsupportedCompression] if the compression is
+    /// unknown or its feature isn't enabled.
+    pub fn compression(&self) -> Result<Compression, DatabaseError> {
+        Compression::from_id(((self.flags & FLAGS_COMPRESSION) >> 8) as u8)
+    }
+
+    /// Returns the schema identifier the file was written with, or `0` if it
+    /// didn't declare one or was written before headers were added.
+    pub fn schema(&self) -> u64 {
+        self.schema
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that a header survives being written and read back.
+    #[test]
+    fn roundtrip() -> Result<(), DatabaseError> {
+        let header = Header::new(Kind::Keyed, Format::Bincode, Compression::None, 42);
+        let mut bytes = vec![];
+
+        header.write(&mut bytes)?;
+
+        assert_eq!(bytes.len(), HEADER_LEN);
+        assert_eq!(Header::read(&mut &bytes[..])?, header);
+        assert_eq!(header.schema(), 42);
+        assert_eq!(header.kind(), Kind::Keyed);
+        assert!(matches!(
+            header.check_kind(Kind::Plain),
+            Err(DatabaseError::ItemTypeMismatch)
+        ));
+        assert_eq!(header.format()?, Format::Bincode);
+        assert_eq!(header.compression()?, Compression::None);
+
+        Ok(())
+    }
+
+    /// Tests that garbage or truncated input is reported as not being a
+    /// TinyDB file.
+    #[test]
+    fn bad_magic() {
+        let garbage = b"definitely not a tinydb file";
+
+        assert!(matches!(
+            Header::read(&mut &garbage[..]),
+            Err(DatabaseError::NotTinyDb)
+        ));
+        assert!(matches!(
+            Header::read(&mut &MAGIC[..]),
+            Err(DatabaseError::NotTinyDb)
+        ));
+    }
+
+    /// Tests that every version other than the current one is rejected.
+    #[test]
+    fn versions() {
+        let mut header = Header::new(Kind::Plain, Format::Bincode, Compression::None, 0x5eed);
+
+        for version in [LEGACY_VERSION, 1, FORMAT_VERSION + 1] {
+            header.version = version;
+
+            assert!(matches!(
+                Header::read(&mut &header.to_bytes()[..]),
+                Err(DatabaseError::UnsupportedVersion(v)) if v == version
+            ));
+        }
+    }
+}
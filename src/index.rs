@@ -0,0 +1,894 @@
+//! Secondary indexes mapping keys extracted from items to the items themselves,
+//! see [crate::Database::create_index],
+//! [crate::Database::create_ordered_index] and
+//! [crate::Database::create_prefix_index], as well as unique constraints which
+//! are kept in the same way, see [crate::Database::create_unique_constraint].
+//!
+//! Items are stored inside of a [HashSet] which indexes can't point into, so
+//! each indexed item is cloned once into an [Arc] shared by every index of the
+//! database, see [Indexes::share].
+
+use crate::error::DatabaseError;
+use crate::text;
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+use std::ops::RangeBounds;
+use std::sync::Arc;
+
+/// Extracts the key an item is indexed by.
+pub(crate) type Extractor<T, K> = Arc<dyn Fn(&T) -> K + Send + Sync>;
+
+/// An index kept up to date as items are added and removed, with its key type
+/// erased so indexes with different keys can be stored together.
+pub(crate) trait AnyIndex<T>: Send + Sync {
+    /// Indexes a newly added item, holding on to the shared `item` rather than
+    /// a clone of it.
+    fn insert(&mut self, item: &Arc<T>);
+
+    /// Stops indexing a removed item.
+    fn remove(&mut self, item: &T);
+
+    /// Clones the whole index.
+    fn box_clone(&self) -> Box<dyn AnyIndex<T>>;
+
+    /// Returns the index as [Any] so it can be downcast to its concrete type.
+    fn as_any(&self) -> &dyn Any;
+
+    /// Returns the index as [Ordered] if it keeps items in key order.
+    fn as_ordered(&self) -> Option<&dyn Ordered<T>> {
+        None
+    }
+
+    /// Checks that `item` can be added without breaking a unique constraint,
+    /// ignoring `replacing` which it is about to replace.
+    fn check(&self, _item: &T, _replacing: Option<&T>) -> Result<(), DatabaseError> {
+        Ok(())
+    }
+
+    /// Sets if the index is dumped alongside the database, returning `false`
+    /// if it can't be.
+    fn set_persist(&mut self, _persist: bool) -> bool {
+        false
+    }
+
+    /// Takes a snapshot of the index to dump if it is persisted.
+    fn snapshot(&self) -> Option<text::Snapshot> {
+        None
+    }
+}
+
+/// An index which keeps items ordered by key, allowing them to be walked in
+/// order without knowing the type of the key.
+pub(crate) trait Ordered<T> {
+    /// Walks every item from the smallest key to the largest.
+    fn ascending(&self) -> Box<dyn Iterator<Item = &T> + '_>;
+
+    /// Walks every item from the largest key to the smallest.
+    fn descending(&self) -> Box<dyn Iterator<Item = &T> + '_>;
+}
+
+/// Index mapping each key to every item it was extracted from.
+pub(crate) struct HashIndex<K, T> {
+    extractor: Extractor<T, K>,
+    map: HashMap<K, HashSet<Arc<T>>>,
+}
+
+impl<K: Hash + Eq, T: Hash + Eq> HashIndex<K, T> {
+    /// Creates an index over the shared `items` using `extractor` to find
+    /// their keys.
+    pub(crate) fn new(extractor: Extractor<T, K>, items: impl IntoIterator<Item = Arc<T>>) -> Self {
+        let mut map: HashMap<K, HashSet<Arc<T>>> = HashMap::new();
+
+        for item in items {
+            map.entry(extractor(&item)).or_default().insert(item);
+        }
+
+        HashIndex { extractor, map }
+    }
+}
+
+impl<K: Hash + Eq, T> HashIndex<K, T> {
+    /// Returns every item with the given key.
+    pub(crate) fn get(&self, key: &K) -> impl Iterator<Item = &T> {
+        self.map.get(key).into_iter().flatten().map(|item| &**item)
+    }
+}
+
+/// Items grouped by key, either by an existing [HashIndex] or by scanning
+/// every item, used to look up the right side of joins.
+pub(crate) enum Lookup<'a, K, T> {
+    Indexed(&'a HashIndex<K, T>),
+    Scanned(HashMap<K, Vec<&'a T>>),
+}
+
+impl<'a, K: Hash + Eq, T> Lookup<'a, K, T> {
+    /// Groups `items` by `extractor`, unless `index` already does.
+    pub(crate) fn new(
+        index: Option<&'a HashIndex<K, T>>,
+        items: impl IntoIterator<Item = &'a T>,
+        extractor: impl Fn(&T) -> K,
+    ) -> Self {
+        if let Some(index) = index {
+            return Lookup::Indexed(index);
+        }
+
+        let mut map: HashMap<K, Vec<&T>> = HashMap::new();
+        for item in items {
+            map.entry(extractor(item)).or_default().push(item);
+        }
+
+        Lookup::Scanned(map)
+    }
+
+    /// Returns every item with the given key.
+    pub(crate) fn get(&self, key: &K) -> Vec<&'a T> {
+        match self {
+            Lookup::Indexed(index) => {
+                let index: &'a HashIndex<K, T> = index;
+                index.get(key).collect()
+            }
+            Lookup::Scanned(map) => map.get(key).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+impl<K, T> AnyIndex<T> for HashIndex<K, T>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    T: Hash + Eq + Send + Sync + 'static,
+{
+    fn insert(&mut self, item: &Arc<T>) {
+        self.map
+            .entry((self.extractor)(item))
+            .or_default()
+            .insert(item.clone());
+    }
+
+    fn remove(&mut self, item: &T) {
+        let key = (self.extractor)(item);
+
+        if let Some(items) = self.map.get_mut(&key) {
+            items.remove(item);
+
+            if items.is_empty() {
+                self.map.remove(&key);
+            }
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn AnyIndex<T>> {
+        Box::new(HashIndex {
+            extractor: self.extractor.clone(),
+            map: self.map.clone(),
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Index keeping each key in order alongside every item it was extracted from.
+/// Items sharing a key are kept in no particular order.
+pub(crate) struct OrderedIndex<K, T> {
+    extractor: Extractor<T, K>,
+    map: BTreeMap<K, HashSet<Arc<T>>>,
+}
+
+impl<K: Ord, T: Hash + Eq> OrderedIndex<K, T> {
+    /// Creates an index over the shared `items` using `extractor` to find
+    /// their keys.
+    pub(crate) fn new(extractor: Extractor<T, K>, items: impl IntoIterator<Item = Arc<T>>) -> Self {
+        let mut map: BTreeMap<K, HashSet<Arc<T>>> = BTreeMap::new();
+
+        for item in items {
+            map.entry(extractor(&item)).or_default().insert(item);
+        }
+
+        OrderedIndex { extractor, map }
+    }
+}
+
+impl<K: Ord, T> OrderedIndex<K, T> {
+    /// Returns every item with a key inside of `range`, in key order.
+    pub(crate) fn range<R: RangeBounds<K>>(&self, range: R) -> impl Iterator<Item = &T> {
+        self.map
+            .range(range)
+            .flat_map(|(_, items)| items)
+            .map(|item| &**item)
+    }
+
+    /// Returns every key inside of `range` with the items sharing it, in key
+    /// order.
+    pub(crate) fn groups<R: RangeBounds<K>>(
+        &self,
+        range: R,
+    ) -> impl Iterator<Item = (&K, &HashSet<Arc<T>>)> {
+        self.map.range(range)
+    }
+}
+
+impl<K, T> AnyIndex<T> for OrderedIndex<K, T>
+where
+    K: Ord + Clone + Send + Sync + 'static,
+    T: Hash + Eq + Send + Sync + 'static,
+{
+    fn insert(&mut self, item: &Arc<T>) {
+        self.map
+            .entry((self.extractor)(item))
+            .or_default()
+            .insert(item.clone());
+    }
+
+    fn remove(&mut self, item: &T) {
+        let key = (self.extractor)(item);
+
+        if let Some(items) = self.map.get_mut(&key) {
+            items.remove(item);
+
+            if items.is_empty() {
+                self.map.remove(&key);
+            }
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn AnyIndex<T>> {
+        Box::new(OrderedIndex {
+            extractor: self.extractor.clone(),
+            map: self.map.clone(),
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_ordered(&self) -> Option<&dyn Ordered<T>> {
+        Some(self)
+    }
+}
+
+impl<K, T> Ordered<T> for OrderedIndex<K, T> {
+    fn ascending(&self) -> Box<dyn Iterator<Item = &T> + '_> {
+        Box::new(self.map.values().flatten().map(|item| &**item))
+    }
+
+    fn descending(&self) -> Box<dyn Iterator<Item = &T> + '_> {
+        Box::new(self.map.values().rev().flatten().map(|item| &**item))
+    }
+}
+
+/// Unique constraint mapping each key to the single item it was extracted from.
+pub(crate) struct UniqueIndex<K, T> {
+    name: String,
+    extractor: Extractor<T, K>,
+    map: HashMap<K, Arc<T>>,
+}
+
+impl<K, T> UniqueIndex<K, T>
+where
+    K: Hash + Eq,
+    T: Eq + Clone + Send + Sync + 'static,
+{
+    /// Creates a constraint called `name` over the shared `items` using
+    /// `extractor` to find their keys.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::UniqueViolation] if two of the `items`
+    /// already share a key.
+    pub(crate) fn new(
+        name: String,
+        extractor: Extractor<T, K>,
+        items: impl IntoIterator<Item = Arc<T>>,
+    ) -> Result<Self, DatabaseError> {
+        let mut index = UniqueIndex {
+            name,
+            extractor,
+            map: HashMap::new(),
+        };
+
+        for item in items {
+            if let Some(existing) = index.map.insert((index.extractor)(&item), item) {
+                return Err(index.violation(&existing));
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Creates the error for an item conflicting with `existing`.
+    fn violation(&self, existing: &T) -> DatabaseError {
+        DatabaseError::UniqueViolation {
+            constraint: self.name.clone(),
+            existing: Box::new(existing.clone()),
+        }
+    }
+}
+
+impl<K, T> AnyIndex<T> for UniqueIndex<K, T>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    T: Eq + Clone + Send + Sync + 'static,
+{
+    fn insert(&mut self, item: &Arc<T>) {
+        self.map.insert((self.extractor)(item), item.clone());
+    }
+
+    fn remove(&mut self, item: &T) {
+        let key = (self.extractor)(item);
+
+        if self.map.get(&key).map(|existing| &**existing) == Some(item) {
+            self.map.remove(&key);
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn AnyIndex<T>> {
+        Box::new(UniqueIndex {
+            name: self.name.clone(),
+            extractor: self.extractor.clone(),
+            map: self.map.clone(),
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn check(&self, item: &T, replacing: Option<&T>) -> Result<(), DatabaseError> {
+        match self
+            .map
+            .get(&(self.extractor)(item))
+            .map(|existing| &**existing)
+        {
+            Some(existing) if existing != item && Some(existing) != replacing => {
+                Err(self.violation(existing))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Node of a [TrieIndex], holding the items whose key ends at it.
+struct TrieNode<T> {
+    children: BTreeMap<char, TrieNode<T>>,
+    items: HashSet<Arc<T>>,
+}
+
+impl<T> Clone for TrieNode<T> {
+    fn clone(&self) -> Self {
+        TrieNode {
+            children: self.children.clone(),
+            items: self.items.clone(),
+        }
+    }
+}
+
+impl<T> TrieNode<T> {
+    /// Creates a node without any children or items.
+    fn new() -> Self {
+        TrieNode {
+            children: BTreeMap::new(),
+            items: HashSet::new(),
+        }
+    }
+
+    /// Collects the items of this node and every node below it, in key order.
+    fn collect<'a>(&'a self, found: &mut Vec<&'a T>) {
+        found.extend(self.items.iter().map(|item| &**item));
+
+        for child in self.children.values() {
+            child.collect(found);
+        }
+    }
+
+    /// Collects the items of every node below this one whose key is at most
+    /// `max` edits from `term`, given the edit distances from `term` to this
+    /// node's key and each of its prefixes as `row`.
+    fn fuzzy<'a>(
+        &'a self,
+        term: &[char],
+        row: &[usize],
+        max: usize,
+        found: &mut Vec<(usize, &'a T)>,
+    ) {
+        for (c, child) in &self.children {
+            let mut next = Vec::with_capacity(row.len());
+            next.push(row[0] + 1);
+
+            for (i, t) in term.iter().enumerate() {
+                let substitute = row[i] + if t == c { 0 } else { 1 };
+                next.push(substitute.min(row[i + 1] + 1).min(next[i] + 1));
+            }
+
+            let distance = next[term.len()];
+            if distance <= max {
+                found.extend(child.items.iter().map(|item| (distance, &**item)));
+            }
+
+            // no key below the child can be any closer than its closest prefix
+            if next.iter().any(|prefix| *prefix <= max) {
+                child.fuzzy(term, &next, max, found);
+            }
+        }
+    }
+}
+
+impl<T: Hash + Eq> TrieNode<T> {
+    /// Removes `item` from the node at `key` below this one, removing any
+    /// nodes left empty. Returns `true` if this node is now empty itself.
+    fn remove(&mut self, key: &[char], item: &T) -> bool {
+        match key.split_first() {
+            Some((c, rest)) => {
+                if let Some(child) = self.children.get_mut(c) {
+                    if child.remove(rest, item) {
+                        self.children.remove(c);
+                    }
+                }
+            }
+            None => {
+                self.items.remove(item);
+            }
+        }
+
+        self.items.is_empty() && self.children.is_empty()
+    }
+}
+
+/// Index keeping string keys in a trie, so that items can be found by a prefix
+/// of their key or by keys within a number of edits of a term.
+pub(crate) struct TrieIndex<T> {
+    extractor: Extractor<T, String>,
+    root: TrieNode<T>,
+}
+
+impl<T: Hash + Eq> TrieIndex<T> {
+    /// Creates an index over the shared `items` using `extractor` to find
+    /// their keys.
+    pub(crate) fn new(
+        extractor: Extractor<T, String>,
+        items: impl IntoIterator<Item = Arc<T>>,
+    ) -> Self {
+        let mut index = TrieIndex {
+            extractor,
+            root: TrieNode::new(),
+        };
+
+        for item in items {
+            index.add(item);
+        }
+
+        index
+    }
+
+    /// Indexes `item` under its key.
+    fn add(&mut self, item: Arc<T>) {
+        let mut node = &mut self.root;
+
+        for c in (self.extractor)(&item).chars() {
+            node = node.children.entry(c).or_insert_with(TrieNode::new);
+        }
+
+        node.items.insert(item);
+    }
+}
+
+impl<T> TrieIndex<T> {
+    /// Returns every item with a key starting with `prefix`, in key order.
+    pub(crate) fn prefix(&self, prefix: &str) -> Vec<&T> {
+        let mut node = &self.root;
+
+        for c in prefix.chars() {
+            node = match node.children.get(&c) {
+                Some(child) => child,
+                None => return vec![],
+            };
+        }
+
+        let mut found = vec![];
+        node.collect(&mut found);
+        found
+    }
+
+    /// Returns every item with a key at most `max` single character
+    /// insertions, deletions or substitutions away from `term`, closest first
+    /// and then in key order.
+    pub(crate) fn fuzzy(&self, term: &str, max: usize) -> Vec<&T> {
+        let term: Vec<char> = term.chars().collect();
+        let row: Vec<usize> = (0..=term.len()).collect();
+
+        let mut found = vec![];
+        if term.len() <= max {
+            found.extend(self.root.items.iter().map(|item| (term.len(), &**item)));
+        }
+        self.root.fuzzy(&term, &row, max, &mut found);
+
+        found.sort_by_key(|(distance, _)| *distance);
+        found.into_iter().map(|(_, item)| item).collect()
+    }
+}
+
+impl<T> AnyIndex<T> for TrieIndex<T>
+where
+    T: Hash + Eq + Send + Sync + 'static,
+{
+    fn insert(&mut self, item: &Arc<T>) {
+        self.add(item.clone());
+    }
+
+    fn remove(&mut self, item: &T) {
+        let key: Vec<char> = (self.extractor)(item).chars().collect();
+        self.root.remove(&key, item);
+    }
+
+    fn box_clone(&self) -> Box<dyn AnyIndex<T>> {
+        Box::new(TrieIndex {
+            extractor: self.extractor.clone(),
+            root: self.root.clone(),
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Every index of a database, by name.
+pub(crate) struct Indexes<T> {
+    by_name: HashMap<String, Box<dyn AnyIndex<T>>>,
+
+    /// The single shared copy of every item which all indexes hold on to,
+    /// which is only kept whilst there are indexes
+    shared: HashSet<Arc<T>>,
+
+    /// Makes the shared copy of a newly added item, set once the first index
+    /// is created as only indexable items have to be [Clone]
+    share_item: Option<fn(&T) -> Arc<T>>,
+
+    /// Persisted text indexes loaded from a dump which haven't been created
+    /// again yet, which are dumped again as they are until then
+    snapshots: text::Snapshots,
+}
+
+impl<T: Hash + Eq + Clone> Indexes<T> {
+    /// Returns the shared copy of each of `items`, the database's items, to
+    /// build a new index over. Copies are made the first time an index is
+    /// created and are kept up to date from then on.
+    pub(crate) fn share<'a>(&mut self, items: impl IntoIterator<Item = &'a T>) -> Vec<Arc<T>>
+    where
+        T: 'a,
+    {
+        if self.by_name.is_empty() {
+            self.shared = items.into_iter().cloned().map(Arc::new).collect();
+            self.share_item = Some(|item| Arc::new(item.clone()));
+        }
+
+        self.shared.iter().cloned().collect()
+    }
+}
+
+impl<T: Hash + Eq> Indexes<T> {
+    /// Indexes a newly added item in every index.
+    pub(crate) fn insert_item(&mut self, item: &T) {
+        let shared = match self.share_item {
+            Some(share_item) if !self.by_name.is_empty() => share_item(item),
+            _ => return,
+        };
+
+        for index in self.by_name.values_mut() {
+            index.insert(&shared);
+        }
+        self.shared.insert(shared);
+    }
+
+    /// Stops indexing a removed item in every index.
+    pub(crate) fn remove_item(&mut self, item: &T) {
+        for index in self.by_name.values_mut() {
+            index.remove(item);
+        }
+        self.shared.remove(item);
+    }
+}
+
+impl<T> Indexes<T> {
+    /// Adds an index, replacing any existing index or snapshot with the same
+    /// name. It should be built over the items returned by [Indexes::share].
+    pub(crate) fn insert_index(&mut self, name: String, index: Box<dyn AnyIndex<T>>) {
+        self.snapshots.remove(&name);
+        self.by_name.insert(name, index);
+    }
+
+    /// Removes an index or snapshot, returning `true` if it existed.
+    pub(crate) fn remove_index(&mut self, name: &str) -> bool {
+        let removed = self.by_name.remove(name).is_some();
+        let persisted = self.snapshots.remove(name).is_some();
+        if self.by_name.is_empty() {
+            self.shared.clear();
+        }
+
+        removed || persisted
+    }
+
+    /// Sets if the index with the given name is dumped alongside the
+    /// database, returning `false` if there is no such index which can be.
+    pub(crate) fn set_persist(&mut self, name: &str, persist: bool) -> bool {
+        match self.by_name.get_mut(name) {
+            Some(index) => index.set_persist(persist),
+            None => false,
+        }
+    }
+
+    /// Takes the snapshot of the text index with the given name loaded from a
+    /// dump, to create it again from.
+    pub(crate) fn take_snapshot(&mut self, name: &str) -> Option<text::Snapshot> {
+        self.snapshots.remove(name)
+    }
+
+    /// Returns snapshots of every persisted index to dump, including those
+    /// loaded which haven't been created again.
+    pub(crate) fn snapshots(&self) -> text::Snapshots {
+        let mut snapshots = self.snapshots.clone();
+        for (name, index) in self.by_name.iter() {
+            if let Some(snapshot) = index.snapshot() {
+                snapshots.insert(name.clone(), snapshot);
+            }
+        }
+
+        snapshots
+    }
+
+    /// Keeps the `snapshots` of persisted indexes loaded from a dump until
+    /// they are created again.
+    pub(crate) fn set_snapshots(&mut self, snapshots: text::Snapshots) {
+        self.snapshots = snapshots;
+    }
+
+    /// Finds the index with the given name if it is of type `I`.
+    pub(crate) fn get<I: 'static>(&self, name: &str) -> Option<&I> {
+        self.by_name.get(name)?.as_any().downcast_ref()
+    }
+
+    /// Finds the index with the given name if it keeps items in key order.
+    pub(crate) fn get_ordered(&self, name: &str) -> Option<&dyn Ordered<T>> {
+        self.by_name.get(name)?.as_ordered()
+    }
+
+    /// Checks that `item` can be added, replacing `replacing` if given, without
+    /// breaking any unique constraint.
+    pub(crate) fn check(&self, item: &T, replacing: Option<&T>) -> Result<(), DatabaseError> {
+        self.by_name
+            .values()
+            .try_for_each(|index| index.check(item, replacing))
+    }
+}
+
+impl<T> Default for Indexes<T> {
+    fn default() -> Self {
+        Indexes {
+            by_name: HashMap::new(),
+            shared: HashSet::new(),
+            share_item: None,
+            snapshots: text::Snapshots::new(),
+        }
+    }
+}
+
+impl<T> Clone for Indexes<T> {
+    fn clone(&self) -> Self {
+        Indexes {
+            by_name: self
+                .by_name
+                .iter()
+                .map(|(name, index)| (name.clone(), index.box_clone()))
+                .collect(),
+            shared: self.shared.clone(),
+            share_item: self.share_item,
+            snapshots: self.snapshots.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Indexes<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.by_name.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that an index follows items being added and removed, sharing
+    /// each item with the other indexes.
+    #[test]
+    fn hash_index() {
+        let mut indexes = Indexes::default();
+        let words = ["moon", "mars", "io"];
+        let items = indexes.share(&words);
+        indexes.insert_index(
+            String::from("first"),
+            Box::new(HashIndex::new(
+                Arc::new(|word: &&str| word.chars().next()),
+                items,
+            )),
+        );
+        let items = indexes.share(&words);
+        indexes.insert_index(
+            String::from("len"),
+            Box::new(HashIndex::new(Arc::new(|word: &&str| word.len()), items)),
+        );
+
+        let first = |indexes: &Indexes<&str>, key| {
+            let mut found: Vec<&str> = indexes
+                .get::<HashIndex<Option<char>, &str>>("first")
+                .unwrap()
+                .get(&Some(key))
+                .copied()
+                .collect();
+            found.sort();
+            found
+        };
+
+        assert_eq!(first(&indexes, 'm'), ["mars", "moon"]);
+
+        indexes.remove_item(&"mars");
+        indexes.insert_item(&"europa");
+
+        assert_eq!(first(&indexes, 'm'), ["moon"]);
+        assert_eq!(first(&indexes, 'e'), ["europa"]);
+        assert!(indexes.get::<HashIndex<char, &str>>("first").is_none());
+
+        let by_first = indexes.get::<HashIndex<Option<char>, &str>>("first");
+        let by_len = indexes.get::<HashIndex<usize, &str>>("len");
+        for (key, len) in [('m', 4), ('e', 6)] {
+            let a = by_first.unwrap().get(&Some(key)).next().unwrap();
+            let b = by_len.unwrap().get(&len).find(|word| word.starts_with(key));
+            assert!(std::ptr::eq(a, b.unwrap()));
+        }
+        assert_eq!(indexes.shared.len(), 3);
+
+        assert!(indexes.remove_index("first"));
+        assert!(indexes.remove_index("len"));
+        assert!(indexes.shared.is_empty());
+    }
+
+    /// Tests that an ordered index walks items in key order and answers range
+    /// queries as items change.
+    #[test]
+    fn ordered_index() {
+        let mut indexes = Indexes::default();
+        let numbers = [5, 1, 9, 3];
+        let items = indexes.share(&numbers);
+        indexes.insert_index(
+            String::from("self"),
+            Box::new(OrderedIndex::new(Arc::new(|n: &i32| *n), items)),
+        );
+
+        indexes.insert_item(&7);
+        indexes.remove_item(&1);
+
+        let ordered = indexes.get_ordered("self").unwrap();
+        assert_eq!(
+            ordered.ascending().copied().collect::<Vec<_>>(),
+            [3, 5, 7, 9]
+        );
+        assert_eq!(
+            ordered.descending().copied().collect::<Vec<_>>(),
+            [9, 7, 5, 3]
+        );
+
+        let index = indexes.get::<OrderedIndex<i32, i32>>("self").unwrap();
+        assert_eq!(index.range(4..=7).copied().collect::<Vec<_>>(), [5, 7]);
+        assert_eq!(index.range(..4).copied().collect::<Vec<_>>(), [3]);
+    }
+
+    /// Tests prefix and fuzzy lookups as items change, checking fuzzy lookups
+    /// against edit distances worked out directly.
+    #[test]
+    fn trie_index() {
+        fn distance(a: &str, b: &str) -> usize {
+            let b: Vec<char> = b.chars().collect();
+            let mut row: Vec<usize> = (0..=b.len()).collect();
+
+            for (i, x) in a.chars().enumerate() {
+                let mut next = vec![i + 1];
+                for (j, y) in b.iter().enumerate() {
+                    let cost = if x == *y { 0 } else { 1 };
+                    next.push((row[j] + cost).min(row[j + 1] + 1).min(next[j] + 1));
+                }
+                row = next;
+            }
+
+            row[b.len()]
+        }
+
+        let mut indexes = Indexes::default();
+        let words = [
+            "",
+            "star",
+            "starbug",
+            "stardrive",
+            "red",
+            "dwarf",
+            "rimmer",
+            "ßtar",
+        ];
+        let items = indexes.share(&words);
+        indexes.insert_index(
+            String::from("word"),
+            Box::new(TrieIndex::new(
+                Arc::new(|word: &&str| word.to_string()),
+                items,
+            )),
+        );
+
+        indexes.insert_item(&"starburst");
+        indexes.remove_item(&"stardrive");
+        indexes.remove_item(&"rimmer");
+        indexes.remove_item(&"never added");
+
+        let index = indexes.get::<TrieIndex<&str>>("word").unwrap();
+        let remaining = ["", "star", "starbug", "red", "dwarf", "ßtar", "starburst"];
+
+        assert_eq!(index.prefix("starb"), [&"starbug", &"starburst"]);
+        assert_eq!(index.prefix("star").len(), 3);
+        assert_eq!(index.prefix("").len(), remaining.len());
+        assert!(index.prefix("rim").is_empty());
+        assert_eq!(index.fuzzy("stab", 1), [&"star"]);
+        assert_eq!(index.fuzzy("tar", 1), [&"star", &"ßtar"]);
+
+        for term in ["", "star", "sta", "dwraf", "starbugs", "ß"] {
+            for max in 0..4 {
+                let mut expected: Vec<&&str> = remaining
+                    .iter()
+                    .filter(|word| distance(term, word) <= max)
+                    .collect();
+                let mut found = index.fuzzy(term, max);
+                expected.sort();
+                found.sort();
+
+                assert_eq!(found, expected, "{} within {}", term, max);
+            }
+        }
+    }
+
+    /// Tests that a unique constraint rejects items sharing a key with a
+    /// different item, other than the one being replaced.
+    #[test]
+    fn unique_index() {
+        let first = |word: &&str| word.chars().next();
+        let mut indexes = Indexes::default();
+        let items = indexes.share(&["moon", "io"]);
+        let index = UniqueIndex::new(String::from("first"), Arc::new(first), items);
+        indexes.insert_index(String::from("first"), Box::new(index.unwrap()));
+
+        assert!(indexes.check(&"europa", None).is_ok());
+        assert!(indexes.check(&"moon", None).is_ok());
+        assert!(indexes.check(&"mars", Some(&"moon")).is_ok());
+
+        match indexes.check(&"mars", None) {
+            Err(DatabaseError::UniqueViolation {
+                constraint,
+                existing,
+            }) => {
+                assert_eq!(constraint, "first");
+                assert_eq!(existing.downcast_ref::<&str>(), Some(&"moon"));
+            }
+            other => panic!("expected a violation, got {:?}", other),
+        }
+
+        indexes.remove_item(&"moon");
+        assert!(indexes.check(&"mars", None).is_ok());
+
+        assert!(matches!(
+            UniqueIndex::new(
+                String::from("first"),
+                Arc::new(first),
+                Indexes::default().share(&["moon", "mars"])
+            ),
+            Err(DatabaseError::UniqueViolation { .. })
+        ));
+    }
+}
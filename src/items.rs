@@ -0,0 +1,125 @@
+//! Decoding of the items inside of an encoded [crate::Database] one at a time,
+//! used to process dumps without collecting their items into memory.
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, Deserializer, MapAccess, SeqAccess};
+use serde::Deserialize;
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+
+/// Fields of [crate::Database] which are encoded, in order.
+const FIELDS: &[&str] = &["label", "save_path", "strict_dupes", "items"];
+
+/// Decodes an encoded [crate::Database], passing each of its items to a
+/// callback as soon as it is decoded and skipping everything else.
+pub(crate) struct ItemsSeed<T, F> {
+    f: F,
+    item: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned, F: FnMut(T)> ItemsSeed<T, F> {
+    /// Creates a seed passing each item to `f`.
+    pub(crate) fn new(f: F) -> Self {
+        ItemsSeed {
+            f,
+            item: PhantomData,
+        }
+    }
+
+    /// Returns the callback items are passed to.
+    #[cfg(feature = "cbor")]
+    pub(crate) fn into_inner(self) -> F {
+        self.f
+    }
+}
+
+impl<'de, T: DeserializeOwned, F: FnMut(T)> DeserializeSeed<'de> for ItemsSeed<T, F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_struct("Database", FIELDS, self)
+    }
+}
+
+impl<'de, T: DeserializeOwned, F: FnMut(T)> de::Visitor<'de> for ItemsSeed<T, F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a database")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        // formats without field names can't skip values without knowing their
+        // types, so decode and drop them
+        if seq.next_element::<String>()?.is_none()
+            || seq.next_element::<Option<PathBuf>>()?.is_none()
+            || seq.next_element::<bool>()?.is_none()
+        {
+            return Err(de::Error::invalid_length(0, &self));
+        }
+
+        match seq.next_element_seed(ItemSeq::<T, _>(&mut self.f, PhantomData))? {
+            Some(()) => Ok(()),
+            None => Err(de::Error::invalid_length(3, &self)),
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+        let mut found = false;
+
+        while let Some(field) = map.next_key::<Field>()? {
+            match field {
+                Field::Items if !found => {
+                    map.next_value_seed(ItemSeq::<T, _>(&mut self.f, PhantomData))?;
+                    found = true;
+                }
+                Field::Items => return Err(de::Error::duplicate_field("items")),
+                Field::Other => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+
+        if found {
+            Ok(())
+        } else {
+            Err(de::Error::missing_field("items"))
+        }
+    }
+}
+
+/// Name of a field of an encoded [crate::Database].
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "snake_case")]
+enum Field {
+    Items,
+    #[serde(other)]
+    Other,
+}
+
+/// Decodes a sequence of items, passing each to the callback.
+struct ItemSeq<'a, T, F>(&'a mut F, PhantomData<T>);
+
+impl<'de, T: DeserializeOwned, F: FnMut(T)> DeserializeSeed<'de> for ItemSeq<'_, T, F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, T: DeserializeOwned, F: FnMut(T)> de::Visitor<'de> for ItemSeq<'_, T, F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a sequence of items")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(item) = seq.next_element::<T>()? {
+            (self.0)(item);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,755 @@
+//! Keyed collections storing items by a primary key, see [KeyedDatabase].
+//!
+//! Keys can be given by hand or generated by the database with
+//! [KeyedDatabase::insert_auto] for any key type implementing [AutoKey], which
+//! are [u64] ids counting up from 1 and random `uuid::Uuid`s with the `uuid`
+//! feature.
+//!
+//! Keyed databases are loaded, dumped, encrypted and logged by the same code
+//! as [crate::Database], with their own kind of write-ahead log record.
+
+#[cfg(feature = "encryption")]
+use crate::encryption;
+use crate::error::DatabaseError;
+use crate::store::{Storage, Store};
+use crate::{check_schema, codec, compression, header, wal};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::hash_map::{Entry, HashMap};
+use std::hash;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+#[cfg(feature = "encryption")]
+use std::sync::Arc;
+
+/// A key type which [KeyedDatabase::insert_auto] can generate new keys of.
+pub trait AutoKey: Sized {
+    /// Generates a new key, given the number of keys generated before it
+    /// which is persisted alongside the database.
+    fn generate(generated: u64) -> Self;
+}
+
+/// Monotonically increasing ids starting at 1, which are never handed out
+/// twice by the same database, even after items are removed or it is dumped
+/// and loaded again.
+impl AutoKey for u64 {
+    fn generate(generated: u64) -> Self {
+        generated + 1
+    }
+}
+
+/// Random version 4 UUIDs, enabled with the `uuid` feature.
+#[cfg(feature = "uuid")]
+impl AutoKey for uuid::Uuid {
+    fn generate(_generated: u64) -> Self {
+        uuid::Uuid::new_v4()
+    }
+}
+
+/// A single logged mutation of a [KeyedDatabase].
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum Record<K, T> {
+    /// An item was stored under a key, replacing any item already there.
+    Put(K, T),
+
+    /// An item was stored under the key generated by the given call of
+    /// [KeyedDatabase::insert_auto], counting from 1.
+    Generated(K, T, u64),
+
+    /// The item under a key was removed.
+    Remove(K),
+}
+
+/// A database storing items of type `T` by a primary key of type `K`, rather
+/// than as bare items like [crate::Database].
+///
+/// As items are found by their key, they don't need to implement
+/// [hash::Hash] or [Eq] and can be changed in place with
+/// [KeyedDatabase::get_mut] or [KeyedDatabase::entry] instead of being cloned
+/// and replaced. Keyed databases are dumped and loaded in the same layout as
+/// [crate::Database], including its [codec::Format], [compression::Compression]
+/// and encryption, but can't be loaded as one.
+///
+/// # Examples
+///
+/// ```rust
+/// use tinydb::keyed::KeyedDatabase;
+///
+/// fn main() {
+///     let mut db = KeyedDatabase::new("keyed_example", None);
+///     db.insert(1, String::from("Dave Lister")).unwrap();
+///     db.insert(2, String::from("Arnold Rimmer")).unwrap();
+///
+///     db.get_mut(&2).unwrap().push_str(" BSc");
+///     *db.entry(3).or_insert_with(String::new) += "Kryten";
+///
+///     assert_eq!(db.get(&2).unwrap(), "Arnold Rimmer BSc");
+///     assert_eq!(db.remove(&1).unwrap(), "Dave Lister");
+///     assert_eq!(db.len(), 2);
+/// }
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyedDatabase<K: hash::Hash + Eq, T> {
+    /// Friendly name for the database, used as the fallback path when dumping
+    /// without a [KeyedDatabase::save_path] in the same way as
+    /// [crate::Database::label]
+    pub label: String,
+
+    /// The overwrite path to save the database as, see
+    /// [crate::Database::save_path]
+    pub save_path: Option<PathBuf>,
+
+    /// In-memory [HashMap] of all items by their key
+    pub items: HashMap<K, T>,
+
+    /// Number of keys generated by [KeyedDatabase::insert_auto], which is
+    /// dumped with the database so that generated keys never repeat
+    generated: u64,
+
+    /// The serialization format used by [KeyedDatabase::dump_db], which is
+    /// bincode by default. This is set to the format of the file when loading
+    /// with [KeyedDatabase::from].
+    #[serde(skip)]
+    pub format: codec::Format,
+
+    /// The compression applied to the encoded database by
+    /// [KeyedDatabase::dump_db], which is none by default. This is set to the
+    /// compression of the file when loading with [KeyedDatabase::from].
+    #[serde(skip)]
+    pub compression: compression::Compression,
+
+    /// Identifier of the layout of the keys and items stored, see
+    /// [crate::Database::schema]
+    #[serde(skip)]
+    pub schema: u64,
+
+    /// Write-ahead log and encryption key, see [KeyedDatabase::enable_wal]
+    /// and `KeyedDatabase::set_key`
+    #[serde(skip)]
+    storage: Storage,
+}
+
+impl<K: hash::Hash + Eq + Clone, T: Clone> Clone for KeyedDatabase<K, T> {
+    fn clone(&self) -> Self {
+        KeyedDatabase {
+            label: self.label.clone(),
+            save_path: self.save_path.clone(),
+            items: self.items.clone(),
+            generated: self.generated,
+            format: self.format,
+            compression: self.compression,
+            schema: self.schema,
+            storage: self.storage.clone(),
+        }
+    }
+}
+
+impl<K: hash::Hash + Eq, T: PartialEq> PartialEq for KeyedDatabase<K, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.label == other.label
+            && self.save_path == other.save_path
+            && self.items == other.items
+            && self.generated == other.generated
+            && self.format == other.format
+            && self.compression == other.compression
+    }
+}
+
+impl<K: hash::Hash + Eq, T: Eq> Eq for KeyedDatabase<K, T> {}
+
+impl<K, T> KeyedDatabase<K, T>
+where
+    K: hash::Hash + Eq + Serialize + DeserializeOwned,
+    T: Serialize + DeserializeOwned,
+{
+    /// Creates a new, empty keyed database.
+    pub fn new(label: impl Into<String>, save_path: impl Into<Option<PathBuf>>) -> Self {
+        KeyedDatabase {
+            label: label.into(),
+            save_path: save_path.into(),
+            items: HashMap::new(),
+            generated: 0,
+            format: codec::Format::default(),
+            compression: compression::Compression::default(),
+            schema: 0,
+            storage: Storage::default(),
+        }
+    }
+
+    /// Creates a keyed database from a file dumped with
+    /// [KeyedDatabase::dump_db], replaying its write-ahead log if there is one.
+    ///
+    /// # Errors
+    ///
+    /// Will return the same errors as [crate::Database::from]. Use
+    /// [KeyedDatabase::check_schema] to guard against loading a dump with a
+    /// different key or item type.
+    pub fn from(path: impl Into<PathBuf>) -> Result<Self, DatabaseError> {
+        Store::load::<codec::Bincode>(path.into(), None, false)
+    }
+
+    /// Creates a keyed database from an encrypted file, decrypting it and its
+    /// write-ahead log with the key given by `provider` which is kept to
+    /// encrypt future dumps. See [crate::Database::from_with_key].
+    #[cfg(feature = "encryption")]
+    pub fn from_with_key(
+        path: impl Into<PathBuf>,
+        provider: impl encryption::KeyProvider + 'static,
+    ) -> Result<Self, DatabaseError> {
+        Store::load_with_key(path.into(), Arc::new(provider))
+    }
+
+    /// Loads a keyed database from `path` if it exists, otherwise creating a
+    /// new one labelled after the path's file stem. See
+    /// [crate::Database::auto_from].
+    pub fn auto_from(path: impl Into<PathBuf>) -> Result<Self, DatabaseError> {
+        Store::auto_load(path.into(), |label, path| {
+            KeyedDatabase::new(label, Some(path))
+        })
+    }
+
+    /// Creates a keyed database from a dump read from any `reader`, see
+    /// [crate::Database::load_from].
+    pub fn load_from<R: Read>(reader: R) -> Result<Self, DatabaseError> {
+        Store::load_stream(reader, &None)
+    }
+
+    /// Creates a keyed database from an encrypted dump read from any
+    /// `reader`, see [crate::Database::load_from_with_key].
+    #[cfg(feature = "encryption")]
+    pub fn load_from_with_key<R: Read>(
+        reader: R,
+        provider: impl encryption::KeyProvider + 'static,
+    ) -> Result<Self, DatabaseError> {
+        let provider: Arc<dyn encryption::KeyProvider> = Arc::new(provider);
+        let mut db: Self = Store::load_stream(reader, &Some(provider.key()?))?;
+        db.storage.set_key(provider);
+
+        Ok(db)
+    }
+
+    /// Checks that the database was loaded from a dump of the given `schema`,
+    /// see [crate::Database::check_schema].
+    pub fn check_schema(&self, schema: u64) -> Result<(), DatabaseError> {
+        check_schema(self.schema, schema)
+    }
+
+    /// Returns the item stored under `key`.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::ItemNotFound] if there is no item under
+    /// `key`.
+    pub fn get(&self, key: &K) -> Result<&T, DatabaseError> {
+        self.items.get(key).ok_or(DatabaseError::ItemNotFound)
+    }
+
+    /// Returns a mutable reference to the item stored under `key`, allowing it
+    /// to be changed in place.
+    ///
+    /// Changes made through the reference can't be written to the write-ahead
+    /// log, so they are only saved by the next dump. Use
+    /// [KeyedDatabase::modify] if the log is enabled.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::ItemNotFound] if there is no item under
+    /// `key`.
+    pub fn get_mut(&mut self, key: &K) -> Result<&mut T, DatabaseError> {
+        self.items.get_mut(key).ok_or(DatabaseError::ItemNotFound)
+    }
+
+    /// Adds a new `item` under `key`.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::DupeFound] if there is already an item
+    /// under `key`, leaving it untouched. Use [KeyedDatabase::put] to replace
+    /// existing items.
+    pub fn insert(&mut self, key: K, item: T) -> Result<(), DatabaseError> {
+        if self.items.contains_key(&key) {
+            return Err(DatabaseError::DupeFound);
+        }
+
+        self.log(&Record::Put(&key, &item))?;
+        self.items.insert(key, item);
+
+        Ok(())
+    }
+
+    /// Adds a new `item` under a key generated by the database, returning the
+    /// key so it can be used to find, update or remove the item later.
+    ///
+    /// Generated keys which are already in use (e.g. given by hand to
+    /// [KeyedDatabase::insert]) are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tinydb::keyed::KeyedDatabase;
+    ///
+    /// fn main() {
+    ///     let mut db: KeyedDatabase<u64, String> = KeyedDatabase::new("auto_example", None);
+    ///
+    ///     let holly = db.insert_auto(String::from("Holly")).unwrap();
+    ///     let kryten = db.insert_auto(String::from("Kryten")).unwrap();
+    ///     db.remove(&holly).unwrap();
+    ///
+    ///     assert_eq!((holly, kryten), (1, 2));
+    ///     assert_eq!(db.insert_auto(String::from("Talkie Toaster")).unwrap(), 3);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::DupeFound] if [AutoKey::generate] keeps
+    /// returning keys which are in use, which can only happen once every
+    /// possible [u64] id has been handed out.
+    pub fn insert_auto(&mut self, item: T) -> Result<K, DatabaseError>
+    where
+        K: AutoKey + Clone,
+    {
+        loop {
+            if self.generated == u64::MAX {
+                return Err(DatabaseError::DupeFound);
+            }
+
+            let key = K::generate(self.generated);
+
+            if !self.items.contains_key(&key) {
+                self.log(&Record::Generated(&key, &item, self.generated + 1))?;
+                self.generated += 1;
+                self.items.insert(key.clone(), item);
+
+                return Ok(key);
+            }
+            self.generated += 1;
+        }
+    }
+
+    /// Replaces the item stored under `key` with `item`, returning the item
+    /// which was replaced.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::ItemNotFound] if there is no item under
+    /// `key`, leaving the database untouched.
+    pub fn update(&mut self, key: &K, item: T) -> Result<T, DatabaseError> {
+        if !self.items.contains_key(key) {
+            return Err(DatabaseError::ItemNotFound);
+        }
+
+        self.log(&Record::Put(key, &item))?;
+        self.get_mut(key)
+            .map(|existing| std::mem::replace(existing, item))
+    }
+
+    /// Changes the item stored under `key` in place with `f`, writing the
+    /// changed item to the write-ahead log if it is enabled.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::ItemNotFound] if there is no item under
+    /// `key`. If logging the changed item fails, the change is kept in memory
+    /// but will only be saved by the next dump.
+    pub fn modify(&mut self, key: &K, f: impl FnOnce(&mut T)) -> Result<(), DatabaseError> {
+        let item = self.items.get_mut(key).ok_or(DatabaseError::ItemNotFound)?;
+        f(item);
+
+        self.storage.log(&Record::Put(key, &self.items[key]))
+    }
+
+    /// Stores `item` under `key`, replacing and returning any item which was
+    /// already there.
+    ///
+    /// # Errors
+    ///
+    /// Will return the errors of appending to the write-ahead log if it is
+    /// enabled, leaving the database untouched.
+    pub fn put(&mut self, key: K, item: T) -> Result<Option<T>, DatabaseError> {
+        self.log(&Record::Put(&key, &item))?;
+
+        Ok(self.items.insert(key, item))
+    }
+
+    /// Removes and returns the item stored under `key`.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::ItemNotFound] if there is no item under
+    /// `key`.
+    pub fn remove(&mut self, key: &K) -> Result<T, DatabaseError> {
+        if !self.items.contains_key(key) {
+            return Err(DatabaseError::ItemNotFound);
+        }
+
+        self.log(&Record::<&K, &T>::Remove(key))?;
+        self.items.remove(key).ok_or(DatabaseError::ItemNotFound)
+    }
+
+    /// Gets the [Entry] for `key` to insert or change its item in place.
+    ///
+    /// As with [KeyedDatabase::get_mut], changes made through the entry can't
+    /// be written to the write-ahead log.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, T> {
+        self.items.entry(key)
+    }
+
+    /// Returns `true` if there is an item stored under `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.items.contains_key(key)
+    }
+
+    /// Returns the number of items in the database.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if the database contains no items.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Dumps the database to [KeyedDatabase::save_path], or a path made from
+    /// [KeyedDatabase::label] if there isn't one, in the same way as
+    /// [crate::Database::dump_db].
+    pub fn dump_db(&self) -> Result<(), DatabaseError> {
+        self.dump_db_as::<codec::Bincode>(self.format)
+    }
+
+    /// Dumps the database to any `writer`, see [crate::Database::dump_to].
+    pub fn dump_to<W: Write>(&self, writer: W) -> Result<(), DatabaseError> {
+        Store::dump_to(self, writer)
+    }
+
+    /// Sets the key provider used to encrypt the database and its write-ahead
+    /// log, see [crate::Database::set_key].
+    #[cfg(feature = "encryption")]
+    pub fn set_key(
+        &mut self,
+        provider: impl encryption::KeyProvider + 'static,
+    ) -> Result<(), DatabaseError> {
+        self.replace_key(Some(Arc::new(provider)))
+    }
+
+    /// Removes the key provider so future dumps are no longer encrypted, see
+    /// [crate::Database::remove_key].
+    #[cfg(feature = "encryption")]
+    pub fn remove_key(&mut self) -> Result<(), DatabaseError> {
+        self.replace_key(None)
+    }
+
+    /// Re-encrypts the encrypted dump of a keyed database at `path` from the
+    /// `old` key to the `new` key, first folding its write-ahead log into it.
+    /// See [encryption::rotate_key].
+    #[cfg(feature = "encryption")]
+    pub fn rotate_key(
+        path: impl Into<PathBuf>,
+        old: &dyn encryption::KeyProvider,
+        new: &dyn encryption::KeyProvider,
+    ) -> Result<(), DatabaseError> {
+        encryption::rotate::<Self>(&path.into(), old, new)
+    }
+
+    /// Enables the write-ahead log, see [crate::Database::enable_wal]. Changes
+    /// made through [KeyedDatabase::get_mut] and [KeyedDatabase::entry] aren't
+    /// logged.
+    pub fn enable_wal(&mut self, policy: wal::SyncPolicy) -> Result<(), DatabaseError> {
+        Store::enable_wal(self, policy)
+    }
+
+    /// Dumps the database and empties the write-ahead log, see
+    /// [crate::Database::checkpoint].
+    pub fn checkpoint(&mut self) -> Result<(), DatabaseError> {
+        Store::checkpoint(self)
+    }
+
+    /// Syncs the write-ahead log to disk, see [crate::Database::sync_wal].
+    pub fn sync_wal(&mut self) -> Result<(), DatabaseError> {
+        Store::sync_wal(self)
+    }
+}
+
+impl<K, T> Store for KeyedDatabase<K, T>
+where
+    K: hash::Hash + Eq + Serialize + DeserializeOwned,
+    T: Serialize + DeserializeOwned,
+{
+    type Record = Record<K, T>;
+
+    const KIND: header::Kind = header::Kind::Keyed;
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+
+    fn save_path(&self) -> Option<&PathBuf> {
+        self.save_path.as_ref()
+    }
+
+    fn layout(&self) -> (codec::Format, compression::Compression, u64) {
+        (self.format, self.compression, self.schema)
+    }
+
+    fn set_layout(
+        &mut self,
+        format: codec::Format,
+        compression: compression::Compression,
+        schema: u64,
+    ) {
+        self.format = format;
+        self.compression = compression;
+        self.schema = schema;
+    }
+
+    fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    fn storage_mut(&mut self) -> &mut Storage {
+        &mut self.storage
+    }
+
+    fn apply_records(&mut self, records: Vec<Record<K, T>>) {
+        for record in records {
+            match record {
+                Record::Put(key, item) => {
+                    self.items.insert(key, item);
+                }
+                Record::Generated(key, item, generated) => {
+                    self.items.insert(key, item);
+                    self.generated = self.generated.max(generated);
+                }
+                Record::Remove(key) => {
+                    self.items.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Database;
+
+    /// Tests that items can be added, changed in place and removed by key.
+    #[test]
+    fn get_put_remove() -> Result<(), DatabaseError> {
+        let mut db = KeyedDatabase::new("crew", None);
+
+        db.insert(1, String::from("Lister"))?;
+        assert!(matches!(
+            db.insert(1, String::from("Rimmer")),
+            Err(DatabaseError::DupeFound)
+        ));
+        assert_eq!(db.put(2, String::from("Rimmer"))?, None);
+        assert_eq!(
+            db.put(2, String::from("Ace Rimmer"))?,
+            Some(String::from("Rimmer"))
+        );
+
+        db.get_mut(&1)?.insert_str(0, "Dave ");
+        db.entry(3).or_insert_with(|| String::from("Cat"));
+        db.entry(3).and_modify(|name| name.push('!'));
+
+        assert_eq!(db.get(&1)?, "Dave Lister");
+        assert_eq!(db.get(&3)?, "Cat!");
+        assert_eq!(db.remove(&2)?, "Ace Rimmer");
+        assert!(!db.contains_key(&2));
+        assert!(matches!(db.get(&2), Err(DatabaseError::ItemNotFound)));
+        assert!(matches!(db.remove(&2), Err(DatabaseError::ItemNotFound)));
+        assert_eq!(db.len(), 2);
+
+        Ok(())
+    }
+
+    /// Tests that generated ids skip ones in use and never repeat, even after
+    /// being dumped and loaded.
+    #[test]
+    fn auto_ids() -> Result<(), DatabaseError> {
+        let mut db = KeyedDatabase::new("auto_ids", None);
+
+        db.insert(2, String::from("Kochanski"))?;
+        assert_eq!(db.insert_auto(String::from("Lister"))?, 1);
+        assert_eq!(db.insert_auto(String::from("Rimmer"))?, 3);
+        db.remove(&3)?;
+
+        let mut buffer = vec![];
+        db.dump_to(&mut buffer)?;
+        let mut loaded: KeyedDatabase<u64, String> = KeyedDatabase::load_from(&buffer[..])?;
+
+        assert_eq!(loaded.insert_auto(String::from("Cat"))?, 4);
+        assert_eq!(loaded.update(&4, String::from("Duane Dibbley"))?, "Cat");
+        assert!(matches!(
+            loaded.update(&3, String::from("Rimmer")),
+            Err(DatabaseError::ItemNotFound)
+        ));
+
+        Ok(())
+    }
+
+    /// Tests that UUID keys are generated randomly.
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn auto_uuids() -> Result<(), DatabaseError> {
+        let mut db = KeyedDatabase::new("auto_uuids", None);
+
+        let first: uuid::Uuid = db.insert_auto(String::from("Holly"))?;
+        let second = db.insert_auto(String::from("Hattie"))?;
+
+        assert_ne!(first, second);
+        assert_eq!(db.get(&second)?, "Hattie");
+
+        Ok(())
+    }
+
+    /// Tests that keyed databases survive being dumped and loaded, and can't
+    /// be loaded as a plain [Database] or the other way around, or with a
+    /// schema, as a different key type.
+    #[test]
+    fn keyed_dump_load() -> Result<(), DatabaseError> {
+        let path = std::env::temp_dir().join("tinydb_keyed_dump_load.tinydb");
+
+        let mut db = KeyedDatabase::new("ships", path.clone());
+        db.schema = 1;
+        db.insert(String::from("Red Dwarf"), 6)?;
+        db.insert(String::from("Starbug"), 1)?;
+        db.dump_db()?;
+
+        let loaded: KeyedDatabase<String, i32> = KeyedDatabase::from(&path)?;
+        assert_eq!(loaded, db);
+        assert_eq!(KeyedDatabase::auto_from(&path)?, db);
+
+        loaded.check_schema(1)?;
+
+        assert!(matches!(
+            Database::<i32>::from(&path),
+            Err(DatabaseError::ItemTypeMismatch)
+        ));
+        assert!(Database::<i32>::verify(&path)?.header.is_failed());
+        assert!(KeyedDatabase::<u64, i32>::from(&path)
+            .and_then(|db| db.check_schema(2))
+            .is_err());
+
+        let mut buffer = vec![];
+        db.dump_to(&mut buffer)?;
+        assert_eq!(KeyedDatabase::load_from(&buffer[..])?, db);
+
+        let mut plain = Database::new("plain", path.clone(), false);
+        plain.add_item(6)?;
+        plain.dump_db()?;
+        assert!(matches!(
+            KeyedDatabase::<String, i32>::from(&path),
+            Err(DatabaseError::ItemTypeMismatch)
+        ));
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    /// Tests that every logged change, including generated ids, is replayed
+    /// from the write-ahead log when loading.
+    #[test]
+    fn keyed_wal() -> Result<(), DatabaseError> {
+        let path = std::env::temp_dir().join("tinydb_keyed_wal.tinydb");
+        let wal_path = wal::wal_path(&path);
+        let _ = std::fs::remove_file(&wal_path);
+
+        let mut db: KeyedDatabase<u64, String> = KeyedDatabase::new("wal", path.clone());
+        db.insert(5, String::from("Rimmer"))?;
+        db.checkpoint()?;
+
+        db.enable_wal(wal::SyncPolicy::Always)?;
+        assert_eq!(db.insert_auto(String::from("Lister"))?, 1);
+        assert_eq!(db.insert_auto(String::from("Kochanski"))?, 2);
+        db.remove(&2)?;
+        db.put(3, String::from("Cat"))?;
+        db.update(&5, String::from("Ace Rimmer"))?;
+        db.modify(&1, |name| name.insert_str(0, "Dave "))?;
+        drop(db);
+
+        let mut loaded: KeyedDatabase<u64, String> = KeyedDatabase::from(&path)?;
+        assert_eq!(loaded.get(&1)?, "Dave Lister");
+        assert_eq!(loaded.get(&3)?, "Cat");
+        assert_eq!(loaded.get(&5)?, "Ace Rimmer");
+        assert!(!loaded.contains_key(&2));
+        assert_eq!(loaded.insert_auto(String::from("Kryten"))?, 4);
+
+        std::fs::remove_file(path)?;
+        std::fs::remove_file(wal_path)?;
+
+        Ok(())
+    }
+
+    /// Tests that encrypted keyed databases and their logs can only be loaded
+    /// with their key.
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn keyed_encryption() -> Result<(), DatabaseError> {
+        let path = std::env::temp_dir().join("tinydb_keyed_encryption.tinydb");
+        let wal_path = wal::wal_path(&path);
+        let _ = std::fs::remove_file(&wal_path);
+        let key = encryption::Key::generate();
+
+        let mut db: KeyedDatabase<u64, String> = KeyedDatabase::new("encrypted", path.clone());
+        db.set_key(key.clone())?;
+        db.insert(1, String::from("Holly"))?;
+
+        let mut buffer = vec![];
+        db.dump_to(&mut buffer)?;
+        assert!(KeyedDatabase::<u64, String>::load_from(&buffer[..]).is_err());
+        assert_eq!(
+            KeyedDatabase::load_from_with_key(&buffer[..], key.clone())?,
+            db
+        );
+
+        db.dump_db()?;
+        db.enable_wal(wal::SyncPolicy::Always)?;
+        db.insert(2, String::from("Hattie"))?;
+        drop(db);
+
+        assert!(KeyedDatabase::<u64, String>::from(&path).is_err());
+        let loaded: KeyedDatabase<u64, String> = KeyedDatabase::from_with_key(&path, key)?;
+        assert_eq!(loaded.get(&2)?, "Hattie");
+
+        std::fs::remove_file(path)?;
+        std::fs::remove_file(wal_path)?;
+
+        Ok(())
+    }
+
+    /// Tests that rotating the key of a keyed database folds its pending
+    /// write-ahead log records into the re-encrypted dump.
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn keyed_rotate_key() -> Result<(), DatabaseError> {
+        let path = std::env::temp_dir().join("tinydb_keyed_rotate_key.tinydb");
+        let wal_path = wal::wal_path(&path);
+        let _ = std::fs::remove_file(&wal_path);
+        let (old, new) = (encryption::Key::generate(), encryption::Key::generate());
+
+        let mut db: KeyedDatabase<u64, String> = KeyedDatabase::new("rotate", path.clone());
+        db.set_key(old.clone())?;
+        db.insert(1, String::from("Holly"))?;
+        db.dump_db()?;
+        db.enable_wal(wal::SyncPolicy::Always)?;
+        db.insert_auto(String::from("Hattie"))?;
+        db.put(1, String::from("Holly 2"))?;
+        db.remove(&1)?;
+        drop(db);
+
+        KeyedDatabase::<u64, String>::rotate_key(&path, &old, &new)?;
+
+        assert!(!wal_path.exists());
+        assert!(KeyedDatabase::<u64, String>::from_with_key(&path, old).is_err());
+        let mut loaded: KeyedDatabase<u64, String> = KeyedDatabase::from_with_key(&path, new)?;
+        assert_eq!(loaded.get(&2)?, "Hattie");
+        assert!(!loaded.contains_key(&1));
+        assert_eq!(loaded.insert_auto(String::from("Kryten"))?, 3);
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,568 @@
+//! A small text query language for querying items by field name, see
+//! [crate::Database::query_str].
+//!
+//! Queries compare fields of items to values, combined with boolean operators:
+//!
+//! ```none
+//! age > 30 and (name ~ "Li*" or not retired = true)
+//! ```
+//!
+//! - Fields are named as they are serialized, see [crate::value::to_value].
+//!   Nested fields are reached with paths such as `address.city`, where
+//!   numbers index into sequences (see [crate::value::Value::get_path]).
+//! - Values can be strings in double or single quotes (with `\"`, `\'`, `\\`,
+//!   `\n` and `\t` escapes), integers, floats, `true`, `false` or `null`.
+//! - Comparisons are `=` (or `==`), `!=`, `<`, `<=`, `>`, `>=` and `~`, which
+//!   matches strings against a glob pattern where `*` is any run of
+//!   characters and `?` is any single character.
+//! - Comparisons can be combined with `and` (or `&&`), `or` (or `||`) and
+//!   `not` (or `!`) in order of increasing precedence, and grouped with
+//!   parentheses. Keywords aren't case sensitive.
+//!
+//! Integers and floats are compared numerically and strings by their bytes.
+//! A comparison between values of different kinds or with a field the item
+//! doesn't have never matches, other than `!=` which always does. Querying
+//! through [crate::Database::query_str] fails if any item can't be converted
+//! into a [Value].
+
+use crate::error::DatabaseError;
+use crate::value::{to_value, Value};
+use serde::Serialize;
+use std::cmp::Ordering;
+
+/// A comparison operator between a field and a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// `=` or `==`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `<`
+    Lt,
+    /// `<=`
+    Le,
+    /// `>`
+    Gt,
+    /// `>=`
+    Ge,
+    /// `~`, a glob match on strings
+    Like,
+}
+
+impl Op {
+    /// Returns `true` if `field` compared to `value` with this operator holds.
+    pub fn apply(self, field: &Value, value: &Value) -> bool {
+        if let Op::Like = self {
+            return match (field, value) {
+                (Value::String(field), Value::String(pattern)) => glob_match(pattern, field),
+                _ => false,
+            };
+        }
+
+        let ordering = field.compare(value);
+
+        match self {
+            Op::Eq => ordering == Some(Ordering::Equal),
+            Op::Ne => ordering != Some(Ordering::Equal),
+            Op::Lt => ordering == Some(Ordering::Less),
+            Op::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            Op::Gt => ordering == Some(Ordering::Greater),
+            Op::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+            Op::Like => unreachable!(),
+        }
+    }
+}
+
+/// A parsed query, see the [module-level documentation](self) for its syntax.
+///
+/// # Examples
+///
+/// ```rust
+/// use serde::Serialize;
+/// use tinydb::lang::Expr;
+///
+/// #[derive(Serialize)]
+/// struct ExampleStruct {
+///     name: String,
+///     age: i32,
+/// }
+///
+/// fn main() {
+///     let expr = Expr::parse(r#"age > 30 and name ~ "Li*""#).unwrap();
+///
+///     let lister = ExampleStruct { name: "Lister".into(), age: 3000025 };
+///     assert!(expr.matches(&lister).unwrap());
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// Both expressions match
+    And(Box<Expr>, Box<Expr>),
+    /// Either expression matches
+    Or(Box<Expr>, Box<Expr>),
+    /// The expression doesn't match
+    Not(Box<Expr>),
+    /// The field at the path compared to the value with the operator holds
+    Compare { field: String, op: Op, value: Value },
+}
+
+impl Expr {
+    /// Parses a query.
+    ///
+    /// # Errors
+    ///
+    /// Will return [DatabaseError::QueryParse] describing the first problem
+    /// found and where it is.
+    pub fn parse(query: &str) -> Result<Self, DatabaseError> {
+        let mut parser = Parser {
+            tokens: tokenize(query)?,
+            next: 0,
+            end: query.len(),
+        };
+
+        let expr = parser.or()?;
+        match parser.peek() {
+            Some((position, token)) => Err(parse_error(
+                *position,
+                format!(
+                    "expected `and`, `or` or the end of the query, found {}",
+                    token
+                ),
+            )),
+            None => Ok(expr),
+        }
+    }
+
+    /// Returns `true` if `item` matches the query.
+    ///
+    /// # Errors
+    ///
+    /// Will return the errors of [to_value] if `item` can't be converted into a
+    /// [Value].
+    pub fn matches<T: Serialize + ?Sized>(&self, item: &T) -> Result<bool, DatabaseError> {
+        Ok(self.matches_value(&to_value(item)?))
+    }
+
+    /// Returns `true` if an item converted into `value` matches the query.
+    pub fn matches_value(&self, value: &Value) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches_value(value) && b.matches_value(value),
+            Expr::Or(a, b) => a.matches_value(value) || b.matches_value(value),
+            Expr::Not(expr) => !expr.matches_value(value),
+            Expr::Compare {
+                field,
+                op,
+                value: expected,
+            } => match value.get_path(field) {
+                Some(found) => op.apply(found, expected),
+                None => *op == Op::Ne,
+            },
+        }
+    }
+}
+
+/// Creates a [DatabaseError::QueryParse] at byte `position`.
+fn parse_error(position: usize, message: impl Into<String>) -> DatabaseError {
+    DatabaseError::QueryParse {
+        position,
+        message: message.into(),
+    }
+}
+
+/// A lexical token of a query.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Literal(Value),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "`{}`", name),
+            Token::Literal(value) => write!(f, "`{}`", value),
+            Token::Op(_) => write!(f, "a comparison"),
+            Token::And => write!(f, "`and`"),
+            Token::Or => write!(f, "`or`"),
+            Token::Not => write!(f, "`not`"),
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+        }
+    }
+}
+
+/// Splits a query into tokens paired with the byte offsets they start at.
+fn tokenize(query: &str) -> Result<Vec<(usize, Token)>, DatabaseError> {
+    let mut tokens = vec![];
+    let mut chars = query.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' | ')' | '~' => {
+                chars.next();
+                match c {
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    _ => Token::Op(Op::Like),
+                }
+            }
+            '=' | '!' | '<' | '>' | '&' | '|' => {
+                chars.next();
+                let doubled = chars.peek().map(|&(_, next)| next);
+                let mut take = |token| {
+                    chars.next();
+                    token
+                };
+
+                match (c, doubled) {
+                    ('=', Some('=')) => take(Token::Op(Op::Eq)),
+                    ('=', _) => Token::Op(Op::Eq),
+                    ('!', Some('=')) => take(Token::Op(Op::Ne)),
+                    ('!', _) => Token::Not,
+                    ('<', Some('=')) => take(Token::Op(Op::Le)),
+                    ('<', _) => Token::Op(Op::Lt),
+                    ('>', Some('=')) => take(Token::Op(Op::Ge)),
+                    ('>', _) => Token::Op(Op::Gt),
+                    ('&', Some('&')) => take(Token::And),
+                    ('|', Some('|')) => take(Token::Or),
+                    _ => return Err(parse_error(start, format!("expected `{}{}`", c, c))),
+                }
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut string = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some((_, end)) if end == c => break,
+                        Some((escape, '\\')) => match chars.next() {
+                            Some((_, e @ ('"' | '\'' | '\\'))) => string.push(e),
+                            Some((_, 'n')) => string.push('\n'),
+                            Some((_, 't')) => string.push('\t'),
+                            _ => return Err(parse_error(escape, "unknown escape in string")),
+                        },
+                        Some((_, other)) => string.push(other),
+                        None => return Err(parse_error(start, "unterminated string")),
+                    }
+                }
+
+                Token::Literal(Value::String(string))
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let mut end = start;
+                while let Some(&(i, next)) = chars.peek() {
+                    let exponent_sign = (next == '-' || next == '+')
+                        && matches!(query[..i].chars().last(), Some('e' | 'E'));
+
+                    if next.is_ascii_alphanumeric()
+                        || next == '.'
+                        || exponent_sign
+                        || (i == start && next == '-')
+                    {
+                        end = i + next.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                let number = &query[start..end];
+                if let Ok(int) = number.parse::<i128>() {
+                    Token::Literal(Value::Int(int))
+                } else if let Ok(float) = number.parse::<f64>() {
+                    Token::Literal(Value::Float(float))
+                } else {
+                    return Err(parse_error(start, format!("invalid number `{}`", number)));
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = start;
+                while let Some(&(i, next)) = chars.peek() {
+                    if next.is_alphanumeric() || next == '_' || next == '.' {
+                        end = i + next.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                let word = &query[start..end];
+                match word.to_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "null" => Token::Literal(Value::Null),
+                    _ if word.ends_with('.') || word.contains("..") => {
+                        return Err(parse_error(start, format!("invalid field path `{}`", word)))
+                    }
+                    _ => Token::Ident(word.into()),
+                }
+            }
+            other => return Err(parse_error(start, format!("unexpected `{}`", other))),
+        };
+
+        tokens.push((start, token));
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser over the tokens of a query.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&(usize, Token)> {
+        self.tokens.get(self.next)
+    }
+
+    /// Consumes the next token if it is `token`.
+    fn eat(&mut self, token: &Token) -> bool {
+        match self.peek() {
+            Some((_, next)) if next == token => {
+                self.next += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Consumes the next token, failing with an error expecting `expected` if
+    /// there isn't one.
+    fn advance(&mut self, expected: &str) -> Result<(usize, Token), DatabaseError> {
+        let token = self.tokens.get(self.next).cloned().ok_or_else(|| {
+            parse_error(
+                self.end,
+                format!("expected {}, found the end of the query", expected),
+            )
+        })?;
+        self.next += 1;
+
+        Ok(token)
+    }
+
+    /// `or := and ("or" and)*`
+    fn or(&mut self) -> Result<Expr, DatabaseError> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+
+        Ok(expr)
+    }
+
+    /// `and := unary ("and" unary)*`
+    fn and(&mut self) -> Result<Expr, DatabaseError> {
+        let mut expr = self.unary()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+
+        Ok(expr)
+    }
+
+    /// `unary := "not" unary | "(" or ")" | comparison`
+    fn unary(&mut self) -> Result<Expr, DatabaseError> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        } else if self.eat(&Token::Open) {
+            let expr = self.or()?;
+
+            return match self.advance("`)`")? {
+                (_, Token::Close) => Ok(expr),
+                (position, token) => Err(parse_error(
+                    position,
+                    format!("expected `)`, found {}", token),
+                )),
+            };
+        }
+
+        self.comparison()
+    }
+
+    /// `comparison := field op value`
+    fn comparison(&mut self) -> Result<Expr, DatabaseError> {
+        let field = match self.advance("a field name")? {
+            (_, Token::Ident(field)) => field,
+            (position, token) => {
+                return Err(parse_error(
+                    position,
+                    format!("expected a field name, found {}", token),
+                ))
+            }
+        };
+        let op = match self.advance("a comparison")? {
+            (_, Token::Op(op)) => op,
+            (position, token) => {
+                return Err(parse_error(
+                    position,
+                    format!("expected a comparison after `{}`, found {}", field, token),
+                ))
+            }
+        };
+        let value = match self.advance("a value")? {
+            (_, Token::Literal(value)) => value,
+            (position, token) => {
+                return Err(parse_error(
+                    position,
+                    format!("expected a value, found {}", token),
+                ))
+            }
+        };
+
+        Ok(Expr::Compare { field, op, value })
+    }
+}
+
+/// Matches `text` against a glob `pattern` where `*` matches any run of
+/// characters and `?` matches any single character.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // let the last `*` match one more character
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that precedence and grouping are respected whilst parsing.
+    #[test]
+    fn parses_precedence() -> Result<(), DatabaseError> {
+        let compare = |field: &str, op, value: i32| Expr::Compare {
+            field: field.into(),
+            op,
+            value: value.into(),
+        };
+
+        assert_eq!(
+            Expr::parse("a = 1 or b != 2 AND not c >= 3")?,
+            Expr::Or(
+                Box::new(compare("a", Op::Eq, 1)),
+                Box::new(Expr::And(
+                    Box::new(compare("b", Op::Ne, 2)),
+                    Box::new(Expr::Not(Box::new(compare("c", Op::Ge, 3))))
+                ))
+            )
+        );
+        assert_eq!(
+            Expr::parse("(a == 1 || b < 2) && !(c <= 3)")?,
+            Expr::And(
+                Box::new(Expr::Or(
+                    Box::new(compare("a", Op::Eq, 1)),
+                    Box::new(compare("b", Op::Lt, 2))
+                )),
+                Box::new(Expr::Not(Box::new(compare("c", Op::Le, 3))))
+            )
+        );
+        assert_eq!(
+            Expr::parse("address.city = 1")?,
+            compare("address.city", Op::Eq, 1)
+        );
+        assert_eq!(
+            Expr::parse(r#"name ~ 'Ri\'*' and x > -1.5e3 and y = null"#)?,
+            Expr::And(
+                Box::new(Expr::And(
+                    Box::new(Expr::Compare {
+                        field: "name".into(),
+                        op: Op::Like,
+                        value: "Ri'*".into()
+                    }),
+                    Box::new(Expr::Compare {
+                        field: "x".into(),
+                        op: Op::Gt,
+                        value: Value::Float(-1500.0)
+                    })
+                )),
+                Box::new(Expr::Compare {
+                    field: "y".into(),
+                    op: Op::Eq,
+                    value: Value::Null
+                })
+            )
+        );
+
+        Ok(())
+    }
+
+    /// Tests that malformed queries are rejected with the position of the
+    /// problem.
+    #[test]
+    fn parse_errors() {
+        for (query, expected) in [
+            ("", 0),
+            ("age >", 5),
+            ("age 30", 4),
+            ("> 30", 0),
+            ("(age > 30", 9),
+            ("age > 30)", 8),
+            ("age > 30 name = 1", 9),
+            (r#"name = "Lister"#, 7),
+            ("age = 3x", 6),
+            ("age # 3", 4),
+            ("a = 1 & b = 2", 6),
+            ("address. = 1", 0),
+            ("a..b = 1", 0),
+        ] {
+            match Expr::parse(query) {
+                Err(DatabaseError::QueryParse { position, .. }) => {
+                    assert_eq!(position, expected, "{}", query)
+                }
+                other => panic!("{} parsed as {:?}", query, other),
+            }
+        }
+    }
+
+    /// Tests glob patterns.
+    #[test]
+    fn globs() {
+        assert!(glob_match("Li*", "Lister"));
+        assert!(glob_match("*ster", "Lister"));
+        assert!(glob_match("L?s*r", "Lister"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*i*e*", "Kochanski the Ace"));
+        assert!(!glob_match("Li*", "Rimmer"));
+        assert!(!glob_match("L?ster", "Lster"));
+        assert!(!glob_match("*x", "Lister"));
+    }
+}
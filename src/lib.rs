@@ -56,31 +56,82 @@
 //!
 //! Some commonly-used operations for the [Database] structure.
 //!
-//! | Operation                               | Implamentation          |
-//! |-----------------------------------------|-------------------------|
-//! | Create database                         | [Database::new]         |
-//! | Create database from file               | [Database::from]        |
-//! | Load database or create if non-existant | [Database::auto_from]   |
-//! | Query all matching items                | [Database::query]       |
-//! | Query for item                          | [Database::query_item]  |
-//! | Contains specific item                  | [Database::contains]    |
-//! | Update/replace item                     | [Database::update_item] |
-//! | Delete item                             | [Database::remove_item] |
-//! | Dump database                           | [Database::dump_db]     |
+//! | Operation                               | Implamentation                       |
+//! |-----------------------------------------|--------------------------------------|
+//! | Create database                         | [Database::new]                      |
+//! | Create database from file               | [Database::from]                     |
+//! | Load database or create if non-existant | [Database::auto_from]                |
+//! | Query all matching items                | [Database::query]                    |
+//! | Query for item                          | [Database::query_item]               |
+//! | Query items matching a predicate        | [Database::query_where]              |
+//! | Query items with a text query           | [Database::query_str]                |
+//! | Query items by nested field paths       | [Database::query_field]              |
+//! | Sort and page through items             | [Database::select]                   |
+//! | Aggregate items and results             | [aggregate::Aggregate]               |
+//! | Join items of two databases             | [Database::join]                     |
+//! | Query items using an index              | [Database::query_indexed]            |
+//! | Query items by a range of keys          | [Database::query_range]              |
+//! | Page through items with cursors         | [Database::query_page]               |
+//! | Search text fields of items             | [Database::search]                   |
+//! | Dump a text index alongside items       | [Database::persist_text_index]       |
+//! | Find items by key prefix or typo        | [Database::query_prefix]             |
+//! | Contains specific item                  | [Database::contains]                 |
+//! | Update/replace item                     | [Database::update_item]              |
+//! | Delete item                             | [Database::remove_item]              |
+//! | Enforce unique fields                   | [Database::create_unique_constraint] |
+//! | Dump database                           | [Database::dump_db]                  |
+//! | Dump database to a stream               | [Database::dump_to]                  |
+//! | Dump with a user serialization format   | [Database::dump_with_codec]          |
+//! | Load database from a stream             | [Database::load_from]                |
+//! | Load a dump from before headers existed | [Database::from_legacy]              |
+//! | Process items lazily from file          | [Database::for_each_item]            |
+//! | Verify dumped database                  | [Database::verify]                   |
+//! | Check the schema of a loaded dump       | [Database::check_schema]             |
+//! | Log mutations between dumps             | [Database::enable_wal]               |
+//! | Encrypt dumped database                 | `Database::set_key`                  |
+//! | Store items by primary key              | [keyed::KeyedDatabase]               |
+//! | Insert with generated ids               | [keyed::KeyedDatabase::insert_auto]  |
 
 #![doc(
     html_logo_url = "https://github.com/Owez/tinydb/raw/master/logo.png",
     html_favicon_url = "https://github.com/Owez/tinydb/raw/master/logo.png"
 )]
+#![allow(clippy::doc_lazy_continuation)]
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp;
 use std::collections::HashSet;
 use std::fs::File;
 use std::hash;
-use std::io::prelude::*;
-use std::path::PathBuf;
+use std::io::{prelude::*, BufReader};
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use store::Store;
 
+pub mod aggregate;
+mod atomic;
+mod checksum;
+pub mod codec;
+pub mod compression;
+pub mod cursor;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 pub mod error;
+#[cfg(feature = "geo")]
+pub mod geo;
+pub mod header;
+mod index;
+mod items;
+pub mod keyed;
+pub mod lang;
+pub mod query;
+mod store;
+pub mod text;
+pub mod value;
+pub mod verify;
+pub mod wal;
 
 /// The primary database structure, allowing storage of a generic type with
 /// dumping/saving options avalible.
@@ -89,7 +140,7 @@ pub mod error;
 /// conventional database model and should implament [hash::Hash] and [Eq] for
 /// basic in-memory storage with [Serialize] and [Deserialize] being implamented
 /// for file operations involving the database (these are also required).
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Database<T: hash::Hash + Eq> {
     /// Friendly name for the database, preferibly in `slug-form-like-this` as
     /// this is the fallback path
@@ -112,8 +163,71 @@ pub struct Database<T: hash::Hash + Eq> {
 
     /// In-memory [HashSet] of all items
     pub items: HashSet<T>,
+
+    /// The serialization format used by [Database::dump_db], which is bincode
+    /// by default. This is set to the format of the file when loading with
+    /// [Database::from].
+    #[serde(skip)]
+    pub format: codec::Format,
+
+    /// The compression applied to the encoded database by [Database::dump_db],
+    /// which is none by default. This is set to the compression of the file
+    /// when loading with [Database::from].
+    #[serde(skip)]
+    pub compression: compression::Compression,
+
+    /// Identifier of the layout of the items stored, written into dumps by
+    /// [Database::dump_db] so that loading can be checked against it with
+    /// [Database::check_schema]. This is `0` by default, which declares no
+    /// schema, and is set to the schema of the file when loading.
+    ///
+    /// TinyDB never checks this by itself, so pick a new identifier whenever
+    /// the item type changes in a way older dumps can't be decoded as.
+    #[serde(skip)]
+    pub schema: u64,
+
+    /// Write-ahead log and encryption key, see [Database::enable_wal] and
+    /// `Database::set_key`
+    #[serde(skip)]
+    storage: store::Storage,
+
+    /// Secondary indexes kept up to date as items change, see
+    /// [Database::create_index]
+    #[serde(skip, default = "index::Indexes::default")]
+    indexes: index::Indexes<T>,
+}
+
+impl<T: hash::Hash + Eq + Clone> Clone for Database<T> {
+    /// Clones the database, leaving the clone without a write-ahead log so that
+    /// it can't append to the same log as the original.
+    fn clone(&self) -> Self {
+        Database {
+            label: self.label.clone(),
+            save_path: self.save_path.clone(),
+            strict_dupes: self.strict_dupes,
+            items: self.items.clone(),
+            format: self.format,
+            compression: self.compression,
+            schema: self.schema,
+            storage: self.storage.clone(),
+            indexes: self.indexes.clone(),
+        }
+    }
+}
+
+impl<T: hash::Hash + Eq> PartialEq for Database<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.label == other.label
+            && self.save_path == other.save_path
+            && self.strict_dupes == other.strict_dupes
+            && self.items == other.items
+            && self.format == other.format
+            && self.compression == other.compression
+    }
 }
 
+impl<T: hash::Hash + Eq> Eq for Database<T> {}
+
 impl<T: hash::Hash + Eq + Serialize + DeserializeOwned> Database<T> {
     /// Creates a new database instance from given parameters.
     ///
@@ -129,13 +243,21 @@ impl<T: hash::Hash + Eq + Serialize + DeserializeOwned> Database<T> {
             save_path: save_path.into(),
             strict_dupes,
             items: HashSet::new(),
+            format: codec::Format::default(),
+            compression: compression::Compression::default(),
+            schema: 0,
+            storage: store::Storage::default(),
+            indexes: index::Indexes::default(),
         }
     }
 
     /// Creates a database from a `.tinydb` file.
     ///
     /// This retrives a dump file (saved database) from the path given and loads
-    /// it as the [Database] structure.
+    /// it as the [Database] structure. Items are decoded straight from the file
+    /// as it is read rather than reading the whole file into memory first,
+    /// unless it is encrypted or in [codec::Format::Ron], which can only be
+    /// parsed from memory.
     ///
     /// # Examples
     ///
@@ -171,11 +293,226 @@ impl<T: hash::Hash + Eq + Serialize + DeserializeOwned> Database<T> {
     ///     ); // Check that the database still has added [ExampleStruct].
     /// }
     /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::NotTinyDb] if the file isn't a dump
+    /// or [error::DatabaseError::UnsupportedVersion] if it was written with an
+    /// unknown format version. Files dumped before headers were added are
+    /// rejected as not being a dump, load them with [Database::from_legacy]
+    /// instead. Dumps of a [keyed::KeyedDatabase] are rejected with
+    /// [error::DatabaseError::ItemTypeMismatch], but the item type itself
+    /// isn't recorded in the file, so use
+    /// [Database::schema] and [Database::check_schema] to guard against
+    /// loading a dump of different items.
+    ///
+    /// If the file's checksum doesn't match its contents this will return
+    /// [error::DatabaseError::ChecksumMismatch], otherwise if it is truncated or
+    /// corrupted in a way which can't be decoded this will return
+    /// [error::DatabaseError::DecodeFailed] containing the offset where decoding
+    /// failed.
+    ///
+    /// # Write-ahead log
+    ///
+    /// If a write-ahead log exists next to `path` (see [Database::enable_wal]),
+    /// every intact mutation inside of it is replayed on top of the loaded
+    /// database. This is the case even if the dump was copied or renamed since
+    /// it was written, as long as its log was too.
+    ///
+    /// # Encryption
+    ///
+    /// Encrypted files can't be loaded with this method and will return
+    /// [error::DatabaseError::KeyRequired], use `Database::from_with_key`
+    /// instead.
     pub fn from(path: impl Into<PathBuf>) -> Result<Self, error::DatabaseError> {
-        let stream = get_stream_from_path(path.into())?;
-        let decoded: Database<T> = bincode::deserialize(&stream[..]).unwrap();
+        Store::load::<codec::Bincode>(path.into(), None, false)
+    }
+
+    /// Creates a database from a `.tinydb` file in the same way as
+    /// [Database::from], but also accepting files dumped before headers were
+    /// added, which are decoded as bare bincode. Dumping the loaded database
+    /// again upgrades the file to have a header.
+    ///
+    /// As files without a header can't be told apart from any other file, only
+    /// use this for paths known to hold a TinyDB dump.
+    ///
+    /// # Errors
+    ///
+    /// Will return the same errors as [Database::from], other than
+    /// [error::DatabaseError::NotTinyDb]. Files without a header which can't
+    /// be decoded return [error::DatabaseError::DecodeFailed] instead.
+    pub fn from_legacy(path: impl Into<PathBuf>) -> Result<Self, error::DatabaseError> {
+        Store::load::<codec::Bincode>(path.into(), None, true)
+    }
+
+    /// Creates a database from a `.tinydb` file dumped with a user [codec::Codec]
+    /// by [Database::dump_with_codec], or with any built-in format as with
+    /// [Database::from]. See [codec::Codec] for an example.
+    ///
+    /// # Errors
+    ///
+    /// Will return the same errors as [Database::from], including
+    /// [error::DatabaseError::UnsupportedFormat] if the file was dumped with a
+    /// different user codec.
+    pub fn from_with_codec<C: codec::Codec>(
+        path: impl Into<PathBuf>,
+    ) -> Result<Self, error::DatabaseError> {
+        Store::load::<C>(path.into(), None, false)
+    }
+
+    /// Creates a database from an encrypted `.tinydb` file, decrypting it and
+    /// its write-ahead log with the key given by `provider`.
+    ///
+    /// The provider is kept on the loaded database so it is encrypted again
+    /// when dumped, see [Database::set_key]. Files which aren't encrypted are
+    /// loaded as with [Database::from].
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::DecryptionFailed] if the key is wrong
+    /// or the file has been tampered with, alongside the errors of
+    /// [Database::from].
+    #[cfg(feature = "encryption")]
+    pub fn from_with_key(
+        path: impl Into<PathBuf>,
+        provider: impl encryption::KeyProvider + 'static,
+    ) -> Result<Self, error::DatabaseError> {
+        Store::load_with_key(path.into(), Arc::new(provider))
+    }
+
+    /// Creates a database from a dump read from any `reader`, such as an
+    /// in-memory buffer or a socket, which [Database::from] is layered on top
+    /// of.
+    ///
+    /// All of `reader` is read into memory before being decoded so that the
+    /// size of the dump is known, meaning it should end where the dump does.
+    /// Unlike [Database::from], no write-ahead log is replayed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tinydb::Database;
+    ///
+    /// fn main() {
+    ///     let mut db = Database::new("stream_example", None, false);
+    ///     db.add_item(String::from("Ace")).unwrap();
+    ///
+    ///     let mut buffer = vec![];
+    ///     db.dump_to(&mut buffer).unwrap();
+    ///
+    ///     let loaded: Database<String> = Database::load_from(&buffer[..]).unwrap();
+    ///     assert_eq!(loaded, db);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return the same errors as [Database::from] other than
+    /// [error::DatabaseError::DatabaseNotFound], without a path inside of
+    /// [error::DatabaseError::DecodeFailed].
+    pub fn load_from<R: Read>(reader: R) -> Result<Self, error::DatabaseError> {
+        Store::load_stream(reader, &None)
+    }
+
+    /// Creates a database from an encrypted dump read from any `reader`,
+    /// decrypting it with the key given by `provider`. See
+    /// [Database::load_from] and [Database::from_with_key].
+    #[cfg(feature = "encryption")]
+    pub fn load_from_with_key<R: Read>(
+        reader: R,
+        provider: impl encryption::KeyProvider + 'static,
+    ) -> Result<Self, error::DatabaseError> {
+        let provider: Arc<dyn encryption::KeyProvider> = Arc::new(provider);
+        let mut db: Self = Store::load_stream(reader, &Some(provider.key()?))?;
+        db.storage.set_key(provider);
 
-        Ok(decoded)
+        Ok(db)
+    }
+
+    /// Checks that the database was loaded from a dump of the given `schema`,
+    /// see [Database::schema]. Dumps which didn't declare a schema, including
+    /// those written before schemas were added, always pass.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tinydb::Database;
+    ///
+    /// fn main() {
+    ///     let mut db: Database<String> = Database::new("schema_example", None, false);
+    ///     db.schema = 2;
+    ///     db.dump_db().unwrap();
+    ///
+    ///     let loaded: Database<String> = Database::from("schema_example.tinydb").unwrap();
+    ///     assert!(loaded.check_schema(2).is_ok());
+    ///     assert!(loaded.check_schema(3).is_err());
+    ///     # std::fs::remove_file("schema_example.tinydb").unwrap();
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::ItemTypeMismatch] if the dump
+    /// declared a different schema.
+    pub fn check_schema(&self, schema: u64) -> Result<(), error::DatabaseError> {
+        check_schema(self.schema, schema)
+    }
+
+    /// Decodes the items dumped to `path` one at a time and passes each to
+    /// `f`, without building a [Database] or holding every item in memory at
+    /// once. This is useful for one-pass processing of large databases.
+    ///
+    /// Items are passed on as soon as they are decoded, so if the file turns
+    /// out to be corrupted an error is only returned after some (or all) of
+    /// them have been passed to `f`. Write-ahead logs aren't replayed and
+    /// encrypted files can't be read this way.
+    ///
+    /// Not every [codec::Format] can be decoded an item at a time: RON
+    /// payloads are read into memory whole before being decoded, and CBOR
+    /// items are all decoded before any are passed on.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tinydb::Database;
+    ///
+    /// fn main() {
+    ///     let mut db = Database::new("for_each_example", None, false);
+    ///     db.add_item(String::from("Bodhisattva")).unwrap();
+    ///     db.add_item(String::from("Legion")).unwrap();
+    ///     db.dump_db().unwrap();
+    ///
+    ///     let mut total_len = 0;
+    ///     Database::for_each_item("for_each_example.tinydb", |item: String| {
+    ///         total_len += item.len()
+    ///     })
+    ///     .unwrap();
+    ///
+    ///     assert_eq!(total_len, 17);
+    ///     # std::fs::remove_file("for_each_example.tinydb").unwrap();
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return the same errors as [Database::from].
+    pub fn for_each_item(
+        path: impl Into<PathBuf>,
+        f: impl FnMut(T),
+    ) -> Result<(), error::DatabaseError> {
+        let path = path.into();
+        let (file, len) = open_path(&path)?;
+
+        read_dump(
+            BufReader::new(file),
+            header::Kind::Plain,
+            false,
+            len,
+            &None,
+            |format, payload, offset, limit, _| format.decode_items(payload, offset, limit, f),
+        )
+        .map(|_| ())
+        .map_err(|e| e.with_path(&path))
     }
 
     /// Loads database from existant path or creates a new one if it doesn't already
@@ -192,6 +529,12 @@ impl<T: hash::Hash + Eq + Serialize + DeserializeOwned> Database<T> {
     /// name of `y`, not `x` so therefore it is recommended to have a database
     /// path with `x.tinydb` or `x.db` only.
     ///
+    /// Existing files are validated in the same way as [Database::from], and
+    /// a write-ahead log left next to a path which hasn't been dumped to yet is
+    /// still replayed. New databases use the [codec::Format] matching the
+    /// path's extension (e.g. `x.json` with the `json` feature), falling back
+    /// to bincode.
+    ///
     /// # Examples
     ///
     /// ```rust
@@ -219,21 +562,9 @@ impl<T: hash::Hash + Eq + Serialize + DeserializeOwned> Database<T> {
         path: impl Into<PathBuf>,
         strict_dupes: bool,
     ) -> Result<Self, error::DatabaseError> {
-        let path_into = path.into();
-
-        if path_into.exists() {
-            Database::from(path_into)
-        } else {
-            let db_name = match path_into.file_stem() {
-                Some(x) => match x.to_str() {
-                    Some(y) => String::from(y),
-                    None => return Err(error::DatabaseError::BadDbName),
-                },
-                None => return Err(error::DatabaseError::BadDbName),
-            };
-
-            Ok(Database::new(db_name, Some(path_into), strict_dupes))
-        }
+        Store::auto_load(path.into(), |label, path| {
+            Database::new(label, Some(path), strict_dupes)
+        })
     }
 
     /// Adds a new item to the in-memory database.
@@ -241,15 +572,28 @@ impl<T: hash::Hash + Eq + Serialize + DeserializeOwned> Database<T> {
     /// If this is the first item added to the database, please ensure it's the
     /// only type you'd like to add. Due to generics, the first item you add
     /// will be set as the type to use (unless removed).
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::DupeFound] if the item already exists
+    /// and [Database::strict_dupes] is enabled, or
+    /// [error::DatabaseError::UniqueViolation] if it breaks a unique constraint
+    /// (see [Database::create_unique_constraint]).
     pub fn add_item(&mut self, item: T) -> Result<(), error::DatabaseError> {
-        if self.strict_dupes {
-            if self.items.contains(&item) {
-                return Err(error::DatabaseError::DupeFound);
-            }
+        if self.items.contains(&item) {
+            return if self.strict_dupes {
+                Err(error::DatabaseError::DupeFound)
+            } else {
+                Ok(())
+            };
         }
 
+        self.indexes.check(&item, None)?;
+        self.log(&wal::Record::Add(&item))?;
+        self.indexes.insert_item(&item);
         self.items.insert(item);
-        return Ok(());
+
+        Ok(())
     }
 
     /// Replaces an item inside of the database with another
@@ -257,9 +601,27 @@ impl<T: hash::Hash + Eq + Serialize + DeserializeOwned> Database<T> {
     ///
     /// [Database::query_item] can be used in conjunction to find and replace
     /// values individually if needed.
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::ItemNotFound] if `item` isn't in the
+    /// database, [error::DatabaseError::DupeFound] if `new` already is and
+    /// [Database::strict_dupes] is enabled or
+    /// [error::DatabaseError::UniqueViolation] if `new` breaks a unique
+    /// constraint, leaving the database untouched.
     pub fn update_item(&mut self, item: &T, new: T) -> Result<(), error::DatabaseError> {
-        self.remove_item(item)?;
-        self.add_item(new)?;
+        if !self.items.contains(item) {
+            return Err(error::DatabaseError::ItemNotFound);
+        } else if self.strict_dupes && &new != item && self.items.contains(&new) {
+            return Err(error::DatabaseError::DupeFound);
+        }
+
+        self.indexes.check(&new, Some(item))?;
+        self.log(&wal::Record::Update(item, &new))?;
+        self.indexes.remove_item(item);
+        self.indexes.insert_item(&new);
+        self.items.remove(item);
+        self.items.insert(new);
 
         Ok(())
     }
@@ -274,11 +636,15 @@ impl<T: hash::Hash + Eq + Serialize + DeserializeOwned> Database<T> {
     /// Will return [error::DatabaseError::ItemNotFound] if the item that is attempting
     /// to be deleted was not found.
     pub fn remove_item(&mut self, item: &T) -> Result<(), error::DatabaseError> {
-        if self.items.remove(item) {
-            Ok(())
-        } else {
-            Err(error::DatabaseError::ItemNotFound)
+        if !self.items.contains(item) {
+            return Err(error::DatabaseError::ItemNotFound);
         }
+
+        self.log(&wal::Record::Remove(item))?;
+        self.indexes.remove_item(item);
+        self.items.remove(item);
+
+        Ok(())
     }
 
     /// Dumps/saves database to a binary file.
@@ -291,11 +657,182 @@ impl<T: hash::Hash + Eq + Serialize + DeserializeOwned> Database<T> {
     ///
     /// You can also overwrite this behaviour by defining a [Database::save_path]
     /// when generating the database inside of [Database::new].
+    ///
+    /// # Crash safety
+    ///
+    /// The dump is first written to a hidden temporary file next to the final
+    /// path which is then synced and atomically renamed over it, so a crash
+    /// mid-dump will leave the previously dumped database intact.
+    ///
+    /// # File layout
+    ///
+    /// Every dump starts with a fixed [header::Header] identifying it as a
+    /// TinyDB file along with its format version, [Database::schema],
+    /// [Database::format] and [Database::compression]. This is followed by the
+    /// encoded (and possibly compressed) database itself and a checksum of the
+    /// whole file which is verified when loading it or with [Database::verify].
+    ///
+    /// If a key has been set with `Database::set_key`, the encoded database is
+    /// encrypted with it after being compressed.
     pub fn dump_db(&self) -> Result<(), error::DatabaseError> {
-        let mut dump_file = self.open_db_path()?;
-        bincode::serialize_into(&mut dump_file, self).unwrap();
+        self.dump_db_as::<codec::Bincode>(self.format)
+    }
 
-        Ok(())
+    /// Dumps the database in the same way as [Database::dump_db], but encoded
+    /// with the user [codec::Codec] `C` rather than [Database::format]. See
+    /// [codec::Codec] for an example.
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::UnsupportedFormat] if the codec's
+    /// identifier isn't a built-in format or one of [codec::USER_IDS].
+    pub fn dump_with_codec<C: codec::Codec>(&self) -> Result<(), error::DatabaseError> {
+        self.dump_db_as::<C>(codec::Format::from_id(C::ID)?)
+    }
+
+    /// Dumps the database to any `writer`, such as an in-memory buffer or a
+    /// socket, in the same layout as [Database::dump_db] which is layered on
+    /// top of this.
+    ///
+    /// The writer is flushed once the whole dump has been written, see
+    /// [Database::load_from] for an example.
+    pub fn dump_to<W: Write>(&self, writer: W) -> Result<(), error::DatabaseError> {
+        Store::dump_to(self, writer)
+    }
+
+    /// Sets the key provider used to encrypt the database whenever it is
+    /// dumped, along with its write-ahead log.
+    ///
+    /// The provider is asked for its key on every dump so it can fetch keys
+    /// from elsewhere, or a plain [encryption::Key] can be given. See the
+    /// [encryption] module for details.
+    ///
+    /// If the write-ahead log is enabled, it is first folded into a dump under
+    /// the previous key with [Database::checkpoint]. The database is then
+    /// dumped again under the new key and the log reopened with it, so that
+    /// the dump and log on disk are never sealed with different keys.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tinydb::encryption::Key;
+    /// use tinydb::Database;
+    ///
+    /// fn main() {
+    ///     let key = Key::generate();
+    ///
+    ///     let mut db = Database::new("encrypted_example", None, false);
+    ///     db.set_key(key.clone()).unwrap();
+    ///     db.add_item(String::from("Kryten")).unwrap();
+    ///     db.dump_db().unwrap();
+    ///
+    ///     let loaded: Database<String> = Database::from_with_key("encrypted_example.tinydb", key).unwrap();
+    ///     assert!(loaded.contains(&String::from("Kryten")));
+    ///     # std::fs::remove_file("encrypted_example.tinydb").unwrap();
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return the errors of [Database::checkpoint] and
+    /// [Database::enable_wal] if the write-ahead log is enabled. If reopening
+    /// the log fails it is left disabled.
+    #[cfg(feature = "encryption")]
+    pub fn set_key(
+        &mut self,
+        provider: impl encryption::KeyProvider + 'static,
+    ) -> Result<(), error::DatabaseError> {
+        self.replace_key(Some(Arc::new(provider)))
+    }
+
+    /// Removes the key provider so future dumps are no longer encrypted,
+    /// folding the write-ahead log away in the same way as
+    /// [Database::set_key].
+    #[cfg(feature = "encryption")]
+    pub fn remove_key(&mut self) -> Result<(), error::DatabaseError> {
+        self.replace_key(None)
+    }
+
+    /// Verifies the integrity of a dumped database without loading it, checking
+    /// its header, checksum and that every item can be decoded.
+    ///
+    /// Unlike [Database::from], items are decoded and immediately discarded
+    /// rather than collected into a [HashSet], and the checksum is checked as
+    /// the file is read, so only as much of it is held in memory as
+    /// [Database::for_each_item] would. This will only return an error if the
+    /// file could not be read at all; any problems with its contents are
+    /// reported inside of the returned [verify::VerifyReport].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tinydb::Database;
+    ///
+    /// fn main() {
+    ///     let mut db = Database::new("verify_example", None, false);
+    ///     db.add_item(String::from("Holly")).unwrap();
+    ///     db.dump_db().unwrap();
+    ///
+    ///     let report = Database::<String>::verify("verify_example.tinydb").unwrap();
+    ///
+    ///     assert!(report.is_ok());
+    ///     # std::fs::remove_file("verify_example.tinydb").unwrap();
+    /// }
+    /// ```
+    pub fn verify(path: impl Into<PathBuf>) -> Result<verify::VerifyReport, error::DatabaseError> {
+        let path = path.into();
+        let (file, len) = open_path(&path)?;
+
+        verify::verify_reader::<T, _>(&path, BufReader::new(file), len)
+    }
+
+    /// Enables the write-ahead log, appending every following mutation to a log
+    /// next to the file the database was loaded from, or the path it dumps to
+    /// if it wasn't loaded from one (e.g. `x.tinydb.wal` for `x.tinydb`), so
+    /// that they survive a crash before the next dump.
+    ///
+    /// Loading the database with [Database::from] or [Database::auto_from]
+    /// replays the log on top of the last dump, and [Database::checkpoint]
+    /// folds it into a fresh dump. The `policy` decides how often the log is
+    /// synced to disk.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tinydb::{wal::SyncPolicy, Database};
+    ///
+    /// fn main() {
+    ///     let mut db = Database::new("wal_example", None, false);
+    ///     db.enable_wal(SyncPolicy::Always).unwrap();
+    ///
+    ///     db.add_item(String::from("Starbug")).unwrap(); // logged, not dumped
+    ///
+    ///     let recovered: Database<String> = Database::auto_from("wal_example.tinydb", false).unwrap();
+    ///     assert!(recovered.contains(&String::from("Starbug")));
+    ///
+    ///     db.checkpoint().unwrap();
+    ///     # std::fs::remove_file("wal_example.tinydb").unwrap();
+    ///     # std::fs::remove_file("wal_example.tinydb.wal").unwrap();
+    /// }
+    /// ```
+    pub fn enable_wal(&mut self, policy: wal::SyncPolicy) -> Result<(), error::DatabaseError> {
+        Store::enable_wal(self, policy)
+    }
+
+    /// Dumps the database in the same way as [Database::dump_db] and then
+    /// empties the write-ahead log, as all of its mutations are now part of
+    /// the dump. The dump is written to the file the log belongs to (see
+    /// [Database::enable_wal]), so that the two are never out of step.
+    ///
+    /// This is the same as [Database::dump_db] if the log isn't enabled.
+    pub fn checkpoint(&mut self) -> Result<(), error::DatabaseError> {
+        Store::checkpoint(self)
+    }
+
+    /// Syncs every mutation appended to the write-ahead log so far to disk,
+    /// regardless of its [wal::SyncPolicy].
+    pub fn sync_wal(&mut self) -> Result<(), error::DatabaseError> {
+        Store::sync_wal(self)
     }
 
     /// Query the database for a specific item.
@@ -382,6 +919,7 @@ impl<T: hash::Hash + Eq + Serialize + DeserializeOwned> Database<T> {
     ///     assert_eq!(results.unwrap().len(), 2);
     /// }
     /// ```
+    #[allow(clippy::len_zero)]
     pub fn query<Q: PartialEq, V: Fn(&T) -> &Q>(
         &self,
         value: V,
@@ -401,280 +939,3284 @@ impl<T: hash::Hash + Eq + Serialize + DeserializeOwned> Database<T> {
         Err(error::DatabaseError::ItemNotFound)
     }
 
-    /// Searches the database for a specific value. If it does not exist, this
-    /// method will return [error::DatabaseError::ItemNotFound].
+    /// Query the database for all items matching `predicate`, for queries
+    /// which can't be written as [Database::query]. Items are returned in no
+    /// particular order.
     ///
-    /// This is a wrapper around [HashSet::contains].
+    /// This scans every item, see [Database::query_indexed_where] and
+    /// [Database::query_range_where] to narrow the items down with an index
+    /// first.
     ///
     /// # Examples
     ///
     /// ```rust
-    /// use tinydb::Database;
     /// use serde::{Serialize, Deserialize};
+    /// use tinydb::Database;
     ///
-    /// #[derive(Hash, Eq, PartialEq, Serialize, Deserialize, Copy, Clone)]
+    /// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
     /// struct ExampleStruct {
-    ///     item: i32
+    ///     name: String,
+    ///     age: i32,
     /// }
     ///
     /// fn main() {
-    ///     let exp_struct = ExampleStruct { item: 4942 };
-    ///     let mut db = Database::new("Contains example", None, false);
+    ///     let mut my_db = Database::new("query_where_test", None, false);
     ///
-    ///     db.add_item(exp_struct.clone());
+    ///     my_db.add_item(ExampleStruct { name: "Alice".into(), age: 34 });
+    ///     my_db.add_item(ExampleStruct { name: "Adam".into(), age: 25 });
+    ///     my_db.add_item(ExampleStruct { name: "Bob".into(), age: 41 });
     ///
-    ///     assert_eq!(db.contains(&exp_struct), true);
+    ///     let results = my_db
+    ///         .query_where(|s: &ExampleStruct| s.name.starts_with('A') && s.age > 30)
+    ///         .unwrap();
+    ///
+    ///     assert_eq!(results[0].name, "Alice");
+    ///     assert_eq!(my_db.count_where(|s| s.age > 30), 2);
     /// }
     /// ```
-    pub fn contains(&self, query: &T) -> bool {
-        self.items.contains(query)
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::ItemNotFound] if no items match.
+    pub fn query_where<P: Fn(&T) -> bool>(
+        &self,
+        predicate: P,
+    ) -> Result<Vec<&T>, error::DatabaseError> {
+        let items: Vec<&T> = self.items.iter().filter(|item| predicate(item)).collect();
+
+        if items.is_empty() {
+            return Err(error::DatabaseError::ItemNotFound);
+        }
+
+        Ok(items)
     }
 
-    /// Returns the number of database entries
-    /// method will return i32.
-    ///
+    /// Query the database with a query written in the [lang] query language,
+    /// comparing fields of items by the names they are serialized with. This
+    /// lets queries be written at runtime, e.g. typed into an admin tool.
     ///
     /// # Examples
     ///
     /// ```rust
-    /// use tinydb::Database;
     /// use serde::{Serialize, Deserialize};
+    /// use tinydb::Database;
     ///
-    /// #[derive(Hash, Eq, PartialEq, Serialize, Deserialize, Copy, Clone)]
+    /// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
     /// struct ExampleStruct {
-    ///     item: i32
+    ///     name: String,
+    ///     age: i32,
     /// }
     ///
     /// fn main() {
-    ///     let exp_struct = ExampleStruct { item: 4942 };
-    ///     let mut db = Database::new("Contains example", None, false);
+    ///     let mut my_db = Database::new("query_str_test", None, false);
     ///
-    ///     db.add_item(exp_struct.clone());
+    ///     my_db.add_item(ExampleStruct { name: "Lister".into(), age: 34 });
+    ///     my_db.add_item(ExampleStruct { name: "Lisa".into(), age: 25 });
+    ///     my_db.add_item(ExampleStruct { name: "Rimmer".into(), age: 41 });
     ///
-    ///     assert_eq!(db.len(), 1);
+    ///     let results = my_db.query_str(r#"age > 30 and name ~ "Li*""#).unwrap();
+    ///
+    ///     assert_eq!(results[0].name, "Lister");
     /// }
     /// ```
-    pub fn len(&self) -> i32 {
-        self.items.len() as i32
-    }
-
-    /// Opens the path given in [Database::save_path] (or auto-generates a path).
-    fn open_db_path(&self) -> Result<File, error::DatabaseError> {
-        let definate_path = self.smart_path_get();
-
-        if definate_path.exists() {
-            std::fs::remove_file(&definate_path)?;
-        }
-
-        Ok(File::create(&definate_path)?)
-    }
-
-    /// Automatically allocates a path for the database if [Database::save_path]
-    /// is not provided. If it is, this function will simply return it.
-    fn smart_path_get(&self) -> PathBuf {
-        if self.save_path.is_none() {
-            return PathBuf::from(format!("{}.tinydb", self.label));
-        }
-
-        PathBuf::from(self.save_path.as_ref().unwrap())
-    }
-}
-
-/// Reads a given path and converts it into a [Vec]<[u8]> stream.
-fn get_stream_from_path(path: PathBuf) -> Result<Vec<u8>, error::DatabaseError> {
-    if !path.exists() {
-        return Err(error::DatabaseError::DatabaseNotFound);
+    ///
+    /// # Performance
+    ///
+    /// Queries can't use indexes, so every query converts every item of the
+    /// database into a [value::Value] before comparing it, which is much
+    /// slower than a closure given to [Database::query]. Prefer that or an
+    /// index for queries on hot paths.
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::QueryParse] if the query is
+    /// malformed or [error::DatabaseError::ItemNotFound] if no items match.
+    ///
+    /// Will return [error::DatabaseError::EncodeFailed] if an item can't be
+    /// converted into a [value::Value], such as one holding a map with keys
+    /// that aren't strings, numbers, bools or chars, as whether it matches
+    /// can't be known.
+    pub fn query_str(&self, query: &str) -> Result<Vec<&T>, error::DatabaseError> {
+        self.query_expr(&lang::Expr::parse(query)?)
     }
 
-    let mut file = File::open(path)?;
-    let mut buffer = Vec::new();
-
-    file.read_to_end(&mut buffer)?;
-
-    Ok(buffer)
+    /// Query the database for all items whose field at `path` compares to
+    /// `value` with `op`, where `path` names fields as they are serialized and
+    /// can reach into nested ones such as `address.city` (see
+    /// [value::Value::get_path]).
+    ///
+    /// Unlike [Database::query] this doesn't need a closure written for the
+    /// item type, so generic tools can query any database by field name.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde::{Serialize, Deserialize};
+    /// use tinydb::{lang::Op, Database};
+    ///
+    /// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+    /// struct Address {
+    ///     city: String,
+    /// }
+    ///
+    /// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+    /// struct ExampleStruct {
+    ///     name: String,
+    ///     address: Address,
+    /// }
+    ///
+    /// fn main() {
+    ///     let mut my_db = Database::new("query_field_test", None, false);
+    ///
+    ///     my_db.add_item(ExampleStruct {
+    ///         name: "Lister".into(),
+    ///         address: Address { city: "Liverpool".into() },
+    ///     });
+    ///
+    ///     let results = my_db.query_field("address.city", Op::Eq, "Liverpool").unwrap();
+    ///
+    ///     assert_eq!(results[0].name, "Lister");
+    /// }
+    /// ```
+    ///
+    /// # Performance
+    ///
+    /// Like [Database::query_str], this converts every item into a
+    /// [value::Value] on each call.
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::ItemNotFound] if no items match or
+    /// [error::DatabaseError::EncodeFailed] if an item can't be converted into
+    /// a [value::Value].
+    pub fn query_field(
+        &self,
+        path: &str,
+        op: lang::Op,
+        value: impl Into<value::Value>,
+    ) -> Result<Vec<&T>, error::DatabaseError> {
+        self.query_expr(&lang::Expr::Compare {
+            field: path.into(),
+            op,
+            value: value.into(),
+        })
+    }
+
+    /// Query the database for all items matching `expr`.
+    fn query_expr(&self, expr: &lang::Expr) -> Result<Vec<&T>, error::DatabaseError> {
+        let mut items = vec![];
+        for item in self.items.iter() {
+            if expr.matches(item)? {
+                items.push(item);
+            }
+        }
+
+        if items.is_empty() {
+            return Err(error::DatabaseError::ItemNotFound);
+        }
+
+        Ok(items)
+    }
+
+    /// Finds the first item matching `predicate`, which is any matching item
+    /// as items are stored in no particular order.
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::ItemNotFound] if no items match.
+    pub fn find_where<P: Fn(&T) -> bool>(&self, predicate: P) -> Result<&T, error::DatabaseError> {
+        self.items
+            .iter()
+            .find(|item| predicate(item))
+            .ok_or(error::DatabaseError::ItemNotFound)
+    }
+
+    /// Starts building a [query::Query] over every item, which can be
+    /// filtered, sorted and paged through before being iterated over in the
+    /// same order every time.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde::{Serialize, Deserialize};
+    /// use tinydb::Database;
+    ///
+    /// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+    /// struct ExampleStruct {
+    ///     name: String,
+    ///     age: i32,
+    /// }
+    ///
+    /// fn main() {
+    ///     let mut my_db = Database::new("select_test", None, false);
+    ///
+    ///     my_db.add_item(ExampleStruct { name: "Alice".into(), age: 34 });
+    ///     my_db.add_item(ExampleStruct { name: "Adam".into(), age: 25 });
+    ///     my_db.add_item(ExampleStruct { name: "Bob".into(), age: 41 });
+    ///
+    ///     let names: Vec<&str> = my_db
+    ///         .select()
+    ///         .filter(|s| s.name.starts_with('A'))
+    ///         .order_by(|s| s.age)
+    ///         .map(|s| s.name.as_str())
+    ///         .collect();
+    ///
+    ///     assert_eq!(names, ["Adam", "Alice"]);
+    /// }
+    /// ```
+    pub fn select(&self) -> query::Query<'_, T> {
+        query::Query::new(&self.items)
+    }
+
+    /// Counts the items matching `predicate`.
+    pub fn count_where<P: Fn(&T) -> bool>(&self, predicate: P) -> usize {
+        self.items.iter().filter(|item| predicate(item)).count()
+    }
+
+    /// Returns `true` if any item matches `predicate`, stopping at the first
+    /// one found.
+    pub fn exists_where<P: Fn(&T) -> bool>(&self, predicate: P) -> bool {
+        self.items.iter().any(predicate)
+    }
+
+    /// Creates a secondary index called `name`, mapping the key `extractor`
+    /// returns for each item to the items themselves. This replaces any index
+    /// with the same name.
+    ///
+    /// The index is built from the current items and kept up to date by
+    /// [Database::add_item], [Database::update_item] and
+    /// [Database::remove_item], but not by changes made directly to
+    /// [Database::items]. Indexes hold a clone of every item and aren't
+    /// dumped, so they have to be created again after loading a database.
+    ///
+    /// See [Database::query_indexed] to look items up by key.
+    pub fn create_index<K, F>(&mut self, name: impl Into<String>, extractor: F)
+    where
+        T: Clone + Send + Sync + 'static,
+        K: hash::Hash + Eq + Clone + Send + Sync + 'static,
+        F: Fn(&T) -> K + Send + Sync + 'static,
+    {
+        let index = index::HashIndex::new(Arc::new(extractor), self.indexes.share(&self.items));
+        self.indexes.insert_index(name.into(), Box::new(index));
+    }
+
+    /// Creates a unique constraint called `name`, rejecting any item added or
+    /// updated whose key from `extractor` is the same as a different existing
+    /// item's. This replaces any index or constraint with the same name.
+    ///
+    /// Unlike [Database::strict_dupes] which only rejects equal items, this
+    /// allows e.g. rejecting two users with the same email but different names.
+    /// Constraints are maintained in the same way as [Database::create_index]
+    /// and share names with indexes, so they are removed with
+    /// [Database::drop_index].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde::{Serialize, Deserialize};
+    /// use tinydb::Database;
+    ///
+    /// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+    /// struct User {
+    ///     name: String,
+    ///     email: String,
+    /// }
+    ///
+    /// fn main() {
+    ///     let mut my_db = Database::new("unique_test", None, false);
+    ///     my_db.create_unique_constraint("email", |u: &User| u.email.clone()).unwrap();
+    ///
+    ///     let lister = User { name: "Lister".into(), email: "dave@jmc.com".into() };
+    ///     my_db.add_item(lister.clone()).unwrap();
+    ///
+    ///     let error = my_db
+    ///         .add_item(User { name: "Ace".into(), email: "dave@jmc.com".into() })
+    ///         .unwrap_err();
+    ///
+    ///     assert_eq!(error.existing_item::<User>(), Some(&lister));
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::UniqueViolation] if existing items
+    /// already break the constraint, in which case it isn't created.
+    pub fn create_unique_constraint<K, F>(
+        &mut self,
+        name: impl Into<String>,
+        extractor: F,
+    ) -> Result<(), error::DatabaseError>
+    where
+        T: Clone + Send + Sync + 'static,
+        K: hash::Hash + Eq + Clone + Send + Sync + 'static,
+        F: Fn(&T) -> K + Send + Sync + 'static,
+    {
+        let name = name.into();
+        let index = index::UniqueIndex::new(
+            name.clone(),
+            Arc::new(extractor),
+            self.indexes.share(&self.items),
+        )?;
+        self.indexes.insert_index(name, Box::new(index));
+
+        Ok(())
+    }
+
+    /// Removes the index or unique constraint called `name`, returning `true`
+    /// if it existed. This includes a persisted text index loaded from a dump
+    /// which hasn't been created again (see [Database::persist_text_index]).
+    pub fn drop_index(&mut self, name: &str) -> bool {
+        self.indexes.remove_index(name)
+    }
+
+    /// Query the database for all items with the given `key`, using the index
+    /// called `name` if it exists (see [Database::create_index]) or otherwise
+    /// scanning every item with `extractor` like [Database::query].
+    ///
+    /// The index is only used if its keys are of the same type as `key`, and
+    /// `extractor` should return the same keys as the one the index was
+    /// created with so that both ways find the same items.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde::{Serialize, Deserialize};
+    /// use tinydb::Database;
+    ///
+    /// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+    /// struct ExampleStruct {
+    ///     uuid: String,
+    ///     age: i32,
+    /// }
+    ///
+    /// fn main() {
+    ///     let mut my_db = Database::new("query_indexed_test", None, false);
+    ///     my_db.create_index("age", |s: &ExampleStruct| s.age);
+    ///
+    ///     my_db.add_item(ExampleStruct { uuid: "test1".into(), age: 20 });
+    ///     my_db.add_item(ExampleStruct { uuid: "test2".into(), age: 20 });
+    ///     my_db.add_item(ExampleStruct { uuid: "test3".into(), age: 18 });
+    ///
+    ///     let results = my_db.query_indexed("age", |s: &ExampleStruct| s.age, 20);
+    ///
+    ///     assert_eq!(results.unwrap().len(), 2);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::ItemNotFound] if no items have the
+    /// given key.
+    pub fn query_indexed<K, F>(
+        &self,
+        name: &str,
+        extractor: F,
+        key: K,
+    ) -> Result<Vec<&T>, error::DatabaseError>
+    where
+        T: 'static,
+        K: hash::Hash + Eq + 'static,
+        F: Fn(&T) -> K,
+    {
+        self.query_indexed_where(name, extractor, key, |_| true)
+    }
+
+    /// Query the database for all items with the given `key` which also match
+    /// `predicate`, narrowing the items down with the index called `name`
+    /// before checking them as in [Database::query_indexed].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde::{Serialize, Deserialize};
+    /// use tinydb::Database;
+    ///
+    /// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+    /// struct ExampleStruct {
+    ///     name: String,
+    ///     age: i32,
+    /// }
+    ///
+    /// fn main() {
+    ///     let mut my_db = Database::new("query_indexed_where_test", None, false);
+    ///     my_db.create_index("age", |s: &ExampleStruct| s.age);
+    ///
+    ///     my_db.add_item(ExampleStruct { name: "Alice".into(), age: 34 });
+    ///     my_db.add_item(ExampleStruct { name: "Bob".into(), age: 34 });
+    ///
+    ///     let results = my_db
+    ///         .query_indexed_where("age", |s| s.age, 34, |s| s.name.starts_with('A'))
+    ///         .unwrap();
+    ///
+    ///     assert_eq!(results.len(), 1);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::ItemNotFound] if no items match.
+    pub fn query_indexed_where<K, F, P>(
+        &self,
+        name: &str,
+        extractor: F,
+        key: K,
+        predicate: P,
+    ) -> Result<Vec<&T>, error::DatabaseError>
+    where
+        T: 'static,
+        K: hash::Hash + Eq + 'static,
+        F: Fn(&T) -> K,
+        P: Fn(&T) -> bool,
+    {
+        let items: Vec<&T> = match self.indexes.get::<index::HashIndex<K, T>>(name) {
+            Some(index) => index.get(&key).filter(|item| predicate(item)).collect(),
+            None => self
+                .items
+                .iter()
+                .filter(|item| extractor(item) == key && predicate(item))
+                .collect(),
+        };
+
+        if items.is_empty() {
+            return Err(error::DatabaseError::ItemNotFound);
+        }
+
+        Ok(items)
+    }
+
+    /// Joins the items of this database to the items of `right` which share
+    /// the same key, returning a pair for every match like an SQL inner join.
+    /// Items without a match on the other side are left out.
+    ///
+    /// Keys are found with `left_key` for this database's items and
+    /// `right_key` for those of `right`. If `right` has an index called
+    /// `right_index` with keys of the same type (see [Database::create_index])
+    /// it is used to find matches, otherwise `right` is grouped by
+    /// `right_key` first, so either way every item is only visited once.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde::{Serialize, Deserialize};
+    /// use tinydb::Database;
+    ///
+    /// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+    /// struct Order {
+    ///     customer_id: u32,
+    ///     item: String,
+    /// }
+    ///
+    /// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+    /// struct Customer {
+    ///     id: u32,
+    ///     name: String,
+    /// }
+    ///
+    /// fn main() {
+    ///     let mut orders = Database::new("orders", None, false);
+    ///     let mut customers = Database::new("customers", None, false);
+    ///     customers.create_index("id", |c: &Customer| c.id);
+    ///
+    ///     orders.add_item(Order { customer_id: 1, item: "Curry".into() });
+    ///     orders.add_item(Order { customer_id: 2, item: "Lager".into() });
+    ///     customers.add_item(Customer { id: 1, name: "Lister".into() });
+    ///
+    ///     let joined = orders.join(&customers, "id", |o| o.customer_id, |c| c.id);
+    ///     assert_eq!(joined.len(), 1);
+    ///     assert_eq!(joined[0].1.name, "Lister");
+    ///
+    ///     let left = orders.left_join(&customers, "id", |o| o.customer_id, |c| c.id);
+    ///     assert_eq!(left.len(), 2);
+    /// }
+    /// ```
+    pub fn join<'a, U, K, FL, FR>(
+        &'a self,
+        right: &'a Database<U>,
+        right_index: &str,
+        left_key: FL,
+        right_key: FR,
+    ) -> Vec<(&'a T, &'a U)>
+    where
+        U: hash::Hash + Eq + 'static,
+        K: hash::Hash + Eq + 'static,
+        FL: Fn(&T) -> K,
+        FR: Fn(&U) -> K,
+    {
+        let lookup = index::Lookup::new(
+            right.indexes.get::<index::HashIndex<K, U>>(right_index),
+            &right.items,
+            right_key,
+        );
+
+        self.items
+            .iter()
+            .flat_map(|item| {
+                lookup
+                    .get(&left_key(item))
+                    .into_iter()
+                    .map(move |matched| (item, matched))
+            })
+            .collect()
+    }
+
+    /// Joins the items of this database to the items of `right` in the same
+    /// way as [Database::join], but also returns the items of this database
+    /// without a match paired with [None] like an SQL left join.
+    pub fn left_join<'a, U, K, FL, FR>(
+        &'a self,
+        right: &'a Database<U>,
+        right_index: &str,
+        left_key: FL,
+        right_key: FR,
+    ) -> Vec<(&'a T, Option<&'a U>)>
+    where
+        U: hash::Hash + Eq + 'static,
+        K: hash::Hash + Eq + 'static,
+        FL: Fn(&T) -> K,
+        FR: Fn(&U) -> K,
+    {
+        let lookup = index::Lookup::new(
+            right.indexes.get::<index::HashIndex<K, U>>(right_index),
+            &right.items,
+            right_key,
+        );
+
+        self.items
+            .iter()
+            .flat_map(|item| {
+                let matches = lookup.get(&left_key(item));
+
+                if matches.is_empty() {
+                    vec![(item, None)]
+                } else {
+                    matches
+                        .into_iter()
+                        .map(|matched| (item, Some(matched)))
+                        .collect()
+                }
+            })
+            .collect()
+    }
+
+    /// Creates an ordered index called `name`, keeping the key `extractor`
+    /// returns for each item in order so that items can be queried by ranges
+    /// of keys. This replaces any index with the same name.
+    ///
+    /// The index is maintained in the same way as [Database::create_index],
+    /// and is used by [Database::query_range], [Database::query_min],
+    /// [Database::query_max], [Database::query_first_n] and
+    /// [Database::query_last_n]. Items sharing a key are returned in no
+    /// particular order.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde::{Serialize, Deserialize};
+    /// use tinydb::Database;
+    ///
+    /// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+    /// struct ExampleStruct {
+    ///     uuid: String,
+    ///     age: i32,
+    /// }
+    ///
+    /// fn main() {
+    ///     let mut my_db = Database::new("query_range_test", None, false);
+    ///     my_db.create_ordered_index("age", |s: &ExampleStruct| s.age);
+    ///
+    ///     my_db.add_item(ExampleStruct { uuid: "test1".into(), age: 16 });
+    ///     my_db.add_item(ExampleStruct { uuid: "test2".into(), age: 20 });
+    ///     my_db.add_item(ExampleStruct { uuid: "test3".into(), age: 35 });
+    ///
+    ///     let results = my_db.query_range("age", 18..=30).unwrap();
+    ///     assert_eq!(results[0].uuid, "test2");
+    ///
+    ///     assert_eq!(my_db.query_max("age").unwrap().age, 35);
+    /// }
+    /// ```
+    pub fn create_ordered_index<K, F>(&mut self, name: impl Into<String>, extractor: F)
+    where
+        T: Clone + Send + Sync + 'static,
+        K: Ord + Clone + Send + Sync + 'static,
+        F: Fn(&T) -> K + Send + Sync + 'static,
+    {
+        let index = index::OrderedIndex::new(Arc::new(extractor), self.indexes.share(&self.items));
+        self.indexes.insert_index(name.into(), Box::new(index));
+    }
+
+    /// Query the ordered index called `name` for all items with a key inside
+    /// of `range`, returned in key order.
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::IndexNotFound] if there is no
+    /// ordered index called `name` with keys of the same type as `range`, or
+    /// [error::DatabaseError::ItemNotFound] if no items are inside of it.
+    pub fn query_range<K, R>(&self, name: &str, range: R) -> Result<Vec<&T>, error::DatabaseError>
+    where
+        T: 'static,
+        K: Ord + 'static,
+        R: RangeBounds<K>,
+    {
+        self.query_range_where(name, range, |_| true)
+    }
+
+    /// Query the ordered index called `name` for all items with a key inside
+    /// of `range` which also match `predicate`, returned in key order. Errors
+    /// in the same way as [Database::query_range].
+    pub fn query_range_where<K, R, P>(
+        &self,
+        name: &str,
+        range: R,
+        predicate: P,
+    ) -> Result<Vec<&T>, error::DatabaseError>
+    where
+        T: 'static,
+        K: Ord + 'static,
+        R: RangeBounds<K>,
+        P: Fn(&T) -> bool,
+    {
+        let items: Vec<&T> = self
+            .indexes
+            .get::<index::OrderedIndex<K, T>>(name)
+            .ok_or_else(|| error::DatabaseError::IndexNotFound(name.into()))?
+            .range(range)
+            .filter(|item| predicate(item))
+            .collect();
+
+        if items.is_empty() {
+            return Err(error::DatabaseError::ItemNotFound);
+        }
+
+        Ok(items)
+    }
+
+    /// Finds an item with the smallest key in the ordered index called `name`.
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::IndexNotFound] if there is no
+    /// ordered index called `name` or [error::DatabaseError::ItemNotFound] if
+    /// the database is empty.
+    pub fn query_min(&self, name: &str) -> Result<&T, error::DatabaseError> {
+        self.ordered_index(name)?
+            .ascending()
+            .next()
+            .ok_or(error::DatabaseError::ItemNotFound)
+    }
+
+    /// Finds an item with the largest key in the ordered index called `name`,
+    /// see [Database::query_min].
+    pub fn query_max(&self, name: &str) -> Result<&T, error::DatabaseError> {
+        self.ordered_index(name)?
+            .descending()
+            .next()
+            .ok_or(error::DatabaseError::ItemNotFound)
+    }
+
+    /// Finds up to `n` items with the smallest keys in the ordered index
+    /// called `name`, from the smallest key upwards. Errors in the same way as
+    /// [Database::query_min].
+    pub fn query_first_n(&self, name: &str, n: usize) -> Result<Vec<&T>, error::DatabaseError> {
+        let items: Vec<&T> = self.ordered_index(name)?.ascending().take(n).collect();
+
+        if items.is_empty() {
+            return Err(error::DatabaseError::ItemNotFound);
+        }
+
+        Ok(items)
+    }
+
+    /// Finds up to `n` items with the largest keys in the ordered index called
+    /// `name`, from the largest key downwards. Errors in the same way as
+    /// [Database::query_min].
+    pub fn query_last_n(&self, name: &str, n: usize) -> Result<Vec<&T>, error::DatabaseError> {
+        let items: Vec<&T> = self.ordered_index(name)?.descending().take(n).collect();
+
+        if items.is_empty() {
+            return Err(error::DatabaseError::ItemNotFound);
+        }
+
+        Ok(items)
+    }
+
+    /// Fetches a page of up to `limit` items from the ordered index called
+    /// `name`, in key order, starting after the `after` cursor of the previous
+    /// page or from the smallest key if there isn't one.
+    ///
+    /// Unlike paging with [query::Query::offset], items added or removed
+    /// between fetching pages don't cause any other items to be skipped or
+    /// repeated. Items sharing a key are returned in the same order every
+    /// time. A `limit` of zero returns an empty page without a next cursor, as
+    /// following it could never get any further.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde::{Serialize, Deserialize};
+    /// use tinydb::{cursor::Cursor, Database};
+    ///
+    /// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+    /// struct ExampleStruct {
+    ///     name: String,
+    ///     age: i32,
+    /// }
+    ///
+    /// fn main() {
+    ///     let mut my_db = Database::new("query_page_test", None, false);
+    ///     my_db.create_ordered_index("age", |s: &ExampleStruct| s.age);
+    ///
+    ///     for (name, age) in [("Alice", 34), ("Adam", 25), ("Bob", 41)] {
+    ///         my_db.add_item(ExampleStruct { name: name.into(), age }).unwrap();
+    ///     }
+    ///
+    ///     let first = my_db.query_page::<i32>("age", None, 2).unwrap();
+    ///     assert_eq!(first.items[0].name, "Adam");
+    ///
+    ///     // cursors can be handed to clients as tokens and read back later
+    ///     let token = first.next.unwrap().to_string();
+    ///     let cursor: Cursor = token.parse().unwrap();
+    ///
+    ///     let second = my_db.query_page::<i32>("age", Some(&cursor), 2).unwrap();
+    ///     assert_eq!(second.items[0].name, "Bob");
+    ///     assert!(second.next.is_none());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::IndexNotFound] if there is no
+    /// ordered index called `name` with keys of type `K`, or
+    /// [error::DatabaseError::InvalidCursor] if `after` was made for a
+    /// different index, or [error::DatabaseError::EncodeFailed] if an item up
+    /// to the end of the page or its key can't be encoded. An empty page is
+    /// returned if there are no items after the cursor.
+    pub fn query_page<K>(
+        &self,
+        name: &str,
+        after: Option<&cursor::Cursor>,
+        limit: usize,
+    ) -> Result<cursor::Page<'_, T>, error::DatabaseError>
+    where
+        T: 'static,
+        K: Ord + Serialize + DeserializeOwned + 'static,
+    {
+        let index = self
+            .indexes
+            .get::<index::OrderedIndex<K, T>>(name)
+            .ok_or_else(|| error::DatabaseError::IndexNotFound(name.into()))?;
+
+        let position = after.map(|cursor| cursor.position::<K>(name)).transpose()?;
+        if limit == 0 {
+            return Ok(cursor::Page {
+                items: vec![],
+                next: None,
+            });
+        }
+
+        let start = match &position {
+            Some((key, _)) => Bound::Included(key),
+            None => Bound::Unbounded,
+        };
+
+        let mut items = vec![];
+        let mut last = None;
+        let next = |last: Option<(&K, (u64, Vec<u8>))>| match last {
+            Some((key, tie)) => cursor::Cursor::new(name, key, tie).map(Some),
+            None => Ok(after.cloned()),
+        };
+
+        for (key, group) in index.groups((start, Bound::Unbounded)) {
+            // only the first group can be skipped entirely, so a full page
+            // ending before any other group always has a next page
+            if items.len() == limit {
+                return Ok(cursor::Page {
+                    items,
+                    next: next(last)?,
+                });
+            }
+
+            let mut sorted = group
+                .iter()
+                .map(|item| Ok((query::fixed_tie(&**item)?, &**item)))
+                .collect::<Result<Vec<_>, error::DatabaseError>>()?;
+            sorted.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+            for (tie, item) in sorted {
+                if let Some((after_key, after_tie)) = &position {
+                    if key == after_key && tie <= *after_tie {
+                        continue;
+                    }
+                }
+
+                if items.len() == limit {
+                    return Ok(cursor::Page {
+                        items,
+                        next: next(last)?,
+                    });
+                }
+
+                items.push(item);
+                last = Some((key, tie));
+            }
+        }
+
+        Ok(cursor::Page { items, next: None })
+    }
+
+    /// Creates a prefix index called `name` over the string key `extractor`
+    /// returns for each item, for autocompletion with [Database::query_prefix]
+    /// and typo-tolerant lookups with [Database::query_fuzzy]. This replaces any
+    /// index with the same name.
+    ///
+    /// Keys are compared exactly, so `extractor` should e.g. lowercase them if
+    /// lookups shouldn't depend on case. The index is maintained in the same
+    /// way as [Database::create_index].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde::{Serialize, Deserialize};
+    /// use tinydb::Database;
+    ///
+    /// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+    /// struct ExampleStruct {
+    ///     name: String,
+    /// }
+    ///
+    /// fn main() {
+    ///     let mut my_db = Database::new("query_prefix_test", None, false);
+    ///     my_db.create_prefix_index("name", |s: &ExampleStruct| s.name.to_lowercase());
+    ///
+    ///     for name in ["Kryten", "Kochanski", "Lister"] {
+    ///         my_db.add_item(ExampleStruct { name: name.into() });
+    ///     }
+    ///
+    ///     let results = my_db.query_prefix("name", "k").unwrap();
+    ///     assert_eq!(results[0].name, "Kochanski");
+    ///     assert_eq!(results[1].name, "Kryten");
+    ///
+    ///     let results = my_db.query_fuzzy("name", "lsiter", 2).unwrap();
+    ///     assert_eq!(results[0].name, "Lister");
+    /// }
+    /// ```
+    pub fn create_prefix_index<F>(&mut self, name: impl Into<String>, extractor: F)
+    where
+        T: Clone + Send + Sync + 'static,
+        F: Fn(&T) -> String + Send + Sync + 'static,
+    {
+        let index = index::TrieIndex::new(Arc::new(extractor), self.indexes.share(&self.items));
+        self.indexes.insert_index(name.into(), Box::new(index));
+    }
+
+    /// Query the prefix index called `name` for all items with a key starting
+    /// with `prefix`, returned in key order.
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::IndexNotFound] if there is no prefix
+    /// index called `name`, or [error::DatabaseError::ItemNotFound] if no keys
+    /// start with `prefix`.
+    pub fn query_prefix(&self, name: &str, prefix: &str) -> Result<Vec<&T>, error::DatabaseError>
+    where
+        T: 'static,
+    {
+        let items = self.prefix_index(name)?.prefix(prefix);
+
+        if items.is_empty() {
+            return Err(error::DatabaseError::ItemNotFound);
+        }
+
+        Ok(items)
+    }
+
+    /// Query the prefix index called `name` for all items with a key at most
+    /// `max_distance` edits from `term`, where an edit inserts, removes or
+    /// replaces a single character (the Levenshtein distance). Items are
+    /// returned closest first, then in key order. Errors in the same way as
+    /// [Database::query_prefix].
+    pub fn query_fuzzy(
+        &self,
+        name: &str,
+        term: &str,
+        max_distance: usize,
+    ) -> Result<Vec<&T>, error::DatabaseError>
+    where
+        T: 'static,
+    {
+        let items = self.prefix_index(name)?.fuzzy(term, max_distance);
+
+        if items.is_empty() {
+            return Err(error::DatabaseError::ItemNotFound);
+        }
+
+        Ok(items)
+    }
+
+    /// Finds the prefix index called `name`.
+    fn prefix_index(&self, name: &str) -> Result<&index::TrieIndex<T>, error::DatabaseError>
+    where
+        T: 'static,
+    {
+        self.indexes
+            .get(name)
+            .ok_or_else(|| error::DatabaseError::IndexNotFound(name.into()))
+    }
+
+    /// Creates a geospatial index called `name` over the point `extractor`
+    /// returns for each item, for finding items by location with
+    /// [Database::query_within_radius], [Database::query_within_box] and
+    /// [Database::query_nearest]. This replaces any index with the same name.
+    ///
+    /// The index is maintained in the same way as [Database::create_index].
+    /// Items whose point has a coordinate which isn't a finite number are left
+    /// out of it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde::{Serialize, Deserialize};
+    /// use tinydb::{geo::Point, Database};
+    ///
+    /// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+    /// struct City {
+    ///     name: String,
+    ///     lat_e6: i64,
+    ///     lon_e6: i64,
+    /// }
+    ///
+    /// fn main() {
+    ///     let mut my_db = Database::new("geo_test", None, false);
+    ///     my_db.create_geo_index("location", |c: &City| {
+    ///         Point::new(c.lat_e6 as f64 / 1e6, c.lon_e6 as f64 / 1e6)
+    ///     });
+    ///
+    ///     my_db.add_item(City { name: "London".into(), lat_e6: 51_507_400, lon_e6: -127_800 });
+    ///     my_db.add_item(City { name: "Paris".into(), lat_e6: 48_856_600, lon_e6: 2_352_200 });
+    ///
+    ///     let near_london = Point::new(51.5, 0.0);
+    ///
+    ///     let results = my_db.query_within_radius("location", near_london, 50_000.0).unwrap();
+    ///     assert_eq!(results.len(), 1);
+    ///
+    ///     let results = my_db.query_nearest("location", near_london, 2).unwrap();
+    ///     assert_eq!(results[1].name, "Paris");
+    /// }
+    /// ```
+    #[cfg(feature = "geo")]
+    pub fn create_geo_index<F>(&mut self, name: impl Into<String>, extractor: F)
+    where
+        T: Clone + Send + Sync + 'static,
+        F: Fn(&T) -> geo::Point + Send + Sync + 'static,
+    {
+        let index = geo::GeoIndex::new(Arc::new(extractor), self.indexes.share(&self.items));
+        self.indexes.insert_index(name.into(), Box::new(index));
+    }
+
+    /// Query the geospatial index called `name` for all items within `radius`
+    /// metres of `center`, measured over the surface of the Earth and returned
+    /// closest first.
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::IndexNotFound] if there is no
+    /// geospatial index called `name`, or [error::DatabaseError::ItemNotFound]
+    /// if no items are within `radius`.
+    #[cfg(feature = "geo")]
+    pub fn query_within_radius(
+        &self,
+        name: &str,
+        center: geo::Point,
+        radius: f64,
+    ) -> Result<Vec<&T>, error::DatabaseError>
+    where
+        T: 'static,
+    {
+        let items = self.geo_index(name)?.within_radius(center, radius);
+
+        if items.is_empty() {
+            return Err(error::DatabaseError::ItemNotFound);
+        }
+
+        Ok(items)
+    }
+
+    /// Query the geospatial index called `name` for all items inside of the
+    /// box with the corners `south_west` and `north_east`, including its edges.
+    /// If the western edge is further east than the eastern one the box
+    /// crosses the antimeridian, e.g. from 170 to -170 degrees longitude.
+    /// Errors in the same way as [Database::query_within_radius].
+    #[cfg(feature = "geo")]
+    pub fn query_within_box(
+        &self,
+        name: &str,
+        south_west: geo::Point,
+        north_east: geo::Point,
+    ) -> Result<Vec<&T>, error::DatabaseError>
+    where
+        T: 'static,
+    {
+        let items = self.geo_index(name)?.within_box(south_west, north_east);
+
+        if items.is_empty() {
+            return Err(error::DatabaseError::ItemNotFound);
+        }
+
+        Ok(items)
+    }
+
+    /// Query the geospatial index called `name` for the `k` items closest to
+    /// `point`, returned closest first. Errors in the same way as
+    /// [Database::query_within_radius].
+    #[cfg(feature = "geo")]
+    pub fn query_nearest(
+        &self,
+        name: &str,
+        point: geo::Point,
+        k: usize,
+    ) -> Result<Vec<&T>, error::DatabaseError>
+    where
+        T: 'static,
+    {
+        let items = self.geo_index(name)?.nearest(point, k);
+
+        if items.is_empty() {
+            return Err(error::DatabaseError::ItemNotFound);
+        }
+
+        Ok(items)
+    }
+
+    /// Finds the geospatial index called `name`.
+    #[cfg(feature = "geo")]
+    fn geo_index(&self, name: &str) -> Result<&geo::GeoIndex<T>, error::DatabaseError>
+    where
+        T: 'static,
+    {
+        self.indexes
+            .get(name)
+            .ok_or_else(|| error::DatabaseError::IndexNotFound(name.into()))
+    }
+
+    /// Finds the ordered index called `name`.
+    fn ordered_index(&self, name: &str) -> Result<&dyn index::Ordered<T>, error::DatabaseError> {
+        self.indexes
+            .get_ordered(name)
+            .ok_or_else(|| error::DatabaseError::IndexNotFound(name.into()))
+    }
+
+    /// Creates a full-text index called `name` over the text `extractor`
+    /// returns for each item, which can be one or more strings such as a title
+    /// and a description. Text is split into words with `analyzer`. This
+    /// replaces any index with the same name.
+    ///
+    /// The index is maintained in the same way as [Database::create_index].
+    /// Like other indexes it isn't dumped unless it is persisted with
+    /// [Database::persist_text_index], and as `extractor` can't be dumped it
+    /// has to be created again after loading a database either way.
+    ///
+    /// See [Database::search] to search the index.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde::{Serialize, Deserialize};
+    /// use tinydb::{text::Analyzer, Database};
+    ///
+    /// #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+    /// struct Note {
+    ///     title: String,
+    ///     body: String,
+    /// }
+    ///
+    /// fn main() {
+    ///     let mut my_db = Database::new("search_test", None, false);
+    ///     my_db.create_text_index("notes", Analyzer::english(), |n: &Note| {
+    ///         [n.title.clone(), n.body.clone()]
+    ///     });
+    ///
+    ///     my_db.add_item(Note { title: "Shopping".into(), body: "Curry and lager".into() });
+    ///     my_db.add_item(Note { title: "Curries".into(), body: "Vindaloo, then more curry".into() });
+    ///     my_db.add_item(Note { title: "Chores".into(), body: "Paint the scutters".into() });
+    ///
+    ///     let hits = my_db.search("notes", "curry").unwrap();
+    ///
+    ///     assert_eq!(hits.len(), 2);
+    ///     assert_eq!(hits[0].item.title, "Curries");
+    /// }
+    /// ```
+    pub fn create_text_index<S, F>(
+        &mut self,
+        name: impl Into<String>,
+        analyzer: text::Analyzer,
+        extractor: F,
+    ) where
+        T: Clone + Send + Sync + 'static,
+        S: IntoIterator,
+        S::Item: AsRef<str>,
+        F: Fn(&T) -> S + Send + Sync + 'static,
+    {
+        let name = name.into();
+        let extractor = move |item: &T| {
+            extractor(item)
+                .into_iter()
+                .map(|text| text.as_ref().to_owned())
+                .collect()
+        };
+
+        let snapshot = self.indexes.take_snapshot(&name);
+        let items = self.indexes.share(&self.items);
+        let index = match snapshot {
+            Some(snapshot) => text::TextIndex::restore(
+                analyzer,
+                Arc::new(extractor),
+                query::fixed_hash,
+                snapshot,
+                items,
+            ),
+            None => text::TextIndex::new(analyzer, Arc::new(extractor), query::fixed_hash, items),
+        };
+        self.indexes.insert_index(name, Box::new(index));
+    }
+
+    /// Sets if the full-text index called `name` is dumped alongside the
+    /// database, which it isn't by default.
+    ///
+    /// A persisted index still has to be created again with
+    /// [Database::create_text_index] after loading, with the same name,
+    /// analyzer and extractor, but the words of items which haven't changed
+    /// since the dump are taken from it rather than found again. Until then
+    /// the loaded index is dumped again as it is. Items are matched up with
+    /// the dump by a hash of their encoding, so items containing a
+    /// [std::collections::HashMap] or [HashSet] are usually indexed from
+    /// scratch instead.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tinydb::{text::Analyzer, Database};
+    ///
+    /// fn main() {
+    ///     let mut my_db = Database::new("persist_text_test", None, false);
+    ///     my_db.add_item(String::from("Smoke me a kipper"));
+    ///     my_db.create_text_index("text", Analyzer::english(), |s: &String| [s.clone()]);
+    ///     my_db.persist_text_index("text", true).unwrap();
+    ///     my_db.dump_db().unwrap();
+    ///
+    ///     let mut loaded: Database<String> = Database::from("persist_text_test.tinydb").unwrap();
+    ///     loaded.create_text_index("text", Analyzer::english(), |s: &String| [s.clone()]);
+    ///
+    ///     assert_eq!(loaded.search("text", "kippers").unwrap().len(), 1);
+    ///     # std::fs::remove_file("persist_text_test.tinydb").unwrap();
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::IndexNotFound] if there is no
+    /// full-text index called `name`.
+    pub fn persist_text_index(
+        &mut self,
+        name: &str,
+        persist: bool,
+    ) -> Result<(), error::DatabaseError> {
+        if self.indexes.set_persist(name, persist) {
+            Ok(())
+        } else {
+            Err(error::DatabaseError::IndexNotFound(name.into()))
+        }
+    }
+
+    /// Searches the full-text index called `name` for items containing any
+    /// word of `query`, returned with the most relevant first (see
+    /// [text::Hit::score]). Items which are equally relevant are always
+    /// returned in the same order.
+    ///
+    /// # Errors
+    ///
+    /// Will return [error::DatabaseError::IndexNotFound] if there is no
+    /// full-text index called `name`, or [error::DatabaseError::ItemNotFound]
+    /// if no items contain any word of `query`.
+    pub fn search(
+        &self,
+        name: &str,
+        query: &str,
+    ) -> Result<Vec<text::Hit<'_, T>>, error::DatabaseError>
+    where
+        T: 'static,
+    {
+        let mut hits: Vec<(Option<u64>, text::Hit<'_, T>)> = self
+            .indexes
+            .get::<text::TextIndex<T>>(name)
+            .ok_or_else(|| error::DatabaseError::IndexNotFound(name.into()))?
+            .search(query)
+            .into_iter()
+            .map(|hit| (query::fixed_hash(hit.item), hit))
+            .collect();
+
+        if hits.is_empty() {
+            return Err(error::DatabaseError::ItemNotFound);
+        }
+
+        hits.sort_unstable_by(|(a_hash, a), (b_hash, b)| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(cmp::Ordering::Equal)
+                .then_with(|| query::fixed_cmp((*a_hash, a.item), (*b_hash, b.item)))
+        });
+
+        Ok(hits.into_iter().map(|(_, hit)| hit).collect())
+    }
+
+    /// Searches the database for a specific value. If it does not exist, this
+    /// method will return [error::DatabaseError::ItemNotFound].
+    ///
+    /// This is a wrapper around [HashSet::contains].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tinydb::Database;
+    /// use serde::{Serialize, Deserialize};
+    ///
+    /// #[derive(Hash, Eq, PartialEq, Serialize, Deserialize, Copy, Clone)]
+    /// struct ExampleStruct {
+    ///     item: i32
+    /// }
+    ///
+    /// fn main() {
+    ///     let exp_struct = ExampleStruct { item: 4942 };
+    ///     let mut db = Database::new("Contains example", None, false);
+    ///
+    ///     db.add_item(exp_struct.clone());
+    ///
+    ///     assert_eq!(db.contains(&exp_struct), true);
+    /// }
+    /// ```
+    pub fn contains(&self, query: &T) -> bool {
+        self.items.contains(query)
+    }
+
+    /// Returns the number of database entries
+    /// method will return i32.
+    ///
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tinydb::Database;
+    /// use serde::{Serialize, Deserialize};
+    ///
+    /// #[derive(Hash, Eq, PartialEq, Serialize, Deserialize, Copy, Clone)]
+    /// struct ExampleStruct {
+    ///     item: i32
+    /// }
+    ///
+    /// fn main() {
+    ///     let exp_struct = ExampleStruct { item: 4942 };
+    ///     let mut db = Database::new("Contains example", None, false);
+    ///
+    ///     db.add_item(exp_struct.clone());
+    ///
+    ///     assert_eq!(db.len(), 1);
+    /// }
+    /// ```
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> i32 {
+        self.items.len() as i32
+    }
+}
+
+impl<T: hash::Hash + Eq + Serialize + DeserializeOwned> Store for Database<T> {
+    type Record = wal::Record<T>;
+
+    const KIND: header::Kind = header::Kind::Plain;
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+
+    fn save_path(&self) -> Option<&PathBuf> {
+        self.save_path.as_ref()
+    }
+
+    fn layout(&self) -> (codec::Format, compression::Compression, u64) {
+        (self.format, self.compression, self.schema)
+    }
+
+    fn set_layout(
+        &mut self,
+        format: codec::Format,
+        compression: compression::Compression,
+        schema: u64,
+    ) {
+        self.format = format;
+        self.compression = compression;
+        self.schema = schema;
+    }
+
+    fn storage(&self) -> &store::Storage {
+        &self.storage
+    }
+
+    fn storage_mut(&mut self) -> &mut store::Storage {
+        &mut self.storage
+    }
+
+    fn apply_records(&mut self, records: Vec<wal::Record<T>>) {
+        for record in records {
+            match record {
+                wal::Record::Add(item) => {
+                    self.items.insert(item);
+                }
+                wal::Record::Remove(item) => {
+                    self.items.remove(&item);
+                }
+                wal::Record::Update(item, new) => {
+                    self.items.remove(&item);
+                    self.items.insert(new);
+                }
+            }
+        }
+    }
+
+    fn text_indexes(&self) -> text::Snapshots {
+        self.indexes.snapshots()
+    }
+
+    fn set_text_indexes(&mut self, indexes: text::Snapshots) {
+        self.indexes.set_snapshots(indexes);
+    }
+}
+
+/// Infers the label of a database from the file stem of `path`.
+fn label_from_path(path: &Path) -> Result<String, error::DatabaseError> {
+    match path.file_stem() {
+        Some(x) => match x.to_str() {
+            Some(y) => Ok(String::from(y)),
+            None => Err(error::DatabaseError::BadDbName),
+        },
+        None => Err(error::DatabaseError::BadDbName),
+    }
+}
+
+/// Opens the dump at `path` for reading, returning it alongside its length.
+fn open_path(path: &Path) -> Result<(File, u64), error::DatabaseError> {
+    if !path.exists() {
+        return Err(error::DatabaseError::DatabaseNotFound);
+    }
+
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    Ok((file, len))
+}
+
+/// Reads the whole of `reader` into a [Vec]<[u8]> stream.
+fn read_stream<R: Read>(mut reader: R) -> Result<Vec<u8>, error::DatabaseError> {
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+
+    Ok(buffer)
+}
+
+/// Writes a whole dump of the database `value` to `writer` with the format,
+/// compression and schema of `layout`, preceded by the `text` indexes
+/// persisted with it if there are any and encrypted with `key` if there is
+/// one. The writer is flushed once the dump has been written.
+///
+/// If the format is that of the codec `C` it is encoded with `C`, which is
+/// [codec::Bincode] when there is no user codec as it is built-in anyway.
+fn write_dump<C: codec::Codec, W: Write, V: Serialize>(
+    mut writer: W,
+    kind: header::Kind,
+    layout: (codec::Format, compression::Compression, u64),
+    key: &CipherKey,
+    text: &text::Snapshots,
+    value: &V,
+) -> Result<(), error::DatabaseError> {
+    let (format, compression, schema) = layout;
+    format.check_encodable::<C>()?;
+
+    let mut header = header::Header::new(kind, format, compression, schema);
+    if key.is_some() {
+        header.flags |= header::FLAG_ENCRYPTED;
+    }
+    if !text.is_empty() {
+        header.flags |= header::FLAG_TEXT_INDEXES;
+    }
+
+    let encode = |writer: &mut dyn Write| {
+        compression.compress(writer, |compressed| {
+            if !text.is_empty() {
+                let mut encoded = vec![];
+                format.encode::<C, _, _>(&mut encoded, text)?;
+                compressed.write_all(&(encoded.len() as u64).to_le_bytes())?;
+                compressed.write_all(&encoded)?;
+            }
+
+            format.encode::<C, _, _>(compressed, value)
+        })
+    };
+
+    let mut checked = checksum::ChecksumWriter::new(&mut writer);
+    header.write(&mut checked)?;
+
+    if key.is_some() {
+        let mut plaintext = vec![];
+        encode(&mut plaintext)?;
+        checked.write_all(&seal_with(key, plaintext, &header.to_bytes())?)?;
+    } else {
+        encode(&mut checked)?;
+    }
+
+    checked.finish()?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reads a whole dump of a database from `reader`, which is `len` bytes long,
+/// returning its header and whatever `decode` returns.
+///
+/// The header is validated first, including that it was dumped by the `kind`
+/// of database being read, then `decode` is given the format, the
+/// decrypted and decompressed payload as it is read, the offset the payload
+/// starts at, the most bytes it may contain and the encoded text indexes
+/// which preceded it if there were any. The checksum is verified
+/// whilst the payload is read and takes precedence over any error from
+/// decoding, as corruption is the likeliest cause of one.
+///
+/// Files without a header return [error::DatabaseError::NotTinyDb], unless
+/// `legacy` is set, in which case they are decoded as the bincode dumps
+/// written before headers were added, returning [header::Header::legacy].
+fn read_dump<R: Read, V>(
+    mut reader: R,
+    kind: header::Kind,
+    legacy: bool,
+    len: u64,
+    key: &CipherKey,
+    decode: impl FnOnce(
+        codec::Format,
+        &mut dyn Read,
+        u64,
+        u64,
+        Option<&[u8]>,
+    ) -> Result<V, error::DatabaseError>,
+) -> Result<(header::Header, V), error::DatabaseError> {
+    let mut start = Vec::with_capacity(header::HEADER_LEN);
+    (&mut reader)
+        .take(header::HEADER_LEN as u64)
+        .read_to_end(&mut start)?;
+
+    if !start.starts_with(&header::MAGIC) {
+        if !legacy {
+            return Err(error::DatabaseError::NotTinyDb);
+        }
+
+        let header = header::Header::legacy();
+        header.check_kind(kind)?;
+
+        let decoded = decode(
+            codec::Format::Bincode,
+            &mut (&start[..]).chain(reader),
+            0,
+            len,
+            None,
+        )?;
+        return Ok((header, decoded));
+    }
+
+    let header = header::Header::read(&mut &start[..])?;
+    header.check_kind(kind)?;
+
+    let body_len = len.saturating_sub(header::HEADER_LEN as u64);
+
+    if !header.has_checksum() {
+        return decode_body(&header, &mut reader, body_len, key, decode).map(|v| (header, v));
+    }
+
+    let mut checked = checksum::ChecksumReader::new(reader, &header.to_bytes());
+    let decoded = decode_body(
+        &header,
+        &mut checked,
+        body_len.saturating_sub(checksum::CHECKSUM_LEN as u64),
+        key,
+        decode,
+    );
+    checked.finish()?;
+
+    decoded.map(|v| (header, v))
+}
+
+/// Checks that a dump which `declared` a schema matches the `expected` one, as
+/// in [Database::check_schema].
+pub(crate) fn check_schema(declared: u64, expected: u64) -> Result<(), error::DatabaseError> {
+    if declared == 0 || declared == expected {
+        Ok(())
+    } else {
+        Err(error::DatabaseError::ItemTypeMismatch)
+    }
+}
+
+/// Key used to encrypt dumps and write-ahead log records, which can never be
+/// set without the `encryption` feature.
+#[cfg(feature = "encryption")]
+pub(crate) type CipherKey = Option<encryption::Key>;
+
+/// Key used to encrypt dumps and write-ahead log records, which can never be
+/// set without the `encryption` feature.
+#[cfg(not(feature = "encryption"))]
+pub(crate) type CipherKey = Option<std::convert::Infallible>;
+
+/// Encrypts `plaintext` with `key` and `aad`, returning it unchanged if there
+/// is no key.
+#[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+pub(crate) fn seal_with(
+    key: &CipherKey,
+    plaintext: Vec<u8>,
+    aad: &[u8],
+) -> Result<Vec<u8>, error::DatabaseError> {
+    match key {
+        #[cfg(feature = "encryption")]
+        Some(key) => encryption::seal(key, &plaintext, aad),
+        #[cfg(not(feature = "encryption"))]
+        Some(never) => match *never {},
+        None => Ok(plaintext),
+    }
+}
+
+/// Decrypts `contents` with `key` and `aad` if they are `sealed`, borrowing
+/// them otherwise.
+///
+/// # Errors
+///
+/// Will return [error::DatabaseError::KeyRequired] if they are sealed but
+/// there is no key, or [error::DatabaseError::DecryptionFailed] if they can't
+/// be decrypted with it.
+#[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+pub(crate) fn open_sealed<'a>(
+    sealed: bool,
+    contents: &'a [u8],
+    aad: &[u8],
+    key: &CipherKey,
+) -> Result<Cow<'a, [u8]>, error::DatabaseError> {
+    if !sealed {
+        return Ok(Cow::Borrowed(contents));
+    }
+
+    match key {
+        #[cfg(feature = "encryption")]
+        Some(key) => encryption::open(key, contents, aad).map(Cow::Owned),
+        #[cfg(not(feature = "encryption"))]
+        Some(never) => match *never {},
+        None => Err(error::DatabaseError::KeyRequired),
+    }
+}
+
+/// Decrypts, decompresses and decodes the `body` of a dump following its
+/// `header`, which is `len` bytes long, with `decode` as in [read_dump].
+pub(crate) fn decode_body<V>(
+    header: &header::Header,
+    body: &mut dyn Read,
+    len: u64,
+    key: &CipherKey,
+    decode: impl FnOnce(
+        codec::Format,
+        &mut dyn Read,
+        u64,
+        u64,
+        Option<&[u8]>,
+    ) -> Result<V, error::DatabaseError>,
+) -> Result<V, error::DatabaseError> {
+    let compression = header.compression()?;
+    let compressed = compression != compression::Compression::None;
+    let offset = header::HEADER_LEN as u64;
+
+    // the size of decompressed payloads isn't known upfront, so they can only
+    // be limited by the checksum
+    let decoded = if header.is_encrypted() {
+        let sealed = read_stream(body)?;
+        let payload = open_sealed(true, &sealed, &header.to_bytes(), key)?;
+        let limit = if compressed {
+            u64::MAX
+        } else {
+            payload.len() as u64
+        };
+        let mut decompressed = compression.decompress(&payload[..])?;
+
+        decode_payload(header, &mut decompressed, offset, limit, decode)
+    } else {
+        let limit = if compressed { u64::MAX } else { len };
+        let mut decompressed = compression.decompress(body)?;
+
+        decode_payload(header, &mut decompressed, offset, limit, decode)
+    };
+
+    decoded.map_err(|e| match e {
+        // offsets into decompressed or decrypted data don't point anywhere in
+        // the file
+        error::DatabaseError::DecodeFailed { path, source, .. }
+            if compressed || header.is_encrypted() =>
+        {
+            error::DatabaseError::DecodeFailed {
+                path,
+                offset: None,
+                source,
+            }
+        }
+        other => other,
+    })
+}
+
+/// Reads the text indexes which precede the database in the decrypted and
+/// decompressed `payload` if `header` says there are any, then decodes the
+/// rest of it with `decode` as in [read_dump].
+fn decode_payload<V>(
+    header: &header::Header,
+    payload: &mut dyn Read,
+    offset: u64,
+    limit: u64,
+    decode: impl FnOnce(
+        codec::Format,
+        &mut dyn Read,
+        u64,
+        u64,
+        Option<&[u8]>,
+    ) -> Result<V, error::DatabaseError>,
+) -> Result<V, error::DatabaseError> {
+    let format = header.format()?;
+    if !header.has_text_indexes() {
+        return decode(format, payload, offset, limit, None);
+    }
+
+    let mut len = [0; 8];
+    payload.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len);
+
+    let mut text = vec![];
+    if len <= limit.saturating_sub(8) {
+        payload.take(len).read_to_end(&mut text)?;
+    }
+    if text.len() as u64 != len {
+        return Err(error::DatabaseError::DecodeFailed {
+            path: None,
+            offset: Some(offset),
+            source: "text indexes run past the end of the dump".into(),
+        });
+    }
+
+    decode(
+        format,
+        payload,
+        offset + 8 + len,
+        limit.saturating_sub(8 + len),
+        Some(&text),
+    )
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A dummy struct to use inside of tests
+    #[derive(Clone, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
+    struct DemoStruct {
+        name: String,
+        age: i32,
+    }
+
+    /// Tests addition to in-memory db
+    #[test]
+    fn item_add() -> Result<(), error::DatabaseError> {
+        let mut my_db = Database::new("Adding test", None, true);
+
+        my_db.add_item(DemoStruct {
+            name: String::from("John"),
+            age: 16,
+        })?;
+
+        Ok(())
+    }
+
+    /// Tests removal from in-memory db
+    #[test]
+    fn item_remove() -> Result<(), error::DatabaseError> {
+        let mut my_db = Database::new("Removal test", None, true);
+
+        let testing_struct = DemoStruct {
+            name: String::from("Xander"),
+            age: 33,
+        };
+
+        my_db.add_item(testing_struct.clone())?;
+        my_db.remove_item(&testing_struct)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn db_dump() -> Result<(), error::DatabaseError> {
+        let mut my_db = Database::new(
+            String::from("Dumping test"),
+            Some(PathBuf::from("test.tinydb")),
+            true,
+        );
+
+        my_db.add_item(DemoStruct {
+            name: String::from("Xander"),
+            age: 33,
+        })?;
+        my_db.add_item(DemoStruct {
+            name: String::from("John"),
+            age: 54,
+        })?;
+
+        my_db.dump_db()?;
+
+        Ok(())
+    }
+    /// Tests [Database::query_item]
+    #[test]
+    fn query_item_db() {
+        let mut my_db = Database::new(
+            String::from("Query test"),
+            Some(PathBuf::from("test.tinydb")),
+            true,
+        );
+
+        my_db
+            .add_item(DemoStruct {
+                name: String::from("Rimmer"),
+                age: 5,
+            })
+            .unwrap();
+        my_db
+            .add_item(DemoStruct {
+                name: String::from("Cat"),
+                age: 10,
+            })
+            .unwrap();
+        my_db
+            .add_item(DemoStruct {
+                name: String::from("Kryten"),
+                age: 3000,
+            })
+            .unwrap();
+        my_db
+            .add_item(DemoStruct {
+                name: String::from("Lister"),
+                age: 62,
+            })
+            .unwrap();
+
+        assert_eq!(
+            my_db.query_item(|f| &f.age, 62).unwrap(),
+            &DemoStruct {
+                name: String::from("Lister"),
+                age: 62,
+            }
+        ); // Finds "Lister" by searching [DemoStruct::age]
+        assert_eq!(
+            my_db.query_item(|f| &f.name, String::from("Cat")).unwrap(),
+            &DemoStruct {
+                name: String::from("Cat"),
+                age: 10,
+            }
+        ); // Finds "Cat" by searching [DemoStruct::name]
+    }
+
+    /// Tests [Database::query]
+    #[test]
+    fn query_db() {
+        let mut my_db = Database::new(
+            String::from("Query test"),
+            Some(PathBuf::from("test.tinydb")),
+            false,
+        );
+
+        my_db
+            .add_item(DemoStruct {
+                name: String::from("Rimmer"),
+                age: 5,
+            })
+            .unwrap();
+        my_db
+            .add_item(DemoStruct {
+                name: String::from("Cat"),
+                age: 10,
+            })
+            .unwrap();
+        my_db
+            .add_item(DemoStruct {
+                name: String::from("Kryten"),
+                age: 3000,
+            })
+            .unwrap();
+        my_db
+            .add_item(DemoStruct {
+                name: String::from("Lister"),
+                age: 62,
+            })
+            .unwrap();
+
+        my_db
+            .add_item(DemoStruct {
+                name: String::from("Lister"),
+                age: 64,
+            })
+            .unwrap();
+
+        assert_eq!(
+            my_db
+                .query(|f| &f.name, String::from("Lister"))
+                .unwrap()
+                .len(),
+            2
+        ); // Finds "Lister" by searching [DemoStruct::name]
+    }
+
+    /// Tests a [Database::from] method call
+    #[test]
+    fn db_from() -> Result<(), error::DatabaseError> {
+        let mut my_db = Database::new(
+            String::from("Dumping test"),
+            Some(PathBuf::from("test.tinydb")),
+            false,
+        );
+
+        let demo_mock = DemoStruct {
+            name: String::from("Xander"),
+            age: 33,
+        };
+
+        my_db.add_item(demo_mock.clone()).unwrap();
+
+        my_db.dump_db()?;
+
+        let db: Database<DemoStruct> = Database::from(PathBuf::from("test.tinydb"))?;
+        assert_eq!(db.label, String::from("Dumping test"));
+
+        Ok(())
+    }
 
-    /// A dummy struct to use inside of tests
-    #[derive(Clone, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
-    struct DemoStruct {
-        name: String,
-        age: i32,
+    /// Tests that [Database::from] rejects files which aren't TinyDB dumps and
+    /// that [Database::check_schema] catches dumps of a different schema.
+    #[test]
+    fn db_from_bad_header() -> Result<(), error::DatabaseError> {
+        let garbage_path = std::env::temp_dir().join("tinydb-garbage.tinydb");
+        std::fs::write(&garbage_path, b"this is not a database")?;
+
+        assert!(matches!(
+            Database::<DemoStruct>::from(&garbage_path),
+            Err(error::DatabaseError::NotTinyDb)
+        ));
+
+        let typed_path = std::env::temp_dir().join("tinydb-typed.tinydb");
+        let mut my_db = Database::new("Typed test", typed_path.clone(), false);
+        my_db.schema = 1;
+        my_db.add_item(String::from("Not a DemoStruct"))?;
+        my_db.dump_db()?;
+
+        let loaded = Database::<String>::from(&typed_path)?;
+        assert_eq!(loaded.schema, 1);
+        loaded.check_schema(1)?;
+        assert!(matches!(
+            loaded.check_schema(2),
+            Err(error::DatabaseError::ItemTypeMismatch)
+        ));
+
+        my_db.schema = 0;
+        my_db.dump_db()?;
+        Database::<String>::from(&typed_path)?.check_schema(2)?;
+
+        Ok(())
+    }
+
+    /// Tests that dumps written before headers were added, which are plain
+    /// bincode, can only be loaded when asked for and report why they can't
+    /// be decoded.
+    #[test]
+    fn db_from_legacy() -> Result<(), error::DatabaseError> {
+        let path = std::env::temp_dir().join("tinydb-legacy.tinydb");
+        let mut my_db = Database::new("Legacy test", path.clone(), true);
+        my_db.add_item(DemoStruct {
+            name: String::from("Rimmer"),
+            age: 34,
+        })?;
+
+        let legacy = bincode::serialize(&my_db).unwrap();
+        std::fs::write(&path, &legacy)?;
+
+        assert!(matches!(
+            Database::<DemoStruct>::from(&path),
+            Err(error::DatabaseError::NotTinyDb)
+        ));
+        assert!(matches!(
+            Database::<DemoStruct>::verify(&path)?.header,
+            verify::Check::Failed(error::DatabaseError::NotTinyDb)
+        ));
+
+        let loaded = Database::<DemoStruct>::from_legacy(&path)?;
+        assert_eq!(loaded.items, my_db.items);
+        assert_eq!(loaded.label, my_db.label);
+        assert!(loaded.strict_dupes);
+
+        loaded.dump_db()?;
+        assert_eq!(Database::<DemoStruct>::from(&path)?, loaded);
+        assert_eq!(Database::<DemoStruct>::from_legacy(&path)?, loaded);
+
+        std::fs::write(&path, &legacy[..legacy.len() - 3])?;
+        match Database::<DemoStruct>::from_legacy(&path) {
+            Err(error::DatabaseError::DecodeFailed {
+                path: Some(p),
+                offset: Some(_),
+                ..
+            }) => assert_eq!(p, path),
+            other => panic!("truncated legacy dump gave {:?}", other.map(|_| ())),
+        }
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    /// Replaces the checksum trailer of `dump` with a freshly-calculated one so
+    /// that damage to it is only caught when decoding.
+    fn reseal(dump: &[u8]) -> Vec<u8> {
+        let body = &dump[..dump.len() - checksum::CHECKSUM_LEN];
+        let mut resealed = body.to_vec();
+
+        resealed.extend_from_slice(&crc32fast::hash(body).to_le_bytes());
+        resealed
+    }
+
+    /// Dumps a small database to a fresh path in the temp directory, returning
+    /// the path and dumped bytes.
+    fn small_dump(name: &str) -> Result<(PathBuf, Vec<u8>), error::DatabaseError> {
+        let path = std::env::temp_dir().join(format!("tinydb-{}.tinydb", name));
+        let mut my_db = Database::new("Small dump", path.clone(), false);
+        my_db.add_item(DemoStruct {
+            name: String::from("Kochanski"),
+            age: 29,
+        })?;
+        my_db.dump_db()?;
+
+        let dump = std::fs::read(&path)?;
+        Ok((path, dump))
+    }
+
+    /// Tests that truncated, corrupted and wrongly-typed dumps are reported as
+    /// [error::DatabaseError::DecodeFailed] rather than panicking.
+    #[test]
+    fn db_from_bad_payload() -> Result<(), error::DatabaseError> {
+        let (_, dump) = small_dump("payload")?;
+        let bad_path = std::env::temp_dir().join("tinydb-payload-bad.tinydb");
+        let payload_end = dump.len() - checksum::CHECKSUM_LEN;
+
+        let mut truncated = dump[..payload_end - 3].to_vec();
+        truncated.extend_from_slice(&[0; checksum::CHECKSUM_LEN]);
+        std::fs::write(&bad_path, reseal(&truncated))?;
+        match Database::<DemoStruct>::from(&bad_path) {
+            Err(error::DatabaseError::DecodeFailed { path, offset, .. }) => {
+                assert_eq!(path, Some(bad_path.clone()));
+                assert_eq!(offset, Some(payload_end as u64 - 4));
+            }
+            _ => panic!("truncated dump was decoded"),
+        } // Truncated inside of the final `age` field, which starts 4 bytes from the end
+
+        let mut corrupted = dump.clone();
+        corrupted[header::HEADER_LEN..header::HEADER_LEN + 8].copy_from_slice(&[0xff; 8]);
+        std::fs::write(&bad_path, reseal(&corrupted))?;
+        assert!(matches!(
+            Database::<DemoStruct>::from(&bad_path),
+            Err(error::DatabaseError::DecodeFailed { .. })
+        )); // Label length is now far larger than the file
+
+        std::fs::write(&bad_path, &dump)?;
+        assert!(matches!(
+            Database::<(String, String)>::from(&bad_path),
+            Err(error::DatabaseError::DecodeFailed { .. })
+        )); // Payload holds [DemoStruct] rather than `(String, String)` items
+
+        Ok(())
+    }
+
+    /// Tests that [Database::from] detects bit rot and truncation using the
+    /// checksum trailer.
+    #[test]
+    fn db_from_bad_checksum() -> Result<(), error::DatabaseError> {
+        let (_, dump) = small_dump("checksum")?;
+        let bad_path = std::env::temp_dir().join("tinydb-checksum-bad.tinydb");
+
+        let mut rotten = dump.clone();
+        rotten[header::HEADER_LEN + 10] ^= 0b0001_0000;
+        std::fs::write(&bad_path, &rotten)?;
+        assert!(matches!(
+            Database::<DemoStruct>::from(&bad_path),
+            Err(error::DatabaseError::ChecksumMismatch)
+        )); // Single flipped bit inside of the label
+
+        std::fs::write(&bad_path, &dump[..dump.len() - 1])?;
+        assert!(matches!(
+            Database::<DemoStruct>::from(&bad_path),
+            Err(error::DatabaseError::ChecksumMismatch)
+        ));
+
+        Ok(())
+    }
+
+    /// Tests that [Database::verify] reports on the header, checksum and items
+    /// of both healthy and damaged dumps.
+    #[test]
+    fn db_verify() -> Result<(), error::DatabaseError> {
+        let (path, dump) = small_dump("verify")?;
+
+        let report = Database::<DemoStruct>::verify(&path)?;
+        assert!(report.is_ok());
+        assert!(report.checksum.is_passed());
+        assert!(matches!(report.items, verify::Check::Passed(1)));
+
+        let bad_path = std::env::temp_dir().join("tinydb-verify-bad.tinydb");
+        let mut rotten = dump.clone();
+        rotten[header::HEADER_LEN + 10] ^= 0b0001_0000;
+        std::fs::write(&bad_path, &rotten)?;
+
+        let report = Database::<DemoStruct>::verify(&bad_path)?;
+        assert!(!report.is_ok());
+        assert!(report.header.is_passed());
+        assert!(report.checksum.is_failed());
+        assert!(report.items.is_passed()); // Label is damaged but still decodes
+
+        let report = Database::<(String, String)>::verify(&path)?;
+        assert!(report.header.is_passed());
+        assert!(report.items.is_failed()); // Items are [DemoStruct], not pairs
+
+        std::fs::write(&bad_path, b"this is not a database")?;
+        let report = Database::<DemoStruct>::verify(&bad_path)?;
+        assert!(matches!(
+            report.header,
+            verify::Check::Failed(error::DatabaseError::NotTinyDb)
+        ));
+        assert!(matches!(report.items, verify::Check::Skipped));
+
+        Ok(())
+    }
+
+    /// Tests that databases round-trip through a user [codec::Codec], which
+    /// can't be loaded or dumped without it.
+    #[test]
+    fn db_user_codec() -> Result<(), error::DatabaseError> {
+        /// Bincode with every byte inverted.
+        struct Inverted;
+
+        impl codec::Codec for Inverted {
+            const ID: u8 = 9;
+
+            fn encode<W: Write, V: Serialize>(
+                mut writer: W,
+                value: &V,
+            ) -> Result<(), error::DatabaseError> {
+                let encoded = bincode::serialize(value).unwrap();
+                Ok(writer.write_all(&encoded.iter().map(|b| !b).collect::<Vec<_>>())?)
+            }
+
+            fn decode<R: Read, V: DeserializeOwned>(
+                reader: R,
+                limit: u64,
+            ) -> Result<V, error::DatabaseError> {
+                let mut encoded = vec![];
+                reader.take(limit).read_to_end(&mut encoded)?;
+                encoded.iter_mut().for_each(|b| *b = !*b);
+
+                Ok(bincode::deserialize(&encoded).unwrap())
+            }
+        }
+
+        /// A codec using an identifier outside of [codec::USER_IDS].
+        struct OutOfRange;
+
+        impl codec::Codec for OutOfRange {
+            const ID: u8 = 200;
+
+            fn encode<W: Write, V: Serialize>(_: W, _: &V) -> Result<(), error::DatabaseError> {
+                unreachable!()
+            }
+
+            fn decode<R: Read, V: DeserializeOwned>(
+                _: R,
+                _: u64,
+            ) -> Result<V, error::DatabaseError> {
+                unreachable!()
+            }
+        }
+
+        let path = std::env::temp_dir().join("tinydb-user-codec.tinydb");
+        let mut my_db = Database::new("User codec", path.clone(), false);
+        my_db.add_item(String::from("Duane Dibbley"))?;
+        my_db.dump_with_codec::<Inverted>()?;
+
+        assert!(matches!(
+            Database::<String>::from(&path),
+            Err(error::DatabaseError::UnsupportedFormat(9))
+        ));
+
+        let loaded: Database<String> = Database::from_with_codec::<Inverted>(&path)?;
+        assert_eq!(loaded.items, my_db.items);
+        assert_eq!(loaded.format, codec::Format::Custom(9));
+        assert!(matches!(
+            loaded.dump_db(),
+            Err(error::DatabaseError::UnsupportedFormat(9))
+        ));
+
+        my_db.dump_db()?;
+        assert_eq!(Database::from_with_codec::<Inverted>(&path)?, my_db);
+        assert!(matches!(
+            my_db.dump_with_codec::<OutOfRange>(),
+            Err(error::DatabaseError::UnsupportedFormat(200))
+        ));
+
+        let mut buffer = vec![];
+        my_db.format = codec::Format::Custom(200);
+        assert!(matches!(
+            my_db.dump_to(&mut buffer),
+            Err(error::DatabaseError::UnsupportedFormat(200))
+        ));
+        my_db.format = codec::Format::Custom(9);
+        assert!(matches!(
+            my_db.dump_to(&mut buffer),
+            Err(error::DatabaseError::UnsupportedFormat(9))
+        ));
+        assert!(buffer.is_empty());
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    /// Tests that databases round-trip through [Database::dump_db] and
+    /// [Database::from] in every enabled [codec::Format], which is detected
+    /// from the header.
+    #[test]
+    fn db_formats() -> Result<(), error::DatabaseError> {
+        let formats = vec![
+            codec::Format::Bincode,
+            #[cfg(feature = "json")]
+            codec::Format::Json,
+            #[cfg(feature = "cbor")]
+            codec::Format::Cbor,
+            #[cfg(feature = "msgpack")]
+            codec::Format::MessagePack,
+            #[cfg(feature = "ron")]
+            codec::Format::Ron,
+        ];
+
+        for format in formats {
+            let path = std::env::temp_dir().join(format!("tinydb-format-{}.tinydb", format.id()));
+            let mut my_db = Database::new("Format test", path.clone(), false);
+            my_db.format = format;
+            my_db.add_item(DemoStruct {
+                name: String::from("Kryten"),
+                age: 3000,
+            })?;
+            my_db.dump_db()?;
+
+            let loaded: Database<DemoStruct> = Database::from(&path)?;
+            assert_eq!(loaded, my_db);
+            assert!(matches!(
+                Database::<DemoStruct>::verify(&path)?.items,
+                verify::Check::Passed(1)
+            ));
+
+            let mut items = HashSet::new();
+            Database::for_each_item(&path, |item| {
+                items.insert(item);
+            })?;
+            assert_eq!(items, my_db.items);
+        }
+
+        Ok(())
+    }
+
+    /// Tests that databases round-trip through every enabled
+    /// [compression::Compression], which is detected from the header.
+    #[test]
+    fn db_compression() -> Result<(), error::DatabaseError> {
+        let algorithms = vec![
+            compression::Compression::None,
+            #[cfg(feature = "zstd")]
+            compression::Compression::Zstd(7),
+            #[cfg(feature = "lz4")]
+            compression::Compression::Lz4,
+        ];
+
+        for algorithm in algorithms {
+            let path =
+                std::env::temp_dir().join(format!("tinydb-compression-{}.tinydb", algorithm.id()));
+            let mut my_db = Database::new("Compression test", path.clone(), false);
+            my_db.compression = algorithm;
+
+            for age in 0..100 {
+                my_db.add_item(DemoStruct {
+                    name: String::from("Duane Dibbley"),
+                    age,
+                })?;
+            }
+            my_db.dump_db()?;
+
+            let loaded: Database<DemoStruct> = Database::from(&path)?;
+            assert_eq!(loaded.compression.id(), algorithm.id());
+            assert_eq!(loaded.items, my_db.items);
+            assert!(Database::<DemoStruct>::verify(&path)?.is_ok());
+        }
+
+        Ok(())
+    }
+
+    /// Tests that databases round-trip through in-memory streams in the same
+    /// layout as files, without a path in decoding errors.
+    #[test]
+    fn db_streams() -> Result<(), error::DatabaseError> {
+        let (path, dump) = small_dump("streams")?;
+        let my_db: Database<DemoStruct> = Database::from(&path)?;
+
+        let mut buffer = vec![];
+        my_db.dump_to(&mut buffer)?;
+        assert_eq!(buffer, dump);
+        assert_eq!(Database::<DemoStruct>::load_from(&buffer[..])?, my_db);
+
+        let truncated = reseal(&buffer[..buffer.len() - 4]);
+        assert!(matches!(
+            Database::<DemoStruct>::load_from(&truncated[..]),
+            Err(error::DatabaseError::DecodeFailed { path: None, .. })
+        ));
+        assert!(matches!(
+            Database::<DemoStruct>::load_from(&b"TINY"[..]),
+            Err(error::DatabaseError::NotTinyDb)
+        ));
+
+        Ok(())
+    }
+
+    /// Tests that [Database::for_each_item] reports corruption found after
+    /// items have been passed on.
+    #[test]
+    fn for_each_item_corrupted() -> Result<(), error::DatabaseError> {
+        let (path, mut dump) = small_dump("for-each-corrupted")?;
+        let last = dump.len() - checksum::CHECKSUM_LEN - 1;
+        dump[last] ^= 0b0000_0001;
+        std::fs::write(&path, &dump)?;
+
+        let mut seen = 0;
+        assert!(matches!(
+            Database::for_each_item(&path, |_: DemoStruct| seen += 1),
+            Err(error::DatabaseError::ChecksumMismatch)
+        ));
+        assert_eq!(seen, 1);
+
+        Ok(())
     }
 
-    /// Tests addition to in-memory db
-    #[test]
-    fn item_add() -> Result<(), error::DatabaseError> {
-        let mut my_db = Database::new("Adding test", None, true);
+    /// Tests that [Database::auto_from] picks the format of new databases from
+    /// their extension.
+    #[cfg(feature = "json")]
+    #[test]
+    fn auto_from_extension() -> Result<(), error::DatabaseError> {
+        let path = std::env::temp_dir().join("tinydb-extension.json");
+        let _ = std::fs::remove_file(&path);
+
+        let db: Database<DemoStruct> = Database::auto_from(&path, false)?;
+        assert_eq!(db.format, codec::Format::Json);
+
+        Ok(())
+    }
+
+    /// Tests that mutations logged to the write-ahead log are replayed by
+    /// [Database::from] and folded away by [Database::checkpoint].
+    #[test]
+    fn wal_replay() -> Result<(), error::DatabaseError> {
+        let path = std::env::temp_dir().join("tinydb-wal-replay.tinydb");
+        let wal_path = wal::wal_path(&path);
+        let _ = std::fs::remove_file(&wal_path);
+
+        let lister = DemoStruct {
+            name: String::from("Lister"),
+            age: 25,
+        };
+        let rimmer = DemoStruct {
+            name: String::from("Rimmer"),
+            age: 30,
+        };
+
+        let mut my_db = Database::new("WAL test", path.clone(), true);
+        my_db.add_item(lister.clone())?;
+        my_db.dump_db()?;
+
+        my_db.enable_wal(wal::SyncPolicy::Always)?;
+        my_db.add_item(rimmer.clone())?;
+        my_db.update_item(
+            &lister,
+            DemoStruct {
+                name: String::from("Lister"),
+                age: 26,
+            },
+        )?;
+        my_db.remove_item(&rimmer)?;
+        my_db.add_item(rimmer.clone())?;
+
+        let recovered: Database<DemoStruct> = Database::from(&path)?;
+        assert_eq!(recovered, my_db); // Dump only contains the original lister
+
+        my_db.checkpoint()?;
+        assert_eq!(std::fs::metadata(&wal_path)?.len(), 0);
+        assert_eq!(Database::<DemoStruct>::from(&path)?, my_db);
+
+        Ok(())
+    }
+
+    /// Tests that the write-ahead log of a renamed dump is replayed from next
+    /// to the dump, rather than from next to the path it was dumped to.
+    #[test]
+    fn wal_renamed() -> Result<(), error::DatabaseError> {
+        let path = std::env::temp_dir().join("tinydb-wal-renamed.tinydb");
+        let renamed = std::env::temp_dir().join("tinydb-wal-renamed-copy.tinydb");
+        for file in [&path, &renamed] {
+            let _ = std::fs::remove_file(wal::wal_path(file));
+        }
+
+        let mut my_db = Database::new("WAL rename test", path.clone(), false);
+        my_db.add_item(String::from("Lister"))?;
+        my_db.dump_db()?;
+        my_db.enable_wal(wal::SyncPolicy::Always)?;
+        my_db.add_item(String::from("Rimmer"))?;
+        drop(my_db);
+
+        std::fs::rename(&path, &renamed)?;
+        std::fs::rename(wal::wal_path(&path), wal::wal_path(&renamed))?;
+
+        let mut loaded: Database<String> = Database::from(&renamed)?;
+        assert!(loaded.contains(&String::from("Rimmer")));
+
+        loaded.enable_wal(wal::SyncPolicy::Always)?;
+        loaded.add_item(String::from("Cat"))?;
+        assert!(!wal::wal_path(&path).exists());
+        assert!(Database::<String>::from(&renamed)?.contains(&String::from("Cat")));
+
+        loaded.checkpoint()?;
+        assert!(!path.exists());
+        assert_eq!(Database::<String>::from(&renamed)?, loaded);
+
+        std::fs::remove_file(&renamed)?;
+        std::fs::remove_file(wal::wal_path(&renamed))?;
+
+        Ok(())
+    }
+
+    /// Tests that encrypted dumps round-trip with the right key, don't leak
+    /// their contents and can't be loaded without or with the wrong key.
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn db_encryption() -> Result<(), error::DatabaseError> {
+        let path = std::env::temp_dir().join("tinydb-encryption.tinydb");
+        let key = encryption::Key::generate();
+
+        let mut my_db = Database::new("Encryption test", path.clone(), false);
+        my_db.set_key(key.clone())?;
+        my_db.add_item(DemoStruct {
+            name: String::from("Holly"),
+            age: 3_000_000,
+        })?;
+        my_db.dump_db()?;
+
+        let dump = std::fs::read(&path)?;
+        assert!(!dump.windows(5).any(|window| window == b"Holly"));
+
+        let loaded: Database<DemoStruct> = Database::from_with_key(&path, key)?;
+        assert_eq!(loaded, my_db);
+
+        assert!(matches!(
+            Database::<DemoStruct>::from(&path),
+            Err(error::DatabaseError::KeyRequired)
+        ));
+        assert!(matches!(
+            Database::<DemoStruct>::from_with_key(&path, encryption::Key::generate()),
+            Err(error::DatabaseError::DecryptionFailed)
+        ));
+
+        let report = Database::<DemoStruct>::verify(&path)?;
+        assert!(report.is_ok());
+        assert!(report.checksum.is_passed());
+        assert!(matches!(report.items, verify::Check::Skipped));
+
+        my_db.remove_key()?;
+        my_db.dump_db()?;
+        assert_eq!(Database::<DemoStruct>::from(&path)?, my_db);
+        assert!(matches!(
+            encryption::rotate_key::<DemoStruct>(
+                &path,
+                &encryption::Key::generate(),
+                &encryption::Key::generate()
+            ),
+            Err(error::DatabaseError::NotEncrypted)
+        ));
+
+        Ok(())
+    }
+
+    /// Tests that the write-ahead log of an encrypted database is encrypted
+    /// too and survives rotating the key, even if rotating fails part-way.
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn wal_encryption() -> Result<(), error::DatabaseError> {
+        let path = std::env::temp_dir().join("tinydb-wal-encryption.tinydb");
+        let wal_path = wal::wal_path(&path);
+        let _ = std::fs::remove_file(&wal_path);
+        let (old, new) = (encryption::Key::generate(), encryption::Key::generate());
+
+        let mut my_db = Database::new("WAL encryption test", path.clone(), false);
+        my_db.set_key(old.clone())?;
+        my_db.dump_db()?;
+
+        my_db.enable_wal(wal::SyncPolicy::Always)?;
+        my_db.add_item(DemoStruct {
+            name: String::from("Cat"),
+            age: 3,
+        })?;
+        drop(std::mem::take(&mut my_db.storage)); // Closes the log
+
+        let log = std::fs::read(&wal_path)?;
+        assert!(!log.windows(3).any(|window| window == b"Cat"));
+
+        /// Always fails to provide a key, as if a key management service is
+        /// down.
+        struct Unavailable;
+
+        impl encryption::KeyProvider for Unavailable {
+            fn key(&self) -> Result<encryption::Key, error::DatabaseError> {
+                Err(error::DatabaseError::KeyRequired)
+            }
+        }
+
+        assert!(matches!(
+            encryption::rotate_key::<DemoStruct>(&path, &old, &Unavailable),
+            Err(error::DatabaseError::KeyRequired)
+        ));
+        assert!(!wal_path.exists()); // Folded into the dump before failing
+        assert_eq!(
+            Database::<DemoStruct>::from_with_key(&path, old.clone())?,
+            my_db
+        );
+
+        encryption::rotate_key::<DemoStruct>(&path, &old, &new)?;
+
+        assert!(matches!(
+            Database::<DemoStruct>::from_with_key(&path, old),
+            Err(error::DatabaseError::DecryptionFailed)
+        ));
+        assert_eq!(Database::<DemoStruct>::from_with_key(&path, new)?, my_db);
+
+        Ok(())
+    }
+
+    /// Tests that setting and removing a key whilst the write-ahead log is
+    /// enabled moves the log over to the new key, rather than mixing records
+    /// sealed with different keys.
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn wal_key_changes() -> Result<(), error::DatabaseError> {
+        let path = std::env::temp_dir().join("tinydb-wal-key-changes.tinydb");
+        let wal_path = wal::wal_path(&path);
+        let _ = std::fs::remove_file(&wal_path);
+        let key = encryption::Key::generate();
+        let cat = |age| DemoStruct {
+            name: String::from("Cat"),
+            age,
+        };
+
+        let mut my_db = Database::new("WAL key test", path.clone(), false);
+        my_db.enable_wal(wal::SyncPolicy::Always)?;
+        my_db.add_item(cat(1))?;
+
+        my_db.set_key(key.clone())?;
+        my_db.add_item(cat(2))?;
+        assert!(!std::fs::read(&wal_path)?
+            .windows(3)
+            .any(|window| window == b"Cat"));
+        assert_eq!(
+            Database::<DemoStruct>::from_with_key(&path, key.clone())?,
+            my_db
+        );
+
+        my_db.remove_key()?;
+        my_db.add_item(cat(3))?;
+        assert!(std::fs::read(&wal_path)?
+            .windows(3)
+            .any(|window| window == b"Cat"));
+        assert_eq!(Database::<DemoStruct>::from(&path)?.items.len(), 3);
+
+        let mut sealed = wal::Wal::open(&wal_path, wal::SyncPolicy::Always, Some(key))?;
+        sealed.append(&wal::Record::Add(&cat(4)))?;
+        assert!(matches!(
+            Database::<DemoStruct>::from(&path),
+            Err(error::DatabaseError::DecodeFailed { .. })
+        )); // Sealed records aren't mistaken for a torn tail
+
+        Ok(())
+    }
+
+    /// Tests that failed updates leave the database and write-ahead log
+    /// untouched.
+    #[test]
+    fn update_item_failure() -> Result<(), error::DatabaseError> {
+        let mut my_db = Database::new("Update test", None, true);
+
+        my_db.add_item(1)?;
+        my_db.add_item(2)?;
+
+        assert!(matches!(
+            my_db.update_item(&1, 2),
+            Err(error::DatabaseError::DupeFound)
+        ));
+        assert!(matches!(
+            my_db.update_item(&3, 4),
+            Err(error::DatabaseError::ItemNotFound)
+        ));
+        assert_eq!(my_db.len(), 2);
+
+        my_db.update_item(&1, 1)?;
+        assert!(my_db.contains(&1));
+
+        Ok(())
+    }
+
+    /// Tests that predicate queries find the same items with and without an
+    /// index narrowing them down.
+    #[test]
+    fn query_where() -> Result<(), error::DatabaseError> {
+        let mut my_db = Database::new("Predicate test", None, false);
+
+        for (name, age) in [("Lister", 25), ("Rimmer", 30), ("Kryten", 30), ("Cat", 25)] {
+            my_db.add_item(DemoStruct {
+                name: String::from(name),
+                age,
+            })?;
+        }
+
+        let older_r = |s: &DemoStruct| s.name.starts_with('R') && s.age >= 30;
+
+        assert_eq!(my_db.query_where(older_r)?[0].name, "Rimmer");
+        assert_eq!(my_db.find_where(older_r)?.name, "Rimmer");
+        assert_eq!(my_db.count_where(|s| s.age == 25), 2);
+        assert!(my_db.exists_where(|s| s.name == "Cat"));
+        assert!(!my_db.exists_where(|s| s.age > 30));
+        assert!(matches!(
+            my_db.query_where(|s| s.age > 30),
+            Err(error::DatabaseError::ItemNotFound)
+        ));
+        assert!(matches!(
+            my_db.find_where(|s| s.name.is_empty()),
+            Err(error::DatabaseError::ItemNotFound)
+        ));
+
+        let starts_r = |s: &DemoStruct| s.name.starts_with('R');
+
+        for name in ["missing", "age"] {
+            let found = my_db.query_indexed_where(name, |s| s.age, 30, starts_r)?;
+            assert_eq!(found[0].name, "Rimmer");
+            assert_eq!(found.len(), 1);
+
+            my_db.create_index("age", |s: &DemoStruct| s.age);
+        }
+
+        my_db.create_ordered_index("age", |s: &DemoStruct| s.age);
+        let found = my_db.query_range_where("age", 20..30, |s| s.name.contains('s'))?;
+        assert_eq!(found[0].name, "Lister");
+        assert_eq!(found.len(), 1);
+
+        Ok(())
+    }
+
+    /// Tests that paging through an ordered index with cursors neither skips
+    /// nor repeats items which were there all along, whilst items are added
+    /// and removed between pages.
+    #[test]
+    fn query_page() -> Result<(), error::DatabaseError> {
+        let mut my_db = Database::new("Paging test", None, false);
+        my_db.create_ordered_index("age", |s: &DemoStruct| s.age);
+
+        let crew = |name: &str, age| DemoStruct {
+            name: String::from(name),
+            age,
+        };
+
+        for (name, age) in [
+            ("Lister", 25),
+            ("Cat", 25),
+            ("Kochanski", 25),
+            ("Rimmer", 30),
+            ("Kryten", 40),
+            ("Holly", 50),
+        ] {
+            my_db.add_item(crew(name, age))?;
+        }
+
+        let first = my_db.query_page::<i32>("age", None, 2)?;
+        let mut seen: Vec<String> = first.items.iter().map(|s| s.name.clone()).collect();
+        let mut cursor = first.next;
+
+        let empty = my_db.query_page::<i32>("age", cursor.as_ref(), 0)?;
+        assert!(empty.items.is_empty() && empty.next.is_none());
+
+        my_db.remove_item(&crew(&seen[1], 25))?;
+        my_db.add_item(crew("Ace", 20))?;
+        my_db.add_item(crew("Talkie Toaster", 45))?;
+
+        while let Some(after) = cursor {
+            let page = my_db.query_page::<i32>("age", Some(&after.to_string().parse()?), 2)?;
+            seen.extend(page.items.iter().map(|s| s.name.clone()));
+            cursor = page.next;
+        }
+
+        assert!(!seen.contains(&String::from("Ace")));
+        assert_eq!(seen[3..], ["Rimmer", "Kryten", "Talkie Toaster", "Holly"]);
+
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 7);
+
+        assert_eq!(my_db.query_page::<i32>("age", None, 0)?.items.len(), 0);
+        assert!(matches!(
+            my_db.query_page::<i64>("age", None, 2),
+            Err(error::DatabaseError::IndexNotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    /// Tests that pages report items sharing a key which can't be encoded,
+    /// rather than skipping them once a page ends on one.
+    #[test]
+    fn query_page_unencodable() -> Result<(), error::DatabaseError> {
+        /// Crew member who can't be encoded whilst `body_swapped`.
+        #[derive(Debug, Eq, PartialEq, Hash, Deserialize, Clone)]
+        struct Crew {
+            age: i32,
+            body_swapped: bool,
+        }
+
+        impl Serialize for Crew {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                if self.body_swapped {
+                    return Err(serde::ser::Error::custom("mind in the wrong body"));
+                }
+
+                self.age.serialize(serializer)
+            }
+        }
 
-        my_db.add_item(DemoStruct {
-            name: String::from("John"),
-            age: 16,
+        let mut my_db = Database::new("Unencodable paging test", None, false);
+        my_db.create_ordered_index("age", |s: &Crew| s.age);
+        my_db.add_item(Crew {
+            age: 25,
+            body_swapped: false,
+        })?;
+        my_db.add_item(Crew {
+            age: 30,
+            body_swapped: true,
         })?;
 
+        assert_eq!(my_db.query_page::<i32>("age", None, 1)?.items.len(), 1);
+        assert!(matches!(
+            my_db.query_page::<i32>("age", None, 2),
+            Err(error::DatabaseError::EncodeFailed { .. })
+        ));
+
         Ok(())
     }
 
-    /// Tests removal from in-memory db
+    /// Tests that text queries find the same items as the equivalent closure.
     #[test]
-    fn item_remove() -> Result<(), error::DatabaseError> {
-        let mut my_db = Database::new("Removal test", None, true);
+    fn query_str() -> Result<(), error::DatabaseError> {
+        let mut my_db = Database::new("Text query test", None, false);
 
-        let testing_struct = DemoStruct {
-            name: String::from("Xander"),
-            age: 33,
-        };
+        for (name, age) in [("Lister", 25), ("Rimmer", 30), ("Kryten", 30), ("Cat", 25)] {
+            my_db.add_item(DemoStruct {
+                name: String::from(name),
+                age,
+            })?;
+        }
 
-        my_db.add_item(testing_struct.clone())?;
-        my_db.remove_item(&testing_struct)?;
+        let mut found: Vec<&str> = my_db
+            .query_str("age >= 30 and not name = 'Rimmer' or name ~ '*t'")?
+            .into_iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        found.sort();
+
+        assert_eq!(found, ["Cat", "Kryten"]);
+        assert_eq!(
+            my_db.query_str("age != 25")?.len(),
+            my_db.count_where(|s| s.age != 25)
+        );
+        assert!(matches!(
+            my_db.query_str("rank = 'Captain'"),
+            Err(error::DatabaseError::ItemNotFound)
+        ));
+        assert!(matches!(
+            my_db.query_str("age >"),
+            Err(error::DatabaseError::QueryParse { position: 5, .. })
+        ));
 
         Ok(())
     }
 
+    /// Tests that an item which can't be converted into a value fails text
+    /// queries instead of being left out of their results.
     #[test]
-    fn db_dump() -> Result<(), error::DatabaseError> {
-        let mut my_db = Database::new(
-            String::from("Dumping test"),
-            Some(PathBuf::from("test.tinydb")),
-            true,
-        );
+    fn query_str_unconvertible() -> Result<(), error::DatabaseError> {
+        #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+        struct Crew {
+            name: String,
+            rooms: std::collections::BTreeMap<Vec<u8>, i32>,
+        }
 
-        my_db.add_item(DemoStruct {
-            name: String::from("Xander"),
-            age: 33,
+        let mut my_db = Database::new("Unconvertible query test", None, false);
+        my_db.add_item(Crew {
+            name: String::from("Lister"),
+            rooms: Default::default(),
         })?;
-        my_db.add_item(DemoStruct {
-            name: String::from("John"),
-            age: 54,
+        my_db.add_item(Crew {
+            name: String::from("Rimmer"),
+            rooms: vec![(vec![1, 2], 3)].into_iter().collect(),
         })?;
 
-        my_db.dump_db()?;
+        assert!(lang::Expr::parse("name = 'Rimmer'")?
+            .matches(&my_db.items.iter().find(|c| c.name == "Rimmer").unwrap())
+            .is_err());
+        for query in &["name ~ '*'", "name != 'Lister'", "not name = 'Lister'"] {
+            assert!(matches!(
+                my_db.query_str(query),
+                Err(error::DatabaseError::EncodeFailed { .. })
+            ));
+        }
+        assert!(matches!(
+            my_db.query_field("name", lang::Op::Eq, "Rimmer"),
+            Err(error::DatabaseError::EncodeFailed { .. })
+        ));
 
         Ok(())
     }
-    /// Tests [Database::query_item]
+
+    /// Tests that items can be queried by paths to nested fields, both with
+    /// [Database::query_field] and in text queries.
     #[test]
-    fn query_item_db() {
-        let mut my_db = Database::new(
-            String::from("Query test"),
-            Some(PathBuf::from("test.tinydb")),
-            true,
-        );
+    fn query_field() -> Result<(), error::DatabaseError> {
+        #[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+        struct Ship {
+            name: String,
+            crew: Vec<DemoStruct>,
+        }
 
-        my_db
-            .add_item(DemoStruct {
-                name: String::from("Rimmer"),
-                age: 5,
-            })
-            .unwrap();
-        my_db
-            .add_item(DemoStruct {
-                name: String::from("Cat"),
-                age: 10,
-            })
-            .unwrap();
-        my_db
-            .add_item(DemoStruct {
+        let mut my_db = Database::new("Field path test", None, false);
+        my_db.add_item(Ship {
+            name: String::from("Red Dwarf"),
+            crew: vec![
+                DemoStruct {
+                    name: String::from("Lister"),
+                    age: 25,
+                },
+                DemoStruct {
+                    name: String::from("Rimmer"),
+                    age: 30,
+                },
+            ],
+        })?;
+        my_db.add_item(Ship {
+            name: String::from("Starbug"),
+            crew: vec![DemoStruct {
                 name: String::from("Kryten"),
                 age: 3000,
-            })
-            .unwrap();
-        my_db
-            .add_item(DemoStruct {
-                name: String::from("Lister"),
-                age: 62,
-            })
-            .unwrap();
+            }],
+        })?;
 
         assert_eq!(
-            my_db.query_item(|f| &f.age, 62).unwrap(),
-            &DemoStruct {
-                name: String::from("Lister"),
-                age: 62,
-            }
-        ); // Finds "Lister" by searching [DemoStruct::age]
+            my_db.query_field("crew.1.name", lang::Op::Eq, "Rimmer")?[0].name,
+            "Red Dwarf"
+        );
         assert_eq!(
-            my_db.query_item(|f| &f.name, String::from("Cat")).unwrap(),
-            &DemoStruct {
-                name: String::from("Cat"),
-                age: 10,
-            }
-        ); // Finds "Cat" by searching [DemoStruct::name]
+            my_db.query_field("crew.0.age", lang::Op::Gt, 100)?[0].name,
+            "Starbug"
+        );
+        assert_eq!(my_db.query_str("crew.0.name ~ '*e*'")?.len(), 2);
+        assert!(matches!(
+            my_db.query_field("captain.name", lang::Op::Eq, "Hollister"),
+            Err(error::DatabaseError::ItemNotFound)
+        ));
+
+        Ok(())
     }
 
-    /// Tests [Database::query]
+    /// Tests that prefix and fuzzy queries follow every kind of mutation.
     #[test]
-    fn query_db() {
-        let mut my_db = Database::new(
-            String::from("Query test"),
-            Some(PathBuf::from("test.tinydb")),
-            false,
-        );
+    fn query_prefix() -> Result<(), error::DatabaseError> {
+        let mut my_db = Database::new("Prefix test", None, false);
+        my_db.create_prefix_index("name", |s: &DemoStruct| s.name.to_lowercase());
 
-        my_db
-            .add_item(DemoStruct {
+        for (name, age) in [("Rimmer", 30), ("Holly", 3000000), ("Hollister", 50)] {
+            my_db.add_item(DemoStruct {
+                name: String::from(name),
+                age,
+            })?;
+        }
+        my_db.update_item(
+            &DemoStruct {
                 name: String::from("Rimmer"),
-                age: 5,
-            })
-            .unwrap();
-        my_db
-            .add_item(DemoStruct {
+                age: 30,
+            },
+            DemoStruct {
+                name: String::from("Ace Rimmer"),
+                age: 30,
+            },
+        )?;
+
+        let names = |items: Vec<&DemoStruct>| -> Vec<String> {
+            items.into_iter().map(|s| s.name.clone()).collect()
+        };
+
+        assert_eq!(
+            names(my_db.query_prefix("name", "holl")?),
+            ["Hollister", "Holly"]
+        );
+        assert_eq!(names(my_db.query_prefix("name", "ace")?), ["Ace Rimmer"]);
+        assert_eq!(names(my_db.query_fuzzy("name", "holy", 1)?), ["Holly"]);
+        assert_eq!(names(my_db.query_fuzzy("name", "hollie", 4)?).len(), 2);
+        assert!(matches!(
+            my_db.query_prefix("name", "rimmer"),
+            Err(error::DatabaseError::ItemNotFound)
+        ));
+        assert!(matches!(
+            my_db.query_fuzzy("name", "kryten", 2),
+            Err(error::DatabaseError::ItemNotFound)
+        ));
+        assert!(matches!(
+            my_db.query_prefix("age", "3"),
+            Err(error::DatabaseError::IndexNotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    /// Tests that geospatial queries follow every kind of mutation.
+    #[cfg(feature = "geo")]
+    #[test]
+    fn query_geo() -> Result<(), error::DatabaseError> {
+        let mut my_db = Database::new("Geo test", None, false);
+        my_db.create_geo_index("location", |s: &DemoStruct| {
+            geo::Point::new(s.age as f64, 0.0)
+        });
+
+        for (name, age) in [("Lister", 25), ("Rimmer", 30), ("Cat", 18)] {
+            my_db.add_item(DemoStruct {
+                name: String::from(name),
+                age,
+            })?;
+        }
+        my_db.update_item(
+            &DemoStruct {
                 name: String::from("Cat"),
-                age: 10,
-            })
-            .unwrap();
-        my_db
-            .add_item(DemoStruct {
-                name: String::from("Kryten"),
-                age: 3000,
-            })
-            .unwrap();
-        my_db
-            .add_item(DemoStruct {
-                name: String::from("Lister"),
-                age: 62,
-            })
-            .unwrap();
+                age: 18,
+            },
+            DemoStruct {
+                name: String::from("Cat"),
+                age: 29,
+            },
+        )?;
+        my_db.remove_item(&DemoStruct {
+            name: String::from("Lister"),
+            age: 25,
+        })?;
 
-        my_db
-            .add_item(DemoStruct {
-                name: String::from("Lister"),
-                age: 64,
-            })
-            .unwrap();
+        let names = |items: Vec<&DemoStruct>| -> Vec<String> {
+            items.into_iter().map(|s| s.name.clone()).collect()
+        };
+        let degree = geo::EARTH_RADIUS.to_radians();
 
+        assert_eq!(
+            names(my_db.query_nearest("location", geo::Point::new(31.0, 0.0), 5)?),
+            ["Rimmer", "Cat"]
+        );
+        assert_eq!(
+            names(my_db.query_within_radius(
+                "location",
+                geo::Point::new(28.0, 0.0),
+                1.5 * degree
+            )?),
+            ["Cat"]
+        );
         assert_eq!(
             my_db
-                .query(|f| &f.name, String::from("Lister"))
-                .unwrap()
+                .query_within_box(
+                    "location",
+                    geo::Point::new(0.0, -1.0),
+                    geo::Point::new(90.0, 1.0)
+                )?
                 .len(),
             2
-        ); // Finds "Lister" by searching [DemoStruct::name]
+        );
+        assert!(matches!(
+            my_db.query_within_radius("location", geo::Point::new(18.0, 0.0), degree),
+            Err(error::DatabaseError::ItemNotFound)
+        ));
+        assert!(matches!(
+            my_db.query_nearest("missing", geo::Point::new(0.0, 0.0), 1),
+            Err(error::DatabaseError::IndexNotFound(_))
+        ));
+
+        Ok(())
     }
 
-    /// Tests a [Database::from] method call
+    /// Tests that full-text searches follow every kind of mutation and rank
+    /// items by relevance.
     #[test]
-    fn db_from() -> Result<(), error::DatabaseError> {
-        let mut my_db = Database::new(
-            String::from("Dumping test"),
-            Some(PathBuf::from("test.tinydb")),
-            false,
-        );
+    fn search() -> Result<(), error::DatabaseError> {
+        let mut my_db = Database::new("Search test", None, false);
+        for (name, age) in [("Lister", 25), ("Rimmer", 30), ("Cat", 25)] {
+            my_db.add_item(DemoStruct {
+                name: String::from(name),
+                age,
+            })?;
+        }
 
-        let demo_mock = DemoStruct {
-            name: String::from("Xander"),
-            age: 33,
+        my_db.create_text_index("bio", text::Analyzer::english(), |s: &DemoStruct| {
+            Some(format!("{} is {} years old", s.name, s.age))
+        });
+        my_db.update_item(
+            &DemoStruct {
+                name: String::from("Cat"),
+                age: 25,
+            },
+            DemoStruct {
+                name: String::from("The Cat"),
+                age: 27,
+            },
+        )?;
+        my_db.remove_item(&DemoStruct {
+            name: String::from("Rimmer"),
+            age: 30,
+        })?;
+
+        let names = |query| -> Result<Vec<String>, error::DatabaseError> {
+            Ok(my_db
+                .search("bio", query)?
+                .into_iter()
+                .map(|hit| hit.item.name.clone())
+                .collect())
         };
 
-        my_db.add_item(demo_mock.clone()).unwrap();
+        assert_eq!(names("Lister")?, ["Lister"]);
+        assert_eq!(names("cat, aged 27 or 25")?, ["The Cat", "Lister"]);
+        assert_eq!(names("years")?.len(), 2);
+        assert!(matches!(
+            names("Rimmer"),
+            Err(error::DatabaseError::ItemNotFound)
+        ));
+        assert!(matches!(
+            my_db.search("missing", "Lister"),
+            Err(error::DatabaseError::IndexNotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    /// Tests that persisted full-text indexes are dumped alongside the
+    /// database and kept through dumps until they are created again, whilst
+    /// other indexes are left out.
+    #[test]
+    fn persisted_search() -> Result<(), error::DatabaseError> {
+        let path = PathBuf::from("persisted_search_test.tinydb");
+        let bio = |s: &DemoStruct| [s.name.clone()];
+        let has_text_indexes = || -> Result<bool, error::DatabaseError> {
+            Ok(header::Header::read(&mut File::open(&path)?)?.has_text_indexes())
+        };
 
+        let mut my_db = Database::new("Persisted search test", Some(path.clone()), false);
+        for (name, age) in [("Lister", 25), ("Rimmer", 30), ("Cat", 25)] {
+            my_db.add_item(DemoStruct {
+                name: String::from(name),
+                age,
+            })?;
+        }
+        my_db.create_text_index("bio", text::Analyzer::english(), bio);
         my_db.dump_db()?;
+        assert!(!has_text_indexes()?);
 
-        let db: Database<DemoStruct> = Database::from(PathBuf::from("test.tinydb"))?;
-        assert_eq!(db.label, String::from("Dumping test"));
+        assert!(matches!(
+            my_db.persist_text_index("missing", true),
+            Err(error::DatabaseError::IndexNotFound(_))
+        ));
+        my_db.persist_text_index("bio", true)?;
+        my_db.dump_db()?;
+        assert!(has_text_indexes()?);
+
+        let mut count = 0;
+        Database::for_each_item(&path, |_: DemoStruct| count += 1)?;
+        assert_eq!(count, 3);
+        assert!(matches!(
+            Database::<DemoStruct>::verify(&path)?.items,
+            verify::Check::Passed(3)
+        ));
+
+        let mut loaded: Database<DemoStruct> = Database::from(&path)?;
+        loaded.remove_item(&DemoStruct {
+            name: String::from("Rimmer"),
+            age: 30,
+        })?;
+        loaded.add_item(DemoStruct {
+            name: String::from("Kryten"),
+            age: 3000000,
+        })?;
+        loaded.dump_db()?;
+        assert!(has_text_indexes()?);
+
+        let mut loaded: Database<DemoStruct> = Database::from(&path)?;
+        loaded.create_text_index("bio", text::Analyzer::english(), bio);
+        let mut names: Vec<&str> = loaded
+            .search("bio", "lister rimmer kryten")?
+            .into_iter()
+            .map(|hit| hit.item.name.as_str())
+            .collect();
+        names.sort_unstable();
+        assert_eq!(names, ["Kryten", "Lister"]);
+
+        loaded.dump_db()?;
+        assert!(has_text_indexes()?);
+
+        let mut loaded: Database<DemoStruct> = Database::from(&path)?;
+        assert!(loaded.drop_index("bio"));
+        loaded.dump_db()?;
+        assert!(!has_text_indexes()?);
+
+        for format in (0..8).filter_map(|id| codec::Format::from_id(id).ok()) {
+            let mut buffer = vec![];
+            my_db.format = format;
+            #[cfg(feature = "zstd")]
+            {
+                my_db.compression = compression::Compression::Zstd(3);
+            }
+            my_db.dump_to(&mut buffer)?;
+
+            let mut loaded: Database<DemoStruct> = Database::load_from(&buffer[..])?;
+            loaded.create_text_index("bio", text::Analyzer::english(), bio);
+            assert_eq!(loaded.search("bio", "cat")?[0].item.name, "Cat");
+        }
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    /// Tests that joins find the same pairs with and without an index on the
+    /// right side.
+    #[test]
+    fn joins() -> Result<(), error::DatabaseError> {
+        let mut crew = Database::new("Crew", None, false);
+        let mut bunks: Database<(i32, String)> = Database::new("Bunks", None, false);
+
+        for (name, age) in [("Lister", 25), ("Rimmer", 30), ("Cat", 40)] {
+            crew.add_item(DemoStruct {
+                name: String::from(name),
+                age,
+            })?;
+        }
+        for (age, bunk) in [(25, "top"), (30, "bottom"), (30, "hologram"), (50, "none")] {
+            bunks.add_item((age, String::from(bunk)))?;
+        }
+
+        for _ in 0..2 {
+            let mut joined: Vec<(&str, &str)> = crew
+                .join(&bunks, "age", |s| s.age, |b| b.0)
+                .into_iter()
+                .map(|(s, b)| (s.name.as_str(), b.1.as_str()))
+                .collect();
+            joined.sort();
+            assert_eq!(
+                joined,
+                [
+                    ("Lister", "top"),
+                    ("Rimmer", "bottom"),
+                    ("Rimmer", "hologram")
+                ]
+            );
+
+            let left = crew.left_join(&bunks, "age", |s| s.age, |b| b.0);
+            assert_eq!(left.len(), 4);
+            assert!(left.contains(&(
+                &DemoStruct {
+                    name: String::from("Cat"),
+                    age: 40
+                },
+                None
+            )));
+
+            bunks.create_index("age", |b: &(i32, String)| b.0);
+        }
+
+        Ok(())
+    }
+
+    /// Tests that indexes follow every kind of mutation and give the same
+    /// results as scanning, which is used when there is no matching index.
+    #[test]
+    fn query_indexed() -> Result<(), error::DatabaseError> {
+        let age = |s: &DemoStruct| s.age;
+        let mut my_db = Database::new("Index test", None, false);
+
+        for (name, age) in [("Lister", 25), ("Rimmer", 30), ("Cat", 25)] {
+            my_db.add_item(DemoStruct {
+                name: String::from(name),
+                age,
+            })?;
+        }
+
+        my_db.create_index("age", age);
+        let cat = my_db.query_item(|s| &s.name, String::from("Cat"))?.clone();
+        my_db.update_item(
+            &cat,
+            DemoStruct {
+                name: String::from("Cat"),
+                age: 30,
+            },
+        )?;
+        my_db.remove_item(&DemoStruct {
+            name: String::from("Rimmer"),
+            age: 30,
+        })?;
+        my_db.add_item(DemoStruct {
+            name: String::from("Kryten"),
+            age: 25,
+        })?;
+
+        for name in ["age", "missing"] {
+            let mut found: Vec<&str> = my_db
+                .query_indexed(name, age, 25)?
+                .into_iter()
+                .map(|s| s.name.as_str())
+                .collect();
+            found.sort();
+
+            assert_eq!(found, ["Kryten", "Lister"]);
+            assert_eq!(my_db.query_indexed(name, age, 30)?.len(), 1);
+            assert!(matches!(
+                my_db.query_indexed(name, age, 40),
+                Err(error::DatabaseError::ItemNotFound)
+            ));
+        }
+
+        assert_eq!(my_db.clone().query_indexed("age", age, 25)?.len(), 2);
+        assert!(my_db.drop_index("age"));
+        assert!(!my_db.drop_index("age"));
+
+        Ok(())
+    }
+
+    /// Tests range, min, max, first and last queries on an ordered index as
+    /// items change.
+    #[test]
+    fn query_ordered() -> Result<(), error::DatabaseError> {
+        let mut my_db = Database::new("Ordered index test", None, false);
+        my_db.create_ordered_index("age", |s: &DemoStruct| s.age);
+
+        for (name, age) in [("Lister", 25), ("Rimmer", 30), ("Cat", 18), ("Holly", 3)] {
+            my_db.add_item(DemoStruct {
+                name: String::from(name),
+                age,
+            })?;
+        }
+        my_db.remove_item(&DemoStruct {
+            name: String::from("Holly"),
+            age: 3,
+        })?;
+
+        let names = |items: Vec<&DemoStruct>| -> Vec<String> {
+            items.into_iter().map(|s| s.name.clone()).collect()
+        };
+
+        assert_eq!(names(my_db.query_range("age", 18..=25)?), ["Cat", "Lister"]);
+        assert_eq!(names(my_db.query_range("age", 26..)?), ["Rimmer"]);
+        assert_eq!(my_db.query_min("age")?.name, "Cat");
+        assert_eq!(my_db.query_max("age")?.name, "Rimmer");
+        assert_eq!(names(my_db.query_first_n("age", 2)?), ["Cat", "Lister"]);
+        assert_eq!(
+            names(my_db.query_last_n("age", 5)?),
+            ["Rimmer", "Lister", "Cat"]
+        );
+
+        assert!(matches!(
+            my_db.query_range("age", 40..50),
+            Err(error::DatabaseError::ItemNotFound)
+        ));
+        assert!(matches!(
+            my_db.query_range("age", 0u8..),
+            Err(error::DatabaseError::IndexNotFound(_))
+        ));
+
+        my_db.create_index("name", |s: &DemoStruct| s.name.clone());
+        assert!(matches!(
+            my_db.query_min("name"),
+            Err(error::DatabaseError::IndexNotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    /// Tests that unique constraints are enforced by adding and updating but
+    /// not against the item being updated, and that violations leave the
+    /// database untouched.
+    #[test]
+    fn unique_constraint() -> Result<(), error::DatabaseError> {
+        let lister = DemoStruct {
+            name: String::from("Lister"),
+            age: 25,
+        };
+        let rimmer = DemoStruct {
+            name: String::from("Rimmer"),
+            age: 30,
+        };
+
+        let mut my_db = Database::new("Unique test", None, false);
+        my_db.add_item(lister.clone())?;
+        my_db.add_item(rimmer.clone())?;
+        my_db.create_unique_constraint("name", |s: &DemoStruct| s.name.clone())?;
+
+        let older_lister = DemoStruct {
+            name: String::from("Lister"),
+            age: 26,
+        };
+        let error = my_db.add_item(older_lister.clone()).unwrap_err();
+        assert!(matches!(
+            &error,
+            error::DatabaseError::UniqueViolation { constraint, .. } if constraint == "name"
+        ));
+        assert_eq!(error.existing_item::<DemoStruct>(), Some(&lister));
+
+        let renamed_rimmer = DemoStruct {
+            name: String::from("Lister"),
+            age: 30,
+        };
+        assert!(my_db.update_item(&rimmer, renamed_rimmer).is_err());
+        assert_eq!(my_db.len(), 2);
+        assert!(my_db.contains(&rimmer));
+
+        my_db.update_item(&lister, older_lister.clone())?;
+        my_db.remove_item(&older_lister)?;
+        my_db.add_item(lister)?;
+
+        assert!(matches!(
+            my_db.create_unique_constraint("age", |_: &DemoStruct| 0),
+            Err(error::DatabaseError::UniqueViolation { .. })
+        ));
+        assert!(my_db.drop_index("name"));
+        assert!(!my_db.drop_index("age"));
 
         Ok(())
     }
@@ -682,6 +4224,7 @@ mod tests {
     /// Test if the database contains that exact item, related to
     /// [Database::contains].
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn db_contains() {
         let exp_struct = DemoStruct {
             name: String::from("Xander"),
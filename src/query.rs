@@ -0,0 +1,372 @@
+//! Composable queries over a [crate::Database], see [Query].
+
+use crate::error::DatabaseError;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+/// Starting state of 64-bit FNV-1a, used by [fnv1a].
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Multiplier of 64-bit FNV-1a, used by [fnv1a].
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Filter applied to each item by a [Query].
+type Predicate<'a, T> = Box<dyn Fn(&T) -> bool + 'a>;
+
+/// Comparison between two items used to sort the results of a [Query].
+type Comparator<'a, T> = Box<dyn Fn(&T, &T) -> Ordering + 'a>;
+
+/// A query built up from filters, sort orders and paging over the items of a
+/// database, created with [crate::Database::select].
+///
+/// Nothing is evaluated until the query is iterated over, which always returns
+/// items in the same order: first by every [Query::order_by] and
+/// [Query::order_by_desc] in the order they were given, then by a fixed hash
+/// of the encoded items themselves. This means results can be paged through
+/// with [Query::offset] and [Query::limit] without items moving between pages
+/// or runs, unlike the [HashSet] iteration order of [crate::Database::query].
+/// Items which can't be encoded are tied before every other item instead.
+///
+/// # Performance
+///
+/// As the first item in order can only be known once every item has been
+/// seen, asking the iterator for its first item filters and encodes every
+/// item of the database and sorts all that match, holding a reference to each
+/// of them. With a [Query::limit] only the items up to the end of the page are
+/// fully sorted. Later items are then returned without any further work.
+/// [Query::count] filters every item but never encodes or sorts them.
+///
+/// # Examples
+///
+/// ```rust
+/// use tinydb::Database;
+///
+/// fn main() {
+///     let mut db = Database::new("select_example", None, false);
+///
+///     for age in [16, 72, 25, 41, 33] {
+///         db.add_item(age).unwrap();
+///     }
+///
+///     let page: Vec<String> = db
+///         .select()
+///         .filter(|age| *age > 18)
+///         .not(|age| *age == 41)
+///         .order_by_desc(|age| *age)
+///         .offset(1)
+///         .limit(2)
+///         .map(|age| format!("{} years", age))
+///         .collect();
+///
+///     assert_eq!(page, ["33 years", "25 years"]);
+/// }
+/// ```
+pub struct Query<'a, T> {
+    items: &'a HashSet<T>,
+    predicate: Option<Predicate<'a, T>>,
+    order: Vec<Comparator<'a, T>>,
+    offset: usize,
+    limit: Option<usize>,
+}
+
+impl<'a, T: Hash + Eq + Serialize> Query<'a, T> {
+    /// Creates a query matching all of `items`.
+    pub(crate) fn new(items: &'a HashSet<T>) -> Self {
+        Query {
+            items,
+            predicate: None,
+            order: vec![],
+            offset: 0,
+            limit: None,
+        }
+    }
+
+    /// Only matches items which also match `predicate`, the same as
+    /// [Query::and].
+    pub fn filter(self, predicate: impl Fn(&T) -> bool + 'a) -> Self {
+        self.and(predicate)
+    }
+
+    /// Only matches items which match both the query so far and `predicate`.
+    pub fn and(mut self, predicate: impl Fn(&T) -> bool + 'a) -> Self {
+        self.predicate = Some(match self.predicate.take() {
+            Some(previous) => Box::new(move |item| previous(item) && predicate(item)),
+            None => Box::new(predicate),
+        });
+        self
+    }
+
+    /// Matches items which match either the query so far or `predicate`. If
+    /// nothing has been filtered yet every item already matches, so this has
+    /// no effect.
+    pub fn or(mut self, predicate: impl Fn(&T) -> bool + 'a) -> Self {
+        if let Some(previous) = self.predicate.take() {
+            self.predicate = Some(Box::new(move |item| previous(item) || predicate(item)));
+        }
+        self
+    }
+
+    /// Only matches items which match the query so far but not `predicate`.
+    pub fn not(self, predicate: impl Fn(&T) -> bool + 'a) -> Self {
+        self.and(move |item| !predicate(item))
+    }
+
+    /// Sorts items by the key `extractor` returns, smallest first. Items with
+    /// the same key are sorted by any orders given after this one.
+    pub fn order_by<K: Ord>(mut self, extractor: impl Fn(&T) -> K + 'a) -> Self {
+        self.order
+            .push(Box::new(move |a, b| extractor(a).cmp(&extractor(b))));
+        self
+    }
+
+    /// Sorts items by the key `extractor` returns, largest first, see
+    /// [Query::order_by].
+    pub fn order_by_desc<K: Ord>(mut self, extractor: impl Fn(&T) -> K + 'a) -> Self {
+        self.order
+            .push(Box::new(move |a, b| extractor(b).cmp(&extractor(a))));
+        self
+    }
+
+    /// Skips the first `offset` matching items.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Returns at most `limit` items after the [Query::offset].
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Counts the items the query would return, without sorting them.
+    pub fn count(self) -> usize {
+        let matched = match &self.predicate {
+            Some(predicate) => self.items.iter().filter(|item| predicate(item)).count(),
+            None => self.items.len(),
+        };
+        let remaining = matched.saturating_sub(self.offset);
+
+        self.limit.map_or(remaining, |limit| remaining.min(limit))
+    }
+
+    /// Projects each item the query returns with `f`, e.g. to pick out a
+    /// single field.
+    pub fn map<U, F: FnMut(&'a T) -> U>(self, f: F) -> std::iter::Map<Iter<'a, T>, F> {
+        self.into_iter().map(f)
+    }
+
+    /// Finds, sorts and pages the matching items.
+    fn evaluate(self) -> Vec<&'a T> {
+        let Query {
+            items,
+            predicate,
+            order,
+            offset,
+            limit,
+        } = self;
+
+        let mut matched: Vec<(Option<u64>, &T)> = items
+            .iter()
+            .filter(|item| match &predicate {
+                Some(predicate) => predicate(item),
+                None => true,
+            })
+            .map(|item| (fixed_hash(item), item))
+            .collect();
+
+        let compare = |a: &(Option<u64>, &T), b: &(Option<u64>, &T)| {
+            order
+                .iter()
+                .map(|compare| compare(a.1, b.1))
+                .find(|ordering| ordering != &Ordering::Equal)
+                .unwrap_or_else(|| fixed_cmp(*a, *b))
+        };
+
+        // only the items up to the end of the page need to be fully sorted
+        let end = limit.map_or(matched.len(), |limit| offset.saturating_add(limit));
+        if end == 0 {
+            return vec![];
+        } else if end < matched.len() {
+            matched.select_nth_unstable_by(end - 1, compare);
+            matched.truncate(end);
+        }
+        matched.sort_unstable_by(compare);
+
+        matched
+            .into_iter()
+            .skip(offset)
+            .map(|(_, item)| item)
+            .collect()
+    }
+}
+
+impl<'a, T: Hash + Eq + Serialize> IntoIterator for Query<'a, T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Iter {
+            state: State::Pending(self),
+        }
+    }
+}
+
+/// Iterator over the results of a [Query], which is evaluated in full when the
+/// first item is asked for, see [Query#performance].
+pub struct Iter<'a, T> {
+    state: State<'a, T>,
+}
+
+/// Whether an [Iter] has evaluated its query yet.
+enum State<'a, T> {
+    Pending(Query<'a, T>),
+    Ready(std::vec::IntoIter<&'a T>),
+}
+
+impl<'a, T: Hash + Eq + Serialize> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let State::Pending(_) = self.state {
+            if let State::Pending(query) =
+                std::mem::replace(&mut self.state, State::Ready(vec![].into_iter()))
+            {
+                self.state = State::Ready(query.evaluate().into_iter());
+            }
+        }
+
+        match &mut self.state {
+            State::Ready(items) => items.next(),
+            State::Pending(_) => None,
+        }
+    }
+}
+
+/// Hashes `bytes` with 64-bit FNV-1a, so that it is the same between runs,
+/// platforms and Rust releases. [Hash] isn't used as neither the standard
+/// hashers nor what [Hash] implementations feed them are specified.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Hashes the bincode encoding of `item` with [fnv1a], or returns [None] if
+/// it can't be encoded.
+pub(crate) fn fixed_hash<T: Serialize>(item: &T) -> Option<u64> {
+    bincode::serialize(item).ok().map(|encoded| fnv1a(&encoded))
+}
+
+/// Orders two items paired with their [fixed_hash] in the same way between
+/// runs, by hash and then by their encoding if the hashes collide.
+pub(crate) fn fixed_cmp<T: Serialize>(a: (Option<u64>, &T), b: (Option<u64>, &T)) -> Ordering {
+    a.0.cmp(&b.0).then_with(|| {
+        bincode::serialize(a.1)
+            .ok()
+            .cmp(&bincode::serialize(b.1).ok())
+    })
+}
+
+/// Encodes `item` with bincode, returning it after its [fnv1a] hash. Unlike a
+/// [fixed_hash] alone, this orders every pair of items with different
+/// encodings, as those with colliding hashes are ordered by their encoding.
+///
+/// # Errors
+///
+/// Will return [DatabaseError::EncodeFailed] if `item` can't be encoded.
+pub(crate) fn fixed_tie<T: Serialize>(item: &T) -> Result<(u64, Vec<u8>), DatabaseError> {
+    let encoded = bincode::serialize(item)
+        .map_err(|source| DatabaseError::EncodeFailed { path: None, source })?;
+
+    Ok((fnv1a(&encoded), encoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// Crew members as (name, age) pairs.
+    fn crew() -> HashSet<(String, i32)> {
+        [
+            ("Lister", 25),
+            ("Rimmer", 30),
+            ("Kryten", 30),
+            ("Cat", 25),
+            ("Holly", 3000000),
+        ]
+        .iter()
+        .map(|(name, age)| (String::from(*name), *age))
+        .collect()
+    }
+
+    /// Tests that filters combine as expected and results are sorted by every
+    /// order given in turn, then paged.
+    #[test]
+    fn filter_sort_page() {
+        fn names<'a>(query: Query<'a, (String, i32)>) -> Vec<&'a str> {
+            query.map(|(name, _)| name.as_str()).collect()
+        }
+
+        let items = crew();
+
+        let query = Query::new(&items)
+            .filter(|(_, age)| *age < 100)
+            .not(|(name, _)| name == "Cat")
+            .or(|(name, _)| name.starts_with('H'))
+            .order_by_desc(|(_, age)| *age)
+            .order_by(|(name, _)| name.clone());
+        assert_eq!(names(query), ["Holly", "Kryten", "Rimmer", "Lister"]);
+
+        let page = |offset, limit| {
+            names(
+                Query::new(&items)
+                    .order_by(|(_, age)| *age)
+                    .offset(offset)
+                    .limit(limit),
+            )
+        };
+        let mut paged = page(0, 2);
+        paged.extend(page(2, 2));
+        paged.extend(page(4, 2));
+
+        assert_eq!(paged.len(), 5);
+        assert_eq!(paged, names(Query::new(&items).order_by(|(_, age)| *age)));
+        assert_eq!(page(5, 2), Vec::<&str>::new());
+        assert_eq!(Query::new(&items).offset(1).limit(3).count(), 3);
+        assert_eq!(Query::new(&items).or(|_| false).count(), 5);
+    }
+
+    /// Tests that nothing is evaluated until the query is iterated over.
+    #[test]
+    fn lazy() {
+        let items = crew();
+        let calls = Cell::new(0);
+
+        let mut iter = Query::new(&items)
+            .filter(|_| {
+                calls.set(calls.get() + 1);
+                true
+            })
+            .into_iter();
+        assert_eq!(calls.get(), 0);
+
+        iter.next();
+        assert_eq!(calls.get(), 5);
+        assert_eq!(iter.count(), 4);
+        assert_eq!(calls.get(), 5);
+    }
+
+    /// Tests that fixed hashes are FNV-1a of the encoded item, so that they
+    /// never change between Rust releases.
+    #[test]
+    fn fixed_hashes() {
+        assert_eq!(fixed_hash(&()), Some(0xcbf2_9ce4_8422_2325));
+        assert_eq!(fixed_hash(b"a"), Some(0xaf63_dc4c_8601_ec8c));
+        assert_eq!(fixed_hash(&0u8), Some(0xaf63_bd4c_8601_b7df));
+        assert_eq!(fixed_tie(&0u8).unwrap(), (0xaf63_bd4c_8601_b7df, vec![0]));
+    }
+}
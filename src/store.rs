@@ -0,0 +1,352 @@
+//! Persistence shared by every kind of database: where it is dumped to, how it
+//! is loaded and dumped, its encryption key and its write-ahead log.
+//!
+//! [crate::Database] and [crate::keyed::KeyedDatabase] both implement [Store],
+//! only differing in how they describe themselves and apply logged mutations,
+//! and expose its methods under their own documentation.
+
+#[cfg(feature = "encryption")]
+use crate::encryption;
+use crate::error::DatabaseError;
+use crate::{
+    atomic, codec, compression, header, label_from_path, open_path, read_dump, read_stream, text,
+    wal, write_dump, CipherKey,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "encryption")]
+use std::sync::Arc;
+
+/// State of a database which is never dumped alongside it.
+#[derive(Debug, Default)]
+pub(crate) struct Storage {
+    /// Path of the dump the database was loaded from, which its write-ahead
+    /// log lives next to instead of the path it dumps to
+    path: Option<PathBuf>,
+
+    /// Write-ahead log mutations are appended to, if enabled with
+    /// [Store::enable_wal]
+    wal: Option<wal::Wal>,
+
+    #[cfg(feature = "encryption")]
+    key: Option<Arc<dyn encryption::KeyProvider>>,
+}
+
+impl Clone for Storage {
+    /// Clones the storage, leaving the clone without a write-ahead log so that
+    /// it can't append to the same log as the original.
+    fn clone(&self) -> Self {
+        Storage {
+            path: self.path.clone(),
+            wal: None,
+            #[cfg(feature = "encryption")]
+            key: self.key.clone(),
+        }
+    }
+}
+
+impl Storage {
+    /// Appends a mutation to the write-ahead log if it is enabled, see
+    /// [Store::log].
+    pub(crate) fn log<R: Serialize>(&mut self, record: &R) -> Result<(), DatabaseError> {
+        match self.wal.as_mut() {
+            Some(wal) => wal.append(record),
+            None => Ok(()),
+        }
+    }
+
+    /// Sets the key provider directly, without moving anything on disk over
+    /// to it.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn set_key(&mut self, provider: Arc<dyn encryption::KeyProvider>) {
+        self.key = Some(provider);
+    }
+}
+
+/// A database which can be dumped to and loaded from disk.
+pub(crate) trait Store: Serialize + DeserializeOwned {
+    /// A single mutation appended to the write-ahead log.
+    type Record: DeserializeOwned;
+
+    /// Kind of database recorded in dumps, which they must match to be loaded.
+    const KIND: header::Kind;
+
+    /// Returns the label the database falls back to dumping as.
+    fn label(&self) -> &str;
+
+    /// Returns the path the database dumps to, if one has been set.
+    fn save_path(&self) -> Option<&PathBuf>;
+
+    /// Returns the format, compression and schema the database dumps with.
+    fn layout(&self) -> (codec::Format, compression::Compression, u64);
+
+    /// Sets the format, compression and schema of a loaded dump.
+    fn set_layout(
+        &mut self,
+        format: codec::Format,
+        compression: compression::Compression,
+        schema: u64,
+    );
+
+    /// Returns the state which isn't dumped.
+    #[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+    fn storage(&self) -> &Storage;
+
+    /// Returns the state which isn't dumped, mutably.
+    fn storage_mut(&mut self) -> &mut Storage;
+
+    /// Applies the mutations of write-ahead log `records` to the items, in
+    /// order. Applying the same records twice must have no further effect.
+    fn apply_records(&mut self, records: Vec<Self::Record>);
+
+    /// Returns the text indexes persisted alongside the database, which none
+    /// are by default.
+    fn text_indexes(&self) -> text::Snapshots {
+        text::Snapshots::new()
+    }
+
+    /// Keeps the text indexes loaded alongside the database until they are
+    /// created again, ignoring them by default.
+    fn set_text_indexes(&mut self, _indexes: text::Snapshots) {}
+
+    /// Returns the path the database dumps to, which is made from its label
+    /// if it doesn't have one.
+    fn smart_path_get(&self) -> PathBuf {
+        match self.save_path() {
+            Some(path) => path.clone(),
+            None => PathBuf::from(format!("{}.tinydb", self.label())),
+        }
+    }
+
+    /// Returns the path of the dump the write-ahead log belongs to, which is
+    /// the path the database was loaded from or otherwise the path it dumps
+    /// to.
+    fn log_home(&self) -> PathBuf {
+        match &self.storage().path {
+            Some(path) => path.clone(),
+            None => self.smart_path_get(),
+        }
+    }
+
+    /// Loads a dump from `path`, decrypting it and the write-ahead log next to
+    /// it with `key` if they are encrypted. The dump is decoded with `C` if it
+    /// is in its format, which is [codec::Bincode] when there is no user codec
+    /// as it is built-in anyway. Files without a header are only decoded as
+    /// dumps written before headers were added if `legacy` is set.
+    fn load<C: codec::Codec>(
+        path: PathBuf,
+        key: CipherKey,
+        legacy: bool,
+    ) -> Result<Self, DatabaseError> {
+        let (file, len) = open_path(&path)?;
+
+        let mut decoded = Self::read_from::<C, _>(BufReader::new(file), len, &key, legacy)
+            .map_err(|e| e.with_path(&path))?;
+        decoded.storage_mut().path = Some(path);
+        decoded.replay_wal(&key)?;
+
+        Ok(decoded)
+    }
+
+    /// Loads a dump from `path` as in [Store::load], decrypting it with the key
+    /// given by `provider` which is kept to encrypt future dumps.
+    #[cfg(feature = "encryption")]
+    fn load_with_key(
+        path: PathBuf,
+        provider: Arc<dyn encryption::KeyProvider>,
+    ) -> Result<Self, DatabaseError> {
+        let mut db = Self::load::<codec::Bincode>(path, Some(provider.key()?), false)?;
+        db.storage_mut().key = Some(provider);
+
+        Ok(db)
+    }
+
+    /// Loads the dump at `path` if it exists, otherwise creating a database
+    /// with `new` given the label and path, in the format of its extension.
+    /// Either way, any write-ahead log next to the path is replayed.
+    fn auto_load(
+        path: PathBuf,
+        new: impl FnOnce(String, PathBuf) -> Self,
+    ) -> Result<Self, DatabaseError> {
+        if path.exists() {
+            return Self::load::<codec::Bincode>(path, None, false);
+        }
+
+        let label = label_from_path(&path)?;
+        let format = codec::Format::from_extension(&path).unwrap_or_default();
+
+        let mut db = new(label, path);
+        let (_, compression, schema) = db.layout();
+        db.set_layout(format, compression, schema);
+        db.replay_wal(&None)?;
+
+        Ok(db)
+    }
+
+    /// Reads all of `reader` into memory and decodes it as a dump, decrypting
+    /// it with `key` if it is encrypted.
+    fn load_stream<R: Read>(reader: R, key: &CipherKey) -> Result<Self, DatabaseError> {
+        let stream = read_stream(reader)?;
+        Self::read_from::<codec::Bincode, _>(&stream[..], stream.len() as u64, key, false)
+    }
+
+    /// Reads and decodes a whole dump of `len` bytes from `reader`, decrypting
+    /// it with `key` if it is encrypted and decoding it with `C` or as a
+    /// `legacy` dump as in [Store::load].
+    fn read_from<C: codec::Codec, R: Read>(
+        reader: R,
+        len: u64,
+        key: &CipherKey,
+        legacy: bool,
+    ) -> Result<Self, DatabaseError> {
+        let (header, mut decoded) = read_dump::<_, Self>(
+            reader,
+            Self::KIND,
+            legacy,
+            len,
+            key,
+            |format, payload, offset, limit, text| {
+                let mut decoded: Self = format.decode::<C, _, _>(payload, offset, limit)?;
+                if let Some(text) = text {
+                    let offset = header::HEADER_LEN as u64 + 8;
+                    decoded.set_text_indexes(format.decode::<C, _, _>(
+                        text,
+                        offset,
+                        text.len() as u64,
+                    )?);
+                }
+
+                Ok(decoded)
+            },
+        )?;
+        decoded.set_layout(header.format()?, header.compression()?, header.schema());
+
+        Ok(decoded)
+    }
+
+    /// Dumps the database to its path in `format`, encoding it with `C` if it
+    /// is in its format as in [Store::load].
+    fn dump_db_as<C: codec::Codec>(&self, format: codec::Format) -> Result<(), DatabaseError> {
+        self.dump_at::<C>(&self.smart_path_get(), format)
+    }
+
+    /// Dumps the database to `path` in `format` as in [Store::dump_db_as].
+    fn dump_at<C: codec::Codec>(
+        &self,
+        path: &Path,
+        format: codec::Format,
+    ) -> Result<(), DatabaseError> {
+        let (_, compression, schema) = self.layout();
+
+        atomic::write_atomic(path, |dump_file| {
+            write_dump::<C, _, _>(
+                dump_file,
+                Self::KIND,
+                (format, compression, schema),
+                &self.cipher_key()?,
+                &self.text_indexes(),
+                self,
+            )
+            .map_err(|e| e.with_path(path))
+        })
+    }
+
+    /// Dumps the database to any `writer` in its own format.
+    fn dump_to<W: Write>(&self, writer: W) -> Result<(), DatabaseError> {
+        write_dump::<codec::Bincode, _, _>(
+            writer,
+            Self::KIND,
+            self.layout(),
+            &self.cipher_key()?,
+            &self.text_indexes(),
+            self,
+        )
+    }
+
+    /// Replaces the key provider, first folding the write-ahead log into a
+    /// dump under the previous key if it is enabled. The database is then
+    /// dumped again under the new key and the log reopened with it, so that
+    /// the dump and log on disk are never sealed with different keys.
+    #[cfg(feature = "encryption")]
+    fn replace_key(
+        &mut self,
+        provider: Option<Arc<dyn encryption::KeyProvider>>,
+    ) -> Result<(), DatabaseError> {
+        let policy = match self.storage().wal.as_ref() {
+            Some(wal) => wal.policy(),
+            None => {
+                self.storage_mut().key = provider;
+                return Ok(());
+            }
+        };
+
+        self.checkpoint()?;
+
+        let old = std::mem::replace(&mut self.storage_mut().key, provider);
+        if let Err(e) = self.dump_at::<codec::Bincode>(&self.log_home(), self.layout().0) {
+            self.storage_mut().key = old;
+            return Err(e);
+        }
+
+        self.storage_mut().wal = None;
+        self.enable_wal(policy)
+    }
+
+    /// Fetches the key to encrypt with from the provider, if there is one.
+    #[cfg(feature = "encryption")]
+    fn cipher_key(&self) -> Result<CipherKey, DatabaseError> {
+        let provider = self.storage().key.as_ref();
+        provider.map(|provider| provider.key()).transpose()
+    }
+
+    /// Fetches the key to encrypt with, which never exists without the
+    /// `encryption` feature.
+    #[cfg(not(feature = "encryption"))]
+    fn cipher_key(&self) -> Result<CipherKey, DatabaseError> {
+        Ok(None)
+    }
+
+    /// Enables the write-ahead log next to [Store::log_home], syncing it
+    /// according to `policy`.
+    fn enable_wal(&mut self, policy: wal::SyncPolicy) -> Result<(), DatabaseError> {
+        let path = wal::wal_path(&self.log_home());
+        let wal = wal::Wal::open(&path, policy, self.cipher_key()?)?;
+        self.storage_mut().wal = Some(wal);
+
+        Ok(())
+    }
+
+    /// Dumps the database to [Store::log_home] and then empties the
+    /// write-ahead log, as all of its mutations are now part of the dump.
+    fn checkpoint(&mut self) -> Result<(), DatabaseError> {
+        self.dump_at::<codec::Bincode>(&self.log_home(), self.layout().0)?;
+
+        match self.storage_mut().wal.as_mut() {
+            Some(wal) => wal.truncate(),
+            None => Ok(()),
+        }
+    }
+
+    /// Syncs every mutation appended to the write-ahead log so far to disk.
+    fn sync_wal(&mut self) -> Result<(), DatabaseError> {
+        match self.storage_mut().wal.as_mut() {
+            Some(wal) => wal.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Appends a mutation to the write-ahead log if it is enabled, which must
+    /// encode in the same way as a [Store::Record].
+    fn log<R: Serialize>(&mut self, record: &R) -> Result<(), DatabaseError> {
+        self.storage_mut().log(record)
+    }
+
+    /// Replays the write-ahead log next to [Store::log_home], if one exists.
+    fn replay_wal(&mut self, key: &CipherKey) -> Result<(), DatabaseError> {
+        let path = wal::wal_path(&self.log_home());
+        self.apply_records(wal::read_records(&path, key)?);
+
+        Ok(())
+    }
+}
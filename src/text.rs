@@ -0,0 +1,612 @@
+//! Full-text search over string fields of items, see
+//! [crate::Database::create_text_index] and [crate::Database::search].
+//!
+//! Text is split into words by an [Analyzer] and kept in an inverted index
+//! mapping each word to the items it appears in. Searches rank every item
+//! containing any of the query's words with [BM25], favouring items where the
+//! words are frequent and shorter items over longer ones.
+//!
+//! Indexes can be persisted in dumps with
+//! [crate::Database::persist_text_index], in which case the words of each item
+//! don't have to be found again when the index is created after loading.
+//!
+//! [BM25]: https://en.wikipedia.org/wiki/Okapi_BM25
+
+use crate::index::{AnyIndex, Extractor};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// How strongly repeated words raise a score, the usual BM25 `k1`.
+const K1: f64 = 1.2;
+
+/// How strongly longer text lowers a score, the usual BM25 `b`.
+const B: f64 = 0.75;
+
+/// Common English words ignored by [Analyzer::english].
+const ENGLISH_STOP_WORDS: [&str; 33] = [
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+/// Suffixes removed when stemming and what replaces them, tried in order.
+const SUFFIXES: [(&str, &str); 7] = [
+    ("sses", "ss"),
+    ("ies", "y"),
+    ("ss", "ss"),
+    ("ing", ""),
+    ("ed", ""),
+    ("ly", ""),
+    ("s", ""),
+];
+
+/// Splits text into the words it is indexed and searched by.
+///
+/// Text is split at anything other than letters and numbers, then lowercased.
+/// Words in [Analyzer::stop_words] are dropped and, if [Analyzer::stemming] is
+/// enabled, common English suffixes are removed so that e.g. "notes" and
+/// "note" are the same word. The default analyzer only splits and lowercases.
+///
+/// # Examples
+///
+/// ```rust
+/// use tinydb::text::Analyzer;
+///
+/// let words = Analyzer::english().analyze("The Boys from the Dwarf, singing");
+///
+/// assert_eq!(words, ["boy", "from", "dwarf", "sing"]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Analyzer {
+    /// Lowercase words which are left out of the index and queries
+    pub stop_words: HashSet<String>,
+
+    /// If plurals and suffixes such as "-ing" and "-ly" are removed from words.
+    /// This is simple suffix stripping for English rather than a full stemmer
+    pub stemming: bool,
+}
+
+impl Analyzer {
+    /// Creates an analyzer for English text, ignoring common words like "the"
+    /// and with [Analyzer::stemming] enabled.
+    pub fn english() -> Self {
+        Analyzer {
+            stop_words: ENGLISH_STOP_WORDS
+                .iter()
+                .map(|word| word.to_string())
+                .collect(),
+            stemming: true,
+        }
+    }
+
+    /// Splits `text` into words, in the order they appear.
+    pub fn analyze(&self, text: &str) -> Vec<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .filter(|word| !self.stop_words.contains(word))
+            .map(|word| if self.stemming { stem(&word) } else { word })
+            .collect()
+    }
+}
+
+/// Removes the first matching suffix from [SUFFIXES] from `word`, as long as
+/// at least three characters are left, then undoubles a trailing consonant
+/// left by "-ing" or "-ed" so that e.g. "stopped" becomes "stop".
+fn stem(word: &str) -> String {
+    for (suffix, replacement) in SUFFIXES.iter() {
+        if !word.ends_with(suffix) {
+            continue;
+        }
+
+        let base = &word[..word.len() - suffix.len()];
+        if base.chars().count() < 3 {
+            return word.into();
+        }
+
+        let mut stemmed = format!("{}{}", base, replacement);
+        if suffix.starts_with(['i', 'e']) && replacement.is_empty() {
+            let mut last = stemmed.chars().rev();
+            if let (Some(a), Some(b)) = (last.next(), last.next()) {
+                if a == b && !"aeioulsz".contains(a) {
+                    stemmed.pop();
+                }
+            }
+        }
+
+        return stemmed;
+    }
+
+    word.into()
+}
+
+/// An item found by [crate::Database::search] and how well it matched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit<'a, T> {
+    /// The matching item
+    pub item: &'a T,
+
+    /// BM25 relevance of the item to the query, where higher is better.
+    /// Scores are only comparable between hits of the same search
+    pub score: f64,
+}
+
+/// An item in a [TextIndex] and the number of words in its text.
+struct Document<T> {
+    item: Arc<T>,
+    len: u32,
+}
+
+impl<T> Clone for Document<T> {
+    fn clone(&self) -> Self {
+        Document {
+            item: self.item.clone(),
+            len: self.len,
+        }
+    }
+}
+
+/// A [TextIndex] as it is persisted in a dump, which refers to items by
+/// their [crate::query::fixed_hash] rather than holding a second copy of them.
+/// A removed item is only mistaken for another one if both their hashes are
+/// the same and neither shares it with any other item.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Snapshot {
+    analyzer: Analyzer,
+
+    /// Hash and number of words of every indexed item
+    documents: Vec<(u64, u32)>,
+
+    /// Positions in `documents` of the items containing each word, and how
+    /// often they do
+    postings: BTreeMap<String, Vec<(u64, u32)>>,
+}
+
+/// Persisted text indexes by name, see [crate::Database::persist_text_index].
+pub(crate) type Snapshots = BTreeMap<String, Snapshot>;
+
+/// Inverted index mapping each word of the text extracted from items to the
+/// items containing it and how often.
+pub(crate) struct TextIndex<T> {
+    analyzer: Analyzer,
+    extractor: Extractor<T, Vec<String>>,
+
+    /// Hashes items to refer to them in a [Snapshot], or [None] if they can't
+    /// be encoded
+    hash: fn(&T) -> Option<u64>,
+
+    /// If the index is dumped alongside the database
+    persist: bool,
+
+    /// Identifier of every indexed item, so that postings don't each need
+    /// their own reference to it
+    ids: HashMap<Arc<T>, u64>,
+    documents: HashMap<u64, Document<T>>,
+    postings: HashMap<String, HashMap<u64, u32>>,
+    next_id: u64,
+    total_len: u64,
+}
+
+impl<T: Hash + Eq> TextIndex<T> {
+    /// Creates an index over the shared `items`, splitting the text
+    /// `extractor` returns for each with `analyzer`.
+    pub(crate) fn new(
+        analyzer: Analyzer,
+        extractor: Extractor<T, Vec<String>>,
+        hash: fn(&T) -> Option<u64>,
+        items: impl IntoIterator<Item = Arc<T>>,
+    ) -> Self {
+        let mut index = TextIndex {
+            analyzer,
+            extractor,
+            hash,
+            persist: false,
+            ids: HashMap::new(),
+            documents: HashMap::new(),
+            postings: HashMap::new(),
+            next_id: 0,
+            total_len: 0,
+        };
+
+        for item in items {
+            index.add(item);
+        }
+
+        index
+    }
+
+    /// Creates a persisted index over the shared `items` from a `snapshot` of
+    /// it, as in [TextIndex::new]. Only items which aren't in the snapshot
+    /// have their text split into words, such as those added since it was
+    /// dumped or whose hash is shared with another item. The index is built
+    /// from scratch if the snapshot was taken with a different analyzer.
+    pub(crate) fn restore(
+        analyzer: Analyzer,
+        extractor: Extractor<T, Vec<String>>,
+        hash: fn(&T) -> Option<u64>,
+        snapshot: Snapshot,
+        items: Vec<Arc<T>>,
+    ) -> Self {
+        if snapshot.analyzer != analyzer {
+            let mut index = TextIndex::new(analyzer, extractor, hash, items);
+            index.persist = true;
+            return index;
+        }
+
+        let mut index = TextIndex::new(analyzer, extractor, hash, vec![]);
+        index.persist = true;
+
+        // hashes shared by several items or documents are left out, so that
+        // their items are indexed from scratch below
+        let mut by_hash: HashMap<u64, Option<&Arc<T>>> = HashMap::new();
+        for item in items.iter() {
+            if let Some(hash) = hash(item) {
+                by_hash
+                    .entry(hash)
+                    .and_modify(|found| *found = None)
+                    .or_insert(Some(item));
+            }
+        }
+        let mut documents: HashMap<u64, Option<(u64, u32)>> = HashMap::new();
+        for (position, (hash, len)) in snapshot.documents.into_iter().enumerate() {
+            documents
+                .entry(hash)
+                .and_modify(|found| *found = None)
+                .or_insert(Some((position as u64, len)));
+        }
+
+        for (hash, document) in documents {
+            if let (Some((id, len)), Some(Some(item))) = (document, by_hash.get(&hash)) {
+                index.ids.insert((*item).clone(), id);
+                index.documents.insert(
+                    id,
+                    Document {
+                        item: (*item).clone(),
+                        len,
+                    },
+                );
+                index.total_len += len as u64;
+            }
+        }
+        for (word, postings) in snapshot.postings {
+            let postings: HashMap<u64, u32> = postings
+                .into_iter()
+                .filter(|(id, _)| index.documents.contains_key(id))
+                .collect();
+
+            if !postings.is_empty() {
+                index.postings.insert(word, postings);
+            }
+        }
+        index.next_id = index.documents.keys().max().map_or(0, |id| id + 1);
+
+        for item in items {
+            index.add(item);
+        }
+
+        index
+    }
+
+    /// Takes a snapshot of the index to persist, leaving out items which can't
+    /// be hashed.
+    fn snapshot(&self) -> Snapshot {
+        let mut positions = HashMap::new();
+        let mut documents = vec![];
+        for (id, document) in self.documents.iter() {
+            if let Some(hash) = (self.hash)(&document.item) {
+                positions.insert(*id, documents.len() as u64);
+                documents.push((hash, document.len));
+            }
+        }
+
+        let postings = self
+            .postings
+            .iter()
+            .map(|(word, postings)| {
+                let mut postings: Vec<(u64, u32)> = postings
+                    .iter()
+                    .filter_map(|(id, count)| Some((*positions.get(id)?, *count)))
+                    .collect();
+                postings.sort_unstable();
+
+                (word.clone(), postings)
+            })
+            .filter(|(_, postings)| !postings.is_empty())
+            .collect();
+
+        Snapshot {
+            analyzer: self.analyzer.clone(),
+            documents,
+            postings,
+        }
+    }
+
+    /// Splits the text of `item` into words, counting how often each occurs.
+    fn words(&self, item: &T) -> (HashMap<String, u32>, u32) {
+        let mut counts = HashMap::new();
+        let mut len = 0;
+
+        for text in (self.extractor)(item) {
+            for word in self.analyzer.analyze(&text) {
+                *counts.entry(word).or_insert(0) += 1;
+                len += 1;
+            }
+        }
+
+        (counts, len)
+    }
+
+    /// Indexes `item` if it isn't already.
+    fn add(&mut self, item: Arc<T>) {
+        if self.ids.contains_key(&item) {
+            return;
+        }
+
+        let (counts, len) = self.words(&item);
+        let id = self.next_id;
+
+        for (word, count) in counts {
+            self.postings.entry(word).or_default().insert(id, count);
+        }
+        self.ids.insert(item.clone(), id);
+        self.documents.insert(id, Document { item, len });
+        self.next_id += 1;
+        self.total_len += len as u64;
+    }
+}
+
+impl<T: Hash + Eq> TextIndex<T> {
+    /// Finds every item containing any word of `query`, with its BM25 score.
+    /// Items are returned in no particular order.
+    pub(crate) fn search(&self, query: &str) -> Vec<Hit<'_, T>> {
+        let count = self.documents.len() as f64;
+        let avg_len = self.total_len as f64 / count.max(1.0);
+
+        let mut words = self.analyzer.analyze(query);
+        words.sort_unstable();
+        words.dedup();
+
+        let mut scores: HashMap<u64, f64> = HashMap::new();
+        for word in words {
+            let postings = match self.postings.get(&word) {
+                Some(postings) => postings,
+                None => continue,
+            };
+
+            let found = postings.len() as f64;
+            let idf = (1.0 + (count - found + 0.5) / (found + 0.5)).ln();
+
+            for (id, frequency) in postings {
+                let frequency = *frequency as f64;
+                let len = self.documents[id].len as f64;
+                let norm = K1 * (1.0 - B + B * len / avg_len.max(1.0));
+
+                *scores.entry(*id).or_insert(0.0) +=
+                    idf * frequency * (K1 + 1.0) / (frequency + norm);
+            }
+        }
+
+        scores
+            .into_iter()
+            .map(|(id, score)| Hit {
+                item: &*self.documents[&id].item,
+                score,
+            })
+            .collect()
+    }
+}
+
+impl<T> AnyIndex<T> for TextIndex<T>
+where
+    T: Hash + Eq + Send + Sync + 'static,
+{
+    fn insert(&mut self, item: &Arc<T>) {
+        self.add(item.clone());
+    }
+
+    fn remove(&mut self, item: &T) {
+        let id = match self.ids.remove(item) {
+            Some(id) => id,
+            None => return,
+        };
+
+        let (counts, len) = self.words(item);
+        for word in counts.keys() {
+            if let Some(postings) = self.postings.get_mut(word) {
+                postings.remove(&id);
+
+                if postings.is_empty() {
+                    self.postings.remove(word);
+                }
+            }
+        }
+        self.documents.remove(&id);
+        self.total_len -= len as u64;
+    }
+
+    fn box_clone(&self) -> Box<dyn AnyIndex<T>> {
+        Box::new(TextIndex {
+            analyzer: self.analyzer.clone(),
+            extractor: self.extractor.clone(),
+            hash: self.hash,
+            persist: self.persist,
+            ids: self.ids.clone(),
+            documents: self.documents.clone(),
+            postings: self.postings.clone(),
+            next_id: self.next_id,
+            total_len: self.total_len,
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_persist(&mut self, persist: bool) -> bool {
+        self.persist = persist;
+        true
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        if self.persist {
+            Some(TextIndex::snapshot(self))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Tests splitting, lowercasing, stop words and stemming.
+    #[test]
+    fn analyzes_text() {
+        assert_eq!(
+            Analyzer::default().analyze("Smoke me a kipper, I'll be back for BREAKFAST!"),
+            [
+                "smoke",
+                "me",
+                "a",
+                "kipper",
+                "i",
+                "ll",
+                "be",
+                "back",
+                "for",
+                "breakfast"
+            ]
+        );
+
+        let english = Analyzer::english();
+        let stems = [
+            ("dresses", "dress"),
+            ("curries", "curry"),
+            ("class", "class"),
+            ("stopped", "stop"),
+            ("running", "run"),
+            ("smelling", "smell"),
+            ("quickly", "quick"),
+            ("notes", "note"),
+            ("gas", "gas"),
+            ("ding", "ding"),
+        ];
+
+        for (word, stemmed) in stems.iter() {
+            assert_eq!(english.analyze(word), [*stemmed], "{}", word);
+        }
+        assert!(english.analyze("this is the").is_empty());
+    }
+
+    /// Tests that searches rank items by relevance and follow items being added
+    /// and removed.
+    #[test]
+    fn ranks_results() {
+        fn ranked<'a>(index: &TextIndex<&'a str>, query: &str) -> Vec<&'a str> {
+            let mut hits = index.search(query);
+            hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+            hits.into_iter().map(|hit| *hit.item).collect()
+        }
+
+        let notes = [
+            "curry curry curry",
+            "a curry on a space station",
+            "the history of the space corps, in many long and dull volumes",
+            "nothing to see here",
+        ];
+        let mut index = TextIndex::new(
+            Analyzer::english(),
+            Arc::new(|note: &&str| vec![note.to_string()]),
+            crate::query::fixed_hash::<&str>,
+            notes.iter().copied().map(Arc::new),
+        );
+
+        assert_eq!(ranked(&index, "curries"), [notes[0], notes[1]]);
+        assert_eq!(ranked(&index, "Space"), [notes[1], notes[2]]);
+        assert!(ranked(&index, "the rimmer").is_empty());
+
+        index.remove(&notes[0]);
+        index.insert(&Arc::new("rimmer's curry"));
+        index.insert(&Arc::new("rimmer's curry"));
+
+        assert_eq!(ranked(&index, "curry").len(), 2);
+        assert_eq!(ranked(&index, "rimmer"), ["rimmer's curry"]);
+        assert_eq!(index.total_len, 3 + 7 + 3 + 3);
+    }
+
+    /// Tests that an index restored from a snapshot finds the same items as
+    /// one built from scratch, even when items were changed since the snapshot
+    /// or share a hash, only extracting the text of items not in the snapshot.
+    #[test]
+    fn restores_snapshots() {
+        fn ranked<'a>(index: &TextIndex<&'a str>, query: &str) -> Vec<(&'a str, String)> {
+            let mut hits: Vec<_> = index
+                .search(query)
+                .into_iter()
+                .map(|hit| (*hit.item, format!("{:.6}", hit.score)))
+                .collect();
+            hits.sort();
+            hits
+        }
+
+        let extractor: Extractor<&str, Vec<String>> = Arc::new(|note| vec![note.to_string()]);
+        let hashes: [fn(&&'static str) -> Option<u64>; 2] = [
+            crate::query::fixed_hash::<&str>,
+            // every item shares its hash with another
+            |note| Some(note.len() as u64 % 2),
+        ];
+        let before = ["curry for one", "curry for two", "space corps directive"];
+        let after = ["curry for one", "space corps directive", "rimmer's curry"];
+
+        for (hash, extracted) in hashes.iter().copied().zip([1, 3].iter()) {
+            let mut index = TextIndex::new(
+                Analyzer::english(),
+                extractor.clone(),
+                hash,
+                before.iter().copied().map(Arc::new),
+            );
+            index.persist = true;
+            let snapshot = AnyIndex::snapshot(&index).unwrap();
+
+            let built = TextIndex::new(
+                Analyzer::english(),
+                extractor.clone(),
+                hash,
+                after.iter().copied().map(Arc::new),
+            );
+            let calls = Arc::new(AtomicUsize::new(0));
+            let counted = calls.clone();
+            let restored = TextIndex::restore(
+                Analyzer::english(),
+                Arc::new(move |note: &&str| {
+                    counted.fetch_add(1, Ordering::Relaxed);
+                    vec![note.to_string()]
+                }),
+                hash,
+                snapshot.clone(),
+                after.iter().copied().map(Arc::new).collect(),
+            );
+
+            for query in ["curry", "two", "space rimmer"].iter() {
+                assert_eq!(ranked(&restored, query), ranked(&built, query));
+            }
+            assert_eq!(restored.total_len, built.total_len);
+            assert_eq!(restored.documents.len(), 3);
+            assert_eq!(calls.load(Ordering::Relaxed), *extracted);
+
+            let rebuilt = TextIndex::restore(
+                Analyzer::default(),
+                extractor.clone(),
+                hash,
+                snapshot,
+                after.iter().copied().map(Arc::new).collect(),
+            );
+            assert_eq!(rebuilt.total_len, 3 + 3 + 3);
+        }
+    }
+}
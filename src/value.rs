@@ -0,0 +1,666 @@
+//! Dynamic values which items are converted to so that their fields can be
+//! inspected by name at runtime, see [to_value].
+
+use crate::error::DatabaseError;
+use serde::{ser, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// A dynamically typed value, mirroring the [serde] data model.
+///
+/// Structures and maps become [Value::Map]s keyed by field name, sequences and
+/// tuples become [Value::Seq]s and unit enum variants become
+/// [Value::String]s of their name. Other enum variants become a map from their
+/// name to their contents, whilst [None] and `()` become [Value::Null].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    Seq(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+    /// Returns the field called `field` if this is a [Value::Map].
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        match self {
+            Value::Map(map) => map.get(field),
+            _ => None,
+        }
+    }
+
+    /// Returns the value at `path`, which is a series of field names separated
+    /// by dots such as `address.city`. Parts which are numbers index into
+    /// [Value::Seq]s, e.g. `crew.0.name`, and an empty path returns this value
+    /// itself.
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        if path.is_empty() {
+            return Some(self);
+        }
+
+        path.split('.').try_fold(self, |value, part| match value {
+            Value::Map(map) => map.get(part),
+            Value::Seq(items) => part.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        })
+    }
+
+    /// Compares two values of the same kind, treating integers and floats as
+    /// the same kind. Returns [None] for values which can't be compared.
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Null, Value::Null) => Some(Ordering::Equal),
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+            (Value::Int(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+            (Value::Float(a), Value::Int(b)) => a.partial_cmp(&(*b as f64)),
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            (Value::Bytes(a), Value::Bytes(b)) => Some(a.cmp(b)),
+            (a, b) if a == b => Some(Ordering::Equal),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Bytes(bytes) => write!(f, "{:?}", bytes),
+            Value::Seq(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Map(map) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}: {}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(i: i32) -> Self {
+        Value::Int(i.into())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Int(i.into())
+    }
+}
+
+impl From<u32> for Value {
+    fn from(i: u32) -> Self {
+        Value::Int(i.into())
+    }
+}
+
+impl From<u64> for Value {
+    fn from(i: u64) -> Self {
+        Value::Int(i.into())
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Float(x)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+/// Converts `value` into a [Value] through its [Serialize] implementation.
+///
+/// # Examples
+///
+/// ```rust
+/// use serde::Serialize;
+/// use tinydb::value::{to_value, Value};
+///
+/// #[derive(Serialize)]
+/// struct ExampleStruct {
+///     name: String,
+///     age: i32,
+/// }
+///
+/// fn main() {
+///     let value = to_value(&ExampleStruct { name: "Kryten".into(), age: 3000 }).unwrap();
+///
+///     assert_eq!(value.get("age"), Some(&Value::Int(3000)));
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Will return [DatabaseError::EncodeFailed] if `value` fails to serialize or
+/// contains a map whose keys aren't strings, numbers, booleans or characters.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, DatabaseError> {
+    value
+        .serialize(ValueSerializer)
+        .map_err(|e| DatabaseError::EncodeFailed {
+            path: None,
+            source: Box::new(e),
+        })
+}
+
+/// Error raised whilst converting into a [Value].
+#[derive(Debug)]
+struct ValueError(String);
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ValueError {}
+
+impl ser::Error for ValueError {
+    fn custom<M: fmt::Display>(msg: M) -> Self {
+        ValueError(msg.to_string())
+    }
+}
+
+/// Serializer producing a [Value].
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = ValueError;
+    type SerializeSeq = SeqBuilder;
+    type SerializeTuple = SeqBuilder;
+    type SerializeTupleStruct = SeqBuilder;
+    type SerializeTupleVariant = SeqBuilder;
+    type SerializeMap = MapBuilder;
+    type SerializeStruct = MapBuilder;
+    type SerializeStructVariant = MapBuilder;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, ValueError> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, ValueError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, ValueError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, ValueError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, ValueError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, ValueError> {
+        Ok(Value::Int(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, ValueError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, ValueError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, ValueError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, ValueError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, ValueError> {
+        i128::try_from(v)
+            .map(Value::Int)
+            .map_err(|_| ValueError(format!("integer {} is too large", v)))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, ValueError> {
+        Ok(Value::Float(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, ValueError> {
+        Ok(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, ValueError> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, ValueError> {
+        Ok(Value::String(v.into()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, ValueError> {
+        Ok(Value::Bytes(v.into()))
+    }
+
+    fn serialize_none(self) -> Result<Value, ValueError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, ValueError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, ValueError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, ValueError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, ValueError> {
+        Ok(Value::String(variant.into()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, ValueError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, ValueError> {
+        let mut map = BTreeMap::new();
+        map.insert(variant.into(), value.serialize(self)?);
+
+        Ok(Value::Map(map))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqBuilder, ValueError> {
+        Ok(SeqBuilder {
+            variant: None,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqBuilder, ValueError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqBuilder, ValueError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqBuilder, ValueError> {
+        Ok(SeqBuilder {
+            variant: Some(variant),
+            items: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapBuilder, ValueError> {
+        Ok(MapBuilder {
+            variant: None,
+            map: BTreeMap::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<MapBuilder, ValueError> {
+        self.serialize_map(None)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapBuilder, ValueError> {
+        Ok(MapBuilder {
+            variant: Some(variant),
+            map: BTreeMap::new(),
+            key: None,
+        })
+    }
+}
+
+/// Wraps `value` in a map from the name of the enum `variant` it belongs to,
+/// if any.
+fn wrap_variant(variant: Option<&'static str>, value: Value) -> Value {
+    match variant {
+        Some(variant) => {
+            let mut map = BTreeMap::new();
+            map.insert(variant.into(), value);
+            Value::Map(map)
+        }
+        None => value,
+    }
+}
+
+/// Builds a [Value::Seq] from sequences, tuples and tuple variants.
+struct SeqBuilder {
+    variant: Option<&'static str>,
+    items: Vec<Value>,
+}
+
+impl SeqBuilder {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ValueError> {
+        self.items.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, ValueError> {
+        Ok(wrap_variant(self.variant, Value::Seq(self.items)))
+    }
+}
+
+impl ser::SerializeSeq for SeqBuilder {
+    type Ok = Value;
+    type Error = ValueError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ValueError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, ValueError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqBuilder {
+    type Ok = Value;
+    type Error = ValueError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ValueError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, ValueError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqBuilder {
+    type Ok = Value;
+    type Error = ValueError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ValueError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, ValueError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqBuilder {
+    type Ok = Value;
+    type Error = ValueError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ValueError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, ValueError> {
+        self.finish()
+    }
+}
+
+/// Builds a [Value::Map] from maps, structures and struct variants.
+struct MapBuilder {
+    variant: Option<&'static str>,
+    map: BTreeMap<String, Value>,
+    key: Option<String>,
+}
+
+impl MapBuilder {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), ValueError> {
+        self.map.insert(key, value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, ValueError> {
+        Ok(wrap_variant(self.variant, Value::Map(self.map)))
+    }
+}
+
+impl ser::SerializeMap for MapBuilder {
+    type Ok = Value;
+    type Error = ValueError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), ValueError> {
+        self.key = Some(match key.serialize(ValueSerializer)? {
+            Value::String(s) => s,
+            Value::Int(i) => i.to_string(),
+            Value::Float(x) => x.to_string(),
+            Value::Bool(b) => b.to_string(),
+            other => return Err(ValueError(format!("unsupported map key {}", other))),
+        });
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ValueError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| ValueError(String::from("map value without a key")))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value, ValueError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapBuilder {
+    type Ok = Value;
+    type Error = ValueError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), ValueError> {
+        self.insert(key.into(), value)
+    }
+
+    fn end(self) -> Result<Value, ValueError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapBuilder {
+    type Ok = Value;
+    type Error = ValueError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), ValueError> {
+        self.insert(key.into(), value)
+    }
+
+    fn end(self) -> Result<Value, ValueError> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Serialize)]
+    enum Rank {
+        Captain,
+        Technician(u8),
+        Hologram { light_bee: bool },
+    }
+
+    #[derive(Serialize)]
+    struct Crew {
+        name: String,
+        rank: Rank,
+        bunk: Option<(u32, char)>,
+        quarters: HashMap<u32, String>,
+    }
+
+    /// Tests that structures, enums, options, tuples and maps are converted
+    /// into the expected values.
+    #[test]
+    fn converts_data_model() -> Result<(), DatabaseError> {
+        let mut quarters = HashMap::new();
+        quarters.insert(42, String::from("Starbug"));
+
+        let crew = Crew {
+            name: String::from("Rimmer"),
+            rank: Rank::Hologram { light_bee: true },
+            bunk: Some((2, 'b')),
+            quarters,
+        };
+        let value = to_value(&crew)?;
+
+        assert_eq!(value.get("name"), Some(&Value::from("Rimmer")));
+        assert_eq!(
+            value
+                .get("rank")
+                .and_then(|rank| rank.get("Hologram"))
+                .and_then(|rank| rank.get("light_bee")),
+            Some(&Value::Bool(true))
+        );
+        assert_eq!(
+            value.get("bunk"),
+            Some(&Value::Seq(vec![Value::Int(2), Value::from("b")]))
+        );
+        assert_eq!(
+            value.get("quarters").and_then(|q| q.get("42")),
+            Some(&Value::from("Starbug"))
+        );
+
+        assert_eq!(to_value(&Rank::Captain)?, Value::from("Captain"));
+        assert_eq!(
+            to_value(&Rank::Technician(4))?.get("Technician"),
+            Some(&Value::Int(4))
+        );
+        assert_eq!(to_value(&None::<i32>)?, Value::Null);
+
+        let mut bad_keys = HashMap::new();
+        bad_keys.insert(vec![1], 1);
+        assert!(matches!(
+            to_value(&bad_keys),
+            Err(DatabaseError::EncodeFailed { .. })
+        ));
+
+        Ok(())
+    }
+
+    /// Tests that paths reach into nested maps and sequences.
+    #[test]
+    fn field_paths() -> Result<(), DatabaseError> {
+        let mut quarters = HashMap::new();
+        quarters.insert(7, String::from("Officer's"));
+
+        let crew = vec![Crew {
+            name: String::from("Lister"),
+            rank: Rank::Technician(3),
+            bunk: Some((1, 't')),
+            quarters,
+        }];
+        let value = to_value(&crew)?;
+
+        assert_eq!(value.get_path("0.name"), Some(&Value::from("Lister")));
+        assert_eq!(value.get_path("0.rank.Technician"), Some(&Value::Int(3)));
+        assert_eq!(value.get_path("0.bunk.1"), Some(&Value::from("t")));
+        assert_eq!(
+            value.get_path("0.quarters.7"),
+            Some(&Value::from("Officer's"))
+        );
+        assert_eq!(value.get_path(""), Some(&value));
+
+        for missing in ["1.name", "0.age", "0.name.first", "0.bunk.x", "0..name"] {
+            assert_eq!(value.get_path(missing), None, "{}", missing);
+        }
+
+        Ok(())
+    }
+
+    /// Tests that integers and floats compare with each other but values of
+    /// different kinds don't.
+    #[test]
+    fn compares_values() {
+        assert_eq!(
+            Value::Int(2).compare(&Value::Float(2.5)),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            Value::from("Cat").compare(&Value::from("Camille")),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(Value::Int(1).compare(&Value::from("1")), None);
+        assert_eq!(Value::Null.compare(&Value::Null), Some(Ordering::Equal));
+    }
+}
@@ -0,0 +1,157 @@
+//! Integrity checking of dumped databases, see [crate::Database::verify].
+
+use crate::error::DatabaseError;
+use crate::header::{Header, Kind, HEADER_LEN, MAGIC};
+use crate::{checksum, decode_body};
+use serde::de::DeserializeOwned;
+use std::io::Read;
+use std::path::Path;
+
+/// Outcome of a single check made whilst verifying a dump.
+#[derive(Debug)]
+pub enum Check<V> {
+    /// The check passed, containing what was found.
+    Passed(V),
+
+    /// The check failed with the given error.
+    Failed(DatabaseError),
+
+    /// The check was not made, either because an earlier check it depends upon
+    /// failed or because it doesn't apply to the file.
+    Skipped,
+}
+
+impl<V> Check<V> {
+    /// Returns `true` if the check passed.
+    pub fn is_passed(&self) -> bool {
+        matches!(self, Check::Passed(_))
+    }
+
+    /// Returns `true` if the check failed.
+    pub fn is_failed(&self) -> bool {
+        matches!(self, Check::Failed(_))
+    }
+}
+
+impl<V> From<Result<V, DatabaseError>> for Check<V> {
+    fn from(result: Result<V, DatabaseError>) -> Self {
+        match result {
+            Ok(x) => Check::Passed(x),
+            Err(e) => Check::Failed(e),
+        }
+    }
+}
+
+/// Report generated by [crate::Database::verify] detailing the state of a dump.
+#[derive(Debug)]
+pub struct VerifyReport {
+    /// Status of the [Header], which fails if it was dumped by a keyed
+    /// database. Files without a header fail with [DatabaseError::NotTinyDb],
+    /// including those dumped before headers were added.
+    pub header: Check<Header>,
+
+    /// Status of the checksum trailer, containing the checksum if it matched.
+    /// This is skipped if the header is invalid or the file has no checksum.
+    pub checksum: Check<u32>,
+
+    /// Status of decoding the database, containing the number of items found.
+    /// This is skipped if the header is invalid or the file is encrypted.
+    pub items: Check<usize>,
+}
+
+impl VerifyReport {
+    /// Returns `true` if no checks failed.
+    pub fn is_ok(&self) -> bool {
+        !self.header.is_failed() && !self.checksum.is_failed() && !self.items.is_failed()
+    }
+
+    /// Creates a report where the header failed and nothing else was checked.
+    fn header_failed(error: DatabaseError) -> Self {
+        VerifyReport {
+            header: Check::Failed(error),
+            checksum: Check::Skipped,
+            items: Check::Skipped,
+        }
+    }
+}
+
+/// Verifies a whole dump of `len` bytes read from `path` through `reader`, as
+/// it is read.
+///
+/// # Errors
+///
+/// Will only return an error if the start of the dump can't be read.
+pub(crate) fn verify_reader<T: DeserializeOwned, R: Read>(
+    path: &Path,
+    mut reader: R,
+    len: u64,
+) -> Result<VerifyReport, DatabaseError> {
+    let mut start = Vec::with_capacity(HEADER_LEN);
+    (&mut reader)
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut start)?;
+
+    if !start.starts_with(&MAGIC) {
+        return Ok(VerifyReport::header_failed(DatabaseError::NotTinyDb));
+    }
+
+    let header = match Header::read(&mut &start[..]) {
+        Ok(header) => header,
+        Err(e) => return Ok(VerifyReport::header_failed(e)),
+    };
+    if let Err(e) = header.check_kind(Kind::Plain) {
+        return Ok(VerifyReport::header_failed(e));
+    }
+    let body_len = len.saturating_sub(HEADER_LEN as u64);
+
+    let (checksum, items) = if header.has_checksum() {
+        let mut checked = checksum::ChecksumReader::new(reader, &header.to_bytes());
+        let items = count_items::<T>(
+            path,
+            &header,
+            &mut checked,
+            body_len.saturating_sub(checksum::CHECKSUM_LEN as u64),
+        );
+
+        (checked.finish().into(), items)
+    } else {
+        (
+            Check::Skipped,
+            count_items::<T>(path, &header, &mut reader, body_len),
+        )
+    };
+
+    Ok(VerifyReport {
+        header: Check::Passed(header),
+        checksum,
+        items,
+    })
+}
+
+/// Counts the items in the `body` of a dump following its `header`, which is
+/// `len` bytes long, skipping them if it is encrypted.
+fn count_items<T: DeserializeOwned>(
+    path: &Path,
+    header: &Header,
+    body: &mut dyn Read,
+    len: u64,
+) -> Check<usize> {
+    if header.is_encrypted() {
+        return Check::Skipped;
+    }
+
+    let mut count = 0;
+
+    decode_body(
+        header,
+        body,
+        len,
+        &None,
+        |format, payload, offset, limit, _| {
+            format.decode_items(payload, offset, limit, |_: T| count += 1)
+        },
+    )
+    .map(|()| count)
+    .map_err(|e| e.with_path(path))
+    .into()
+}
@@ -0,0 +1,620 @@
+//! Append-only write-ahead log which lets mutations survive crashes between
+//! dumps, see [crate::Database::enable_wal].
+//!
+//! Each mutation is appended to a log file next to the database as a record
+//! made up of its little-endian `u32` length, the `u32` CRC32 of its contents
+//! and then the bincode-encoded [Record] itself. Loading a database replays
+//! every intact record on top of the last dump, stopping at the first torn or
+//! corrupted record which a crash mid-append may leave behind. A record which
+//! passes its checksum but can't be decoded wasn't torn, so it is an error.
+//!
+//! When the database has an encryption key, the contents of every record are
+//! sealed with it. Changing the key folds the log into a dump first, so every
+//! record is always sealed with the same key as the dump before it.
+
+use crate::error::DatabaseError;
+use crate::{open_sealed, seal_with, CipherKey};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Length of the length and checksum prefix of each record.
+const PREFIX_LEN: usize = 8;
+
+/// How often the log is synced to disk after appending a record, trading
+/// durability for latency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Sync after every record, so no acknowledged mutation can be lost.
+    Always,
+
+    /// Sync at most this long after a record is appended, so at most this
+    /// window of mutations can be lost. Syncs are made by a background thread,
+    /// batching every record appended in the meantime, and failures are
+    /// returned by the next append without appending its record.
+    Interval(Duration),
+
+    /// Never explicitly sync, leaving it up to the operating system.
+    Never,
+}
+
+/// A single logged mutation of a [crate::Database].
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum Record<T> {
+    /// An item was added.
+    Add(T),
+
+    /// An item was removed.
+    Remove(T),
+
+    /// The first item was replaced with the second.
+    Update(T, T),
+}
+
+/// An open log which records can be appended to.
+#[derive(Debug)]
+pub(crate) struct Wal {
+    file: File,
+
+    /// Length of the intact records in the log, which a failed append is cut
+    /// back to
+    len: u64,
+
+    /// If cutting back a failed append failed too, so the next append must
+    /// retry it first
+    torn: bool,
+
+    policy: SyncPolicy,
+    syncer: Option<Syncer>,
+    key: CipherKey,
+}
+
+/// State shared between a [Wal] and its [Syncer] thread.
+#[derive(Debug, Default)]
+struct SyncState {
+    /// If records have been appended since the last sync
+    dirty: bool,
+
+    /// If the log is being dropped and the thread should exit
+    stopped: bool,
+
+    /// Error from the last failed sync, returned by the next append before it
+    /// writes anything
+    error: Option<std::io::Error>,
+}
+
+/// Background thread syncing a log for [SyncPolicy::Interval].
+#[derive(Debug)]
+struct Syncer {
+    state: Arc<(Mutex<SyncState>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Syncer {
+    /// Starts a thread which syncs `file` no later than `every` after it is
+    /// marked as dirty.
+    fn spawn(file: File, every: Duration) -> Self {
+        let state = Arc::new((Mutex::new(SyncState::default()), Condvar::new()));
+        let shared = state.clone();
+
+        let thread = std::thread::spawn(move || {
+            let (lock, wake) = &*shared;
+            let mut state = lock.lock().unwrap();
+
+            loop {
+                state = wake.wait_while(state, |s| !s.dirty && !s.stopped).unwrap();
+                state = wake
+                    .wait_timeout_while(state, every, |s| !s.stopped)
+                    .unwrap()
+                    .0;
+
+                // dropping the log syncs it one last time
+                if state.stopped {
+                    return;
+                }
+
+                state.dirty = false;
+                drop(state);
+
+                let synced = file.sync_data();
+
+                state = lock.lock().unwrap();
+                if let Err(e) = synced {
+                    state.error = Some(e);
+                }
+            }
+        });
+
+        Syncer {
+            state,
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns the error of the last failed sync if there was one.
+    fn take_error(&self) -> Result<(), DatabaseError> {
+        match self.state.0.lock().unwrap().error.take() {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Marks the log as having unsynced records.
+    fn mark_dirty(&self) {
+        let (lock, wake) = &*self.state;
+        lock.lock().unwrap().dirty = true;
+        wake.notify_one();
+    }
+
+    /// Returns `true` if records have been appended since the last sync.
+    #[cfg(test)]
+    fn is_dirty(&self) -> bool {
+        self.state.0.lock().unwrap().dirty
+    }
+}
+
+impl Drop for Syncer {
+    fn drop(&mut self) {
+        let (lock, wake) = &*self.state;
+        lock.lock().unwrap().stopped = true;
+        wake.notify_one();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Wal {
+    /// Opens or creates the log at `path` for appending, removing any torn
+    /// record left at the end of it. Records are sealed with `key` if given.
+    pub(crate) fn open(
+        path: &Path,
+        policy: SyncPolicy,
+        key: CipherKey,
+    ) -> Result<Self, DatabaseError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let mut log = vec![];
+        file.read_to_end(&mut log)?;
+
+        let intact = intact_len(&log);
+        if intact != log.len() {
+            file.set_len(intact as u64)?;
+            file.sync_data()?;
+        }
+        file.seek(SeekFrom::Start(intact as u64))?;
+
+        let syncer = match policy {
+            SyncPolicy::Interval(every) => Some(Syncer::spawn(file.try_clone()?, every)),
+            _ => None,
+        };
+
+        Ok(Wal {
+            file,
+            len: intact as u64,
+            torn: false,
+            policy,
+            syncer,
+            key,
+        })
+    }
+
+    /// Appends a record to the log, syncing it according to the policy. If
+    /// this fails, the record is removed again so that the log never holds a
+    /// mutation which the caller rejects, nor a torn record before later ones.
+    pub(crate) fn append<R: Serialize>(&mut self, record: &R) -> Result<(), DatabaseError> {
+        let encoded = bincode::serialize(record)
+            .map_err(|source| DatabaseError::EncodeFailed { path: None, source })?;
+        let framed = frame(&seal_with(&self.key, encoded, &[])?);
+
+        if let Some(syncer) = &self.syncer {
+            syncer.take_error()?;
+        }
+        if self.torn {
+            self.file.truncate_to(self.len)?;
+            self.torn = false;
+        }
+
+        let policy = self.policy;
+        let appended = append_framed(&mut self.file, self.len, &framed, |file| match policy {
+            SyncPolicy::Always => file.sync_data(),
+            _ => Ok(()),
+        });
+        if let Err(e) = appended {
+            self.torn = true;
+            return Err(e.into());
+        }
+        self.len += framed.len() as u64;
+
+        if let Some(syncer) = &self.syncer {
+            syncer.mark_dirty();
+        }
+
+        Ok(())
+    }
+
+    /// Returns the policy the log is synced with.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn policy(&self) -> SyncPolicy {
+        self.policy
+    }
+
+    /// Syncs all appended records to disk.
+    pub(crate) fn sync(&mut self) -> Result<(), DatabaseError> {
+        Ok(self.file.sync_data()?)
+    }
+
+    /// Empties the log once its records have been folded into a dump.
+    pub(crate) fn truncate(&mut self) -> Result<(), DatabaseError> {
+        self.file.truncate_to(0)?;
+        self.len = 0;
+        self.torn = false;
+
+        self.sync()
+    }
+}
+
+impl Drop for Wal {
+    fn drop(&mut self) {
+        self.syncer.take();
+
+        if self.policy != SyncPolicy::Never {
+            let _ = self.file.sync_data();
+        }
+    }
+}
+
+/// Generates the path of the log for a database dumped to `path`, which is
+/// the same path with `.wal` appended.
+pub(crate) fn wal_path(path: &Path) -> PathBuf {
+    let mut wal = path.as_os_str().to_owned();
+    wal.push(".wal");
+
+    PathBuf::from(wal)
+}
+
+/// Reads every intact record from the log at `path`, returning none if it
+/// doesn't exist. Records are unsealed with `key` if given.
+///
+/// # Errors
+///
+/// Will return [DatabaseError::KeyRequired] or [DatabaseError::DecryptionFailed]
+/// if records are sealed and can't be unsealed with `key`, or
+/// [DatabaseError::DecodeFailed] if an intact record can't be decoded.
+pub(crate) fn read_records<R: DeserializeOwned>(
+    path: &Path,
+    key: &CipherKey,
+) -> Result<Vec<R>, DatabaseError> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let log = std::fs::read(path)?;
+    let mut records = vec![];
+    let mut result = Ok(());
+
+    for_each_intact(&log, |offset, contents| {
+        let encoded = match open_sealed(key.is_some(), contents, &[], key) {
+            Ok(x) => x,
+            Err(e) => {
+                result = Err(e);
+                return false;
+            }
+        };
+
+        // a record passing its checksum but failing to decode wasn't torn
+        // by a crash, so it was written for another item type or key
+        match bincode::deserialize(&encoded) {
+            Ok(record) => {
+                records.push(record);
+                true
+            }
+            Err(source) => {
+                result = Err(DatabaseError::DecodeFailed {
+                    path: Some(path.to_path_buf()),
+                    offset: Some(offset as u64),
+                    source,
+                });
+                false
+            }
+        }
+    });
+
+    result.map(|_| records)
+}
+
+/// A log file which can be cut back to a shorter length, removing records
+/// appended after it.
+trait LogFile: Write {
+    /// Cuts the file back to `len` bytes, appending from there on.
+    fn truncate_to(&mut self, len: u64) -> std::io::Result<()>;
+
+    /// Syncs the contents of the file to disk.
+    fn sync_data(&mut self) -> std::io::Result<()>;
+}
+
+impl LogFile for File {
+    fn truncate_to(&mut self, len: u64) -> std::io::Result<()> {
+        self.set_len(len)?;
+        self.seek(SeekFrom::Start(len))?;
+
+        Ok(())
+    }
+
+    fn sync_data(&mut self) -> std::io::Result<()> {
+        File::sync_data(self)
+    }
+}
+
+/// Appends the `framed` record to `file`, which is `len` bytes long, then
+/// calls `commit` on it. If either fails, `file` is cut back to `len`.
+fn append_framed<F: LogFile>(
+    file: &mut F,
+    len: u64,
+    framed: &[u8],
+    commit: impl FnOnce(&mut F) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let appended = file.write_all(framed).and_then(|_| commit(file));
+
+    if appended.is_err() {
+        file.truncate_to(len)?;
+    }
+
+    appended
+}
+
+/// Frames the contents of a record with its length and checksum.
+fn frame(contents: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(PREFIX_LEN + contents.len());
+
+    framed.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+    framed.extend_from_slice(&crc32fast::hash(contents).to_le_bytes());
+    framed.extend_from_slice(contents);
+
+    framed
+}
+
+/// Returns the length of the intact prefix of `log`.
+fn intact_len(log: &[u8]) -> usize {
+    let mut len = 0;
+
+    for_each_intact(log, |_, encoded| {
+        len += PREFIX_LEN + encoded.len();
+        true
+    });
+
+    len
+}
+
+/// Calls `f` with the offset and contents of each record in `log` which passes
+/// its checksum, stopping at the first which doesn't or when `f` returns
+/// `false`.
+fn for_each_intact(log: &[u8], mut f: impl FnMut(usize, &[u8]) -> bool) {
+    let mut offset = 0;
+
+    while log.len() - offset >= PREFIX_LEN {
+        let record = &log[offset..];
+        let len = u32::from_le_bytes([record[0], record[1], record[2], record[3]]) as usize;
+        let checksum = u32::from_le_bytes([record[4], record[5], record[6], record[7]]);
+
+        let encoded = match record.get(PREFIX_LEN..PREFIX_LEN + len) {
+            Some(x) if crc32fast::hash(x) == checksum => x,
+            _ => return,
+        };
+
+        if !f(offset + PREFIX_LEN, encoded) {
+            return;
+        }
+
+        offset += PREFIX_LEN + len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a fresh log path inside of the system's temp directory.
+    fn test_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("tinydb-wal-{}.tinydb.wal", name));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Tests that appended records are read back in order.
+    #[test]
+    fn append_and_read() -> Result<(), DatabaseError> {
+        let path = test_path("append");
+        let mut wal = Wal::open(&path, SyncPolicy::Always, None)?;
+
+        wal.append(&Record::Add(1))?;
+        wal.append(&Record::Update(1, 2))?;
+        wal.append(&Record::Remove(2))?;
+
+        let records = read_records::<Record<i32>>(&path, &None)?;
+        assert!(matches!(
+            records[..],
+            [Record::Add(1), Record::Update(1, 2), Record::Remove(2)]
+        ));
+
+        wal.truncate()?;
+        assert!(read_records::<Record<i32>>(&path, &None)?.is_empty());
+
+        Ok(())
+    }
+
+    /// Tests that a torn record at the end of the log is ignored when reading
+    /// and removed when reopening the log for appending.
+    #[test]
+    fn torn_tail() -> Result<(), DatabaseError> {
+        let path = test_path("torn");
+        let mut wal = Wal::open(&path, SyncPolicy::Never, None)?;
+        wal.append(&Record::Add(1))?;
+        drop(wal);
+
+        let intact = std::fs::read(&path)?;
+        let mut torn = intact.clone();
+        torn.extend_from_slice(&[9, 0, 0, 0, 1, 2]);
+        std::fs::write(&path, &torn)?;
+
+        assert_eq!(read_records::<Record<i32>>(&path, &None)?.len(), 1);
+
+        let mut wal = Wal::open(&path, SyncPolicy::Never, None)?;
+        assert_eq!(std::fs::read(&path)?, intact);
+
+        wal.append(&Record::Add(2))?;
+        assert_eq!(read_records::<Record<i32>>(&path, &None)?.len(), 2);
+
+        Ok(())
+    }
+
+    /// Tests that an intact record which can't be decoded is an error rather
+    /// than being mistaken for a torn tail.
+    #[test]
+    fn undecodable_record() -> Result<(), DatabaseError> {
+        let path = test_path("undecodable");
+        let mut wal = Wal::open(&path, SyncPolicy::Never, None)?;
+        wal.append(&Record::Add(String::from("Kill Crazy")))?;
+        wal.append(&Record::Add(true))?;
+
+        match read_records::<Record<String>>(&path, &None) {
+            Err(DatabaseError::DecodeFailed {
+                path: p, offset, ..
+            }) => {
+                assert_eq!(p, Some(path.clone()));
+                assert_eq!(offset, Some(2 * PREFIX_LEN as u64 + 4 + 8 + 10));
+            }
+            _ => panic!("undecodable record was skipped"),
+        }
+
+        Ok(())
+    }
+
+    /// A log held in memory which fails writes once it has no room left,
+    /// after writing as much as fits.
+    struct FailingLog {
+        log: Vec<u8>,
+        room: usize,
+    }
+
+    impl Write for FailingLog {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.room == 0 {
+                return Err(std::io::Error::other("disk full"));
+            }
+
+            let written = buf.len().min(self.room);
+            self.log.extend_from_slice(&buf[..written]);
+            self.room -= written;
+
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl LogFile for FailingLog {
+        fn truncate_to(&mut self, len: u64) -> std::io::Result<()> {
+            self.log.truncate(len as usize);
+            Ok(())
+        }
+
+        fn sync_data(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Tests that a record which fails part-way through being written, or
+    /// fails to be committed, is removed so later records are still replayed.
+    #[test]
+    fn failed_append_rolled_back() {
+        let first = frame(b"Lister");
+        let second = frame(b"Rimmer");
+        let mut file = FailingLog {
+            log: vec![],
+            room: first.len() + 4,
+        };
+
+        append_framed(&mut file, 0, &first, |_| Ok(())).unwrap();
+        assert!(append_framed(&mut file, first.len() as u64, &second, |_| Ok(())).is_err());
+        assert_eq!(file.log, first);
+
+        file.room = usize::MAX;
+        assert!(append_framed(&mut file, first.len() as u64, &second, |_| {
+            Err(std::io::Error::other("sync failed"))
+        })
+        .is_err());
+        assert_eq!(file.log, first);
+
+        append_framed(&mut file, first.len() as u64, &frame(b"Cat"), |_| Ok(())).unwrap();
+
+        let mut replayed = vec![];
+        for_each_intact(&file.log, |_, contents| {
+            replayed.push(contents.to_vec());
+            true
+        });
+        assert_eq!(replayed, [b"Lister".to_vec(), b"Cat".to_vec()]);
+    }
+
+    /// Tests that [SyncPolicy::Interval] syncs the last record appended even
+    /// if nothing is appended after it.
+    #[test]
+    fn interval_idle_tail() -> Result<(), DatabaseError> {
+        let path = test_path("interval");
+        let every = Duration::from_millis(20);
+        let mut wal = Wal::open(&path, SyncPolicy::Interval(every), None)?;
+
+        wal.append(&Record::Add(1))?;
+        wal.append(&Record::Add(2))?;
+        let syncer = wal.syncer.as_ref().unwrap();
+        assert!(syncer.is_dirty());
+
+        let started = std::time::Instant::now();
+        while syncer.is_dirty() {
+            assert!(started.elapsed() < every * 50, "tail was never synced");
+            std::thread::sleep(every / 4);
+        }
+        assert!(started.elapsed() >= every / 2);
+
+        drop(wal);
+        assert_eq!(read_records::<Record<i32>>(&path, &None)?.len(), 2);
+
+        Ok(())
+    }
+
+    /// Tests that a failed background sync is returned by the next append
+    /// before its record is written.
+    #[test]
+    fn interval_sync_error() -> Result<(), DatabaseError> {
+        let path = test_path("interval-error");
+        let mut wal = Wal::open(&path, SyncPolicy::Interval(Duration::from_secs(60)), None)?;
+        wal.append(&Record::Add(1))?;
+
+        let syncer = wal.syncer.as_ref().unwrap();
+        syncer.state.0.lock().unwrap().error = Some(std::io::Error::other("sync failed"));
+
+        assert!(matches!(
+            wal.append(&Record::Add(2)),
+            Err(DatabaseError::IOError(_))
+        ));
+        wal.append(&Record::Add(3))?;
+        drop(wal);
+
+        let records = read_records::<Record<i32>>(&path, &None)?;
+        assert!(matches!(records[..], [Record::Add(1), Record::Add(3)]));
+
+        Ok(())
+    }
+}